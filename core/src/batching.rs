@@ -0,0 +1,89 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::{Async, Future};
+use std::fmt;
+use std::time::Duration;
+use timer::{Delay, Timer};
+
+/// Collects the payloads of calls made within a time window into a single batch payload. See
+/// [`Client::batch_calls`](struct.Client.html#method.batch_calls).
+pub struct CallBatching {
+    timer: Box<dyn Timer + Send>,
+    window: Duration,
+    max_calls: usize,
+    payloads: Vec<String>,
+    window_end: Option<Delay>,
+    due: bool,
+}
+
+impl CallBatching {
+    pub fn new(timer: Box<dyn Timer + Send>, window: Duration, max_calls: usize) -> Self {
+        CallBatching {
+            timer,
+            window,
+            max_calls: max_calls.max(1),
+            payloads: Vec::new(),
+            window_end: None,
+            due: false,
+        }
+    }
+
+    /// Adds the payload of a call to the batch, starting a new window if this is the first call.
+    pub fn push(&mut self, payload: String) {
+        if self.window_end.is_none() && !self.due {
+            self.window_end = Some(self.timer.delay(self.window));
+        }
+        self.payloads.push(payload);
+        if self.payloads.len() >= self.max_calls {
+            self.due = true;
+        }
+    }
+
+    /// Returns true if the batch should be sent, because it is full or its window has passed.
+    pub fn poll_due(&mut self) -> bool {
+        let window_passed = match self.window_end {
+            Some(ref mut window_end) => match window_end.poll() {
+                Ok(Async::NotReady) => false,
+                _ => true,
+            },
+            None => false,
+        };
+        if window_passed {
+            self.window_end = None;
+            self.due = true;
+        }
+        self.due
+    }
+
+    /// Takes the batch as a single payload. A batch of one call is sent as a plain call.
+    pub fn take_payload(&mut self) -> Option<String> {
+        self.window_end = None;
+        self.due = false;
+        match self.payloads.len() {
+            0 => None,
+            1 => self.payloads.pop(),
+            _ => {
+                let payload = format!("[{}]", self.payloads.join(","));
+                self.payloads.clear();
+                Some(payload)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CallBatching {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallBatching")
+            .field("window", &self.window)
+            .field("max_calls", &self.max_calls)
+            .field("payloads", &self.payloads.len())
+            .field("due", &self.due)
+            .finish()
+    }
+}
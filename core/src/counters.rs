@@ -0,0 +1,59 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static PAYLOADS_SENT: AtomicUsize = AtomicUsize::new(0);
+static BYTES_SENT: AtomicUsize = AtomicUsize::new(0);
+static PAYLOADS_RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static BYTES_RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+/// The values of the counters kept by all clients in the process, from
+/// [`snapshot`](fn.snapshot.html). Meant for benchmarks, to tell how much work the client did
+/// besides the time it took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// The number of payloads handed to the sinks of transports.
+    pub payloads_sent: usize,
+    /// The total size in bytes of the payloads handed to the sinks of transports.
+    pub bytes_sent: usize,
+    /// The number of payloads received from the streams of transports.
+    pub payloads_received: usize,
+    /// The total size in bytes of the payloads received from the streams of transports.
+    pub bytes_received: usize,
+}
+
+/// Returns the current value of all counters.
+pub fn snapshot() -> Counters {
+    Counters {
+        payloads_sent: PAYLOADS_SENT.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        payloads_received: PAYLOADS_RECEIVED.load(Ordering::Relaxed),
+        bytes_received: BYTES_RECEIVED.load(Ordering::Relaxed),
+    }
+}
+
+/// Sets all counters back to zero.
+pub fn reset() {
+    PAYLOADS_SENT.store(0, Ordering::Relaxed);
+    BYTES_SENT.store(0, Ordering::Relaxed);
+    PAYLOADS_RECEIVED.store(0, Ordering::Relaxed);
+    BYTES_RECEIVED.store(0, Ordering::Relaxed);
+}
+
+#[doc(hidden)]
+pub fn payload_sent(len: usize) {
+    PAYLOADS_SENT.fetch_add(1, Ordering::Relaxed);
+    BYTES_SENT.fetch_add(len, Ordering::Relaxed);
+}
+
+#[doc(hidden)]
+pub fn payload_received(len: usize) {
+    PAYLOADS_RECEIVED.fetch_add(1, Ordering::Relaxed);
+    BYTES_RECEIVED.fetch_add(len, Ordering::Relaxed);
+}
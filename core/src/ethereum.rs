@@ -0,0 +1,172 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A client for the standard `web3_`, `net_` and `eth_` methods of Ethereum nodes.
+//!
+//! Addresses, hashes and data are passed as [`Bytes`](../types/struct.Bytes.html). Block
+//! parameters are either a block number as a hex quantity, like `"0x1b4"`, or one of the tags
+//! `"earliest"`, `"latest"` and `"pending"`. Methods returning structured objects, like blocks
+//! and receipts, let the caller pick the type to deserialize them into, for example
+//! `serde_json::Value` or a struct with just the fields the application needs.
+//!
+//! Filter ids are kept as the strings the node returns. Nodes pick them freely, and many return
+//! ids of 128 bits or more, which don't fit in a `Quantity`.
+
+#![allow(non_snake_case)]
+
+use serde_json::Value as JsonValue;
+use types::{Bytes, Quantity, U256};
+
+jsonrpc_client!(
+    /// A client for Ethereum nodes, like Geth and Parity.
+    pub struct EthereumClient {
+        /// Returns the name and version of the node software.
+        pub fn web3_clientVersion(&mut self) -> Future<String>;
+
+        /// Returns the Keccak-256 hash of the given data.
+        pub fn web3_sha3(&mut self, data: Bytes) -> Future<Bytes>;
+
+        /// Returns the id of the network the node is connected to.
+        pub fn net_version(&mut self) -> Future<String>;
+
+        /// Returns true if the node is listening for network connections.
+        pub fn net_listening(&mut self) -> Future<bool>;
+
+        /// Returns the number of peers connected to the node.
+        pub fn net_peerCount(&mut self) -> Future<Quantity>;
+
+        /// Returns the Ethereum protocol version of the node.
+        pub fn eth_protocolVersion(&mut self) -> Future<String>;
+
+        /// Returns the chain id used for signing replay protected transactions.
+        pub fn eth_chainId(&mut self) -> Future<Quantity>;
+
+        /// Returns the sync status of the node, `false` if it is not syncing.
+        pub fn eth_syncing<T>(&mut self) -> Future<T>;
+
+        /// Returns the address mining rewards go to.
+        pub fn eth_coinbase(&mut self) -> Future<Bytes>;
+
+        /// Returns true if the node is mining.
+        pub fn eth_mining(&mut self) -> Future<bool>;
+
+        /// Returns the number of hashes per second the node is mining with.
+        pub fn eth_hashrate(&mut self) -> Future<Quantity>;
+
+        /// Returns the current gas price in wei.
+        pub fn eth_gasPrice(&mut self) -> Future<U256>;
+
+        /// Returns the addresses of the accounts owned by the node.
+        pub fn eth_accounts(&mut self) -> Future<Vec<Bytes>>;
+
+        /// Returns the number of the most recent block.
+        pub fn eth_blockNumber(&mut self) -> Future<Quantity>;
+
+        /// Returns the balance in wei of an address at the given block.
+        pub fn eth_getBalance(&mut self, address: Bytes, block: &str) -> Future<U256>;
+
+        /// Returns the value of a storage position of an address at the given block.
+        pub fn eth_getStorageAt(
+            &mut self,
+            address: Bytes,
+            position: U256,
+            block: &str
+        ) -> Future<Bytes>;
+
+        /// Returns the number of transactions sent from an address at the given block.
+        pub fn eth_getTransactionCount(&mut self, address: Bytes, block: &str) -> Future<Quantity>;
+
+        /// Returns the number of transactions in the block with the given hash.
+        pub fn eth_getBlockTransactionCountByHash(&mut self, hash: Bytes) -> Future<Quantity>;
+
+        /// Returns the number of transactions in the given block.
+        pub fn eth_getBlockTransactionCountByNumber(&mut self, block: &str) -> Future<Quantity>;
+
+        /// Returns the code of the contract at an address at the given block.
+        pub fn eth_getCode(&mut self, address: Bytes, block: &str) -> Future<Bytes>;
+
+        /// Signs data with the key of an account owned by the node.
+        pub fn eth_sign(&mut self, address: Bytes, data: Bytes) -> Future<Bytes>;
+
+        /// Signs and sends a transaction from an account owned by the node, returning the
+        /// transaction hash.
+        pub fn eth_sendTransaction(&mut self, transaction: JsonValue) -> Future<Bytes>;
+
+        /// Sends a signed transaction, returning the transaction hash.
+        pub fn eth_sendRawTransaction(&mut self, data: Bytes) -> Future<Bytes>;
+
+        /// Executes a call without creating a transaction, returning what the call returned.
+        pub fn eth_call(&mut self, call: JsonValue, block: &str) -> Future<Bytes>;
+
+        /// Returns an estimate of the gas a transaction would use.
+        pub fn eth_estimateGas(&mut self, transaction: JsonValue) -> Future<Quantity>;
+
+        /// Returns the block with the given hash, with full transactions or only their hashes.
+        pub fn eth_getBlockByHash<T>(&mut self, hash: Bytes, full_transactions: bool) -> Future<T>;
+
+        /// Returns the given block, with full transactions or only their hashes.
+        pub fn eth_getBlockByNumber<T>(
+            &mut self,
+            block: &str,
+            full_transactions: bool
+        ) -> Future<T>;
+
+        /// Returns the transaction with the given hash.
+        pub fn eth_getTransactionByHash<T>(&mut self, hash: Bytes) -> Future<T>;
+
+        /// Returns the receipt of the transaction with the given hash, or null if it is not mined.
+        pub fn eth_getTransactionReceipt<T>(&mut self, hash: Bytes) -> Future<T>;
+
+        /// Returns the logs matching the given filter object.
+        pub fn eth_getLogs<T>(&mut self, filter: JsonValue) -> Future<T>;
+
+        /// Creates a filter for logs, returning its id.
+        pub fn eth_newFilter(&mut self, filter: JsonValue) -> Future<String>;
+
+        /// Creates a filter for new blocks, returning its id.
+        pub fn eth_newBlockFilter(&mut self) -> Future<String>;
+
+        /// Returns what happened since the filter with the given id was last polled.
+        pub fn eth_getFilterChanges<T>(&mut self, filter_id: &str) -> Future<T>;
+
+        /// Removes the filter with the given id, returning true if it existed.
+        pub fn eth_uninstallFilter(&mut self, filter_id: &str) -> Future<bool>;
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+    use std::thread;
+    use test_transport;
+    use Transport;
+
+    #[test]
+    fn filter_ids() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+        let mut rpc = EthereumClient::new(handle);
+
+        let filter_id = "0x1d4f0a8fd2cbbe6e3f1a9e4b6d2c7e5a";
+        let call = rpc.eth_newBlockFilter();
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "eth_newBlockFilter");
+        server.respond(&request, json!(filter_id));
+        assert_eq!(call.join().unwrap().unwrap(), filter_id);
+
+        let call = rpc.eth_uninstallFilter(filter_id);
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["params"], json!([filter_id]));
+        server.respond(&request, json!(true));
+        assert!(call.join().unwrap().unwrap());
+    }
+}
@@ -14,9 +14,17 @@ jsonrpc_client!(
         /// lots of side effects on the server where it executes.
         pub fn nullary(&mut self) -> Future<()>;
 
+        /// A method whose result is not interesting to the caller. The returned future resolves
+        /// to `()` no matter what the server responds with, as long as it is not an error.
+        pub fn fire(&mut self, target: u64);
+
         /// Send a string to the server and it will presumably echo it back.
         pub fn echo(&mut self, input: &str) -> Future<String>;
 
+        /// A method where the caller picks the type the result is deserialized into, for servers
+        /// returning differently shaped results depending on the parameters.
+        pub fn lookup<T>(&mut self, key: String, verbose: bool) -> Future<T>;
+
         /// Example RPC method named "concat" that takes a `String` and an unsigned integer and
         /// returns a `String`. From the name one could guess it will concatenate the two
         /// arguments. But that of course depends on the server where this call is sent.
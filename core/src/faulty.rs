@@ -0,0 +1,305 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::{Async, Future, Poll, Stream};
+use std::collections::VecDeque;
+use std::time::Duration;
+use timer::{Delay, Timer};
+use {RecoveryHint, Transport};
+
+/// The faults a `FaultyTransport` injects into the messages from the server, each with the
+/// probability, from 0.0 to 1.0, that it happens to a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Faults {
+    delay: f64,
+    delay_duration: Duration,
+    drop: f64,
+    corrupt: f64,
+    reorder: f64,
+}
+
+/// Wraps a transport and injects faults into the messages received from the server: delays,
+/// dropped messages, corrupted JSON and messages delivered out of order. Each fault happens to a
+/// message with a configured probability. No faults are injected by default.
+///
+/// The faults are decided by a pseudo-random generator with a fixed seed, so a test sees the same
+/// faults every run. Set a different seed with [`seed`](#method.seed).
+pub struct FaultyTransport<T: Transport, M: Timer> {
+    inner: T,
+    timer: M,
+    faults: Faults,
+    seed: u64,
+}
+
+impl<T: Transport, M: Timer + Send + 'static> FaultyTransport<T, M> {
+    /// Wraps `inner`, using `timer` for the injected delays.
+    pub fn new(inner: T, timer: M) -> Self {
+        FaultyTransport {
+            inner,
+            timer,
+            faults: Faults {
+                delay: 0.0,
+                delay_duration: Duration::from_secs(0),
+                drop: 0.0,
+                corrupt: 0.0,
+                reorder: 0.0,
+            },
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// Delays messages by `duration` with the given probability. Messages after a delayed
+    /// message wait for it.
+    pub fn delay(mut self, probability: f64, duration: Duration) -> Self {
+        self.faults.delay = probability;
+        self.faults.delay_duration = duration;
+        self
+    }
+
+    /// Drops messages with the given probability.
+    pub fn drop_messages(mut self, probability: f64) -> Self {
+        self.faults.drop = probability;
+        self
+    }
+
+    /// Cuts messages in half, making them invalid JSON, with the given probability.
+    pub fn corrupt_json(mut self, probability: f64) -> Self {
+        self.faults.corrupt = probability;
+        self
+    }
+
+    /// Holds messages back with the given probability, and delivers them after the next message
+    /// from the server, so that responses arrive in a different order than their ids. A held
+    /// message is delivered when the server closes the stream if no other message arrives.
+    pub fn reorder(mut self, probability: f64) -> Self {
+        self.faults.reorder = probability;
+        self
+    }
+
+    /// Seeds the generator deciding which messages are hit by faults.
+    pub fn seed(mut self, seed: u64) -> Self {
+        // xorshift never leaves zero
+        self.seed = if seed == 0 { 1 } else { seed };
+        self
+    }
+}
+
+impl<T: Transport, M: Timer + Send + 'static> Transport for FaultyTransport<T, M> {
+    type Error = T::Error;
+    type Sink = T::Sink;
+    type Stream = FaultyStream<T::Stream, M>;
+
+    fn endpoint(&self) -> Option<String> {
+        self.inner.endpoint()
+    }
+
+    fn recovery_hint(error: &T::Error) -> RecoveryHint {
+        T::recovery_hint(error)
+    }
+
+    fn io_pair(self) -> (Self::Sink, Self::Stream) {
+        let (sink, stream) = self.inner.io_pair();
+        let stream = FaultyStream {
+            inner: stream,
+            timer: self.timer,
+            faults: self.faults,
+            rng: self.seed,
+            held: None,
+            ready: VecDeque::new(),
+            delayed: None,
+            ended: false,
+        };
+        (sink, stream)
+    }
+}
+
+/// The stream of messages from the server of a `FaultyTransport`.
+pub struct FaultyStream<S, M> {
+    inner: S,
+    timer: M,
+    faults: Faults,
+    rng: u64,
+    held: Option<String>,
+    ready: VecDeque<String>,
+    delayed: Option<(Delay, String)>,
+    ended: bool,
+}
+
+impl<S: Stream<Item = String>, M: Timer> FaultyStream<S, M> {
+    /// Returns true with the given probability.
+    fn roll(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let random = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+        (random as f64) / ((1u64 << 53) as f64) < probability
+    }
+
+    /// Applies the drop, corruption and reordering faults to a message from the server.
+    fn receive(&mut self, mut message: String) {
+        let (drop, corrupt, reorder) = (self.faults.drop, self.faults.corrupt, self.faults.reorder);
+        if self.roll(drop) {
+            debug!("Dropping message from the server: {}", message);
+            return;
+        }
+        if self.roll(corrupt) {
+            let mut half = message.len() / 2;
+            while !message.is_char_boundary(half) {
+                half -= 1;
+            }
+            message.truncate(half);
+        }
+        if self.held.is_none() && self.roll(reorder) {
+            self.held = Some(message);
+            return;
+        }
+        self.ready.push_back(message);
+        if let Some(held) = self.held.take() {
+            self.ready.push_back(held);
+        }
+    }
+}
+
+impl<S: Stream<Item = String>, M: Timer> Stream for FaultyStream<S, M> {
+    type Item = String;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<String>, S::Error> {
+        loop {
+            if let Some((mut delay, message)) = self.delayed.take() {
+                if let Ok(Async::NotReady) = delay.poll() {
+                    self.delayed = Some((delay, message));
+                    return Ok(Async::NotReady);
+                }
+                return Ok(Async::Ready(Some(message)));
+            }
+            if let Some(message) = self.ready.pop_front() {
+                let delay = self.faults.delay;
+                if self.roll(delay) {
+                    let delay = self.timer.delay(self.faults.delay_duration);
+                    self.delayed = Some((delay, message));
+                    continue;
+                }
+                return Ok(Async::Ready(Some(message)));
+            }
+            if self.ended {
+                return Ok(Async::Ready(None));
+            }
+            match self.inner.poll()? {
+                Async::Ready(Some(message)) => self.receive(message),
+                Async::Ready(None) => {
+                    self.ended = true;
+                    if let Some(held) = self.held.take() {
+                        self.ready.push_back(held);
+                    }
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use test_transport::{self, TestTransport};
+    use timer::ManualTimer;
+
+    /// Sends `messages` through a `FaultyTransport` configured by `faults`, and returns what
+    /// comes out of it.
+    fn received<F>(messages: &[&str], faults: F) -> Vec<String>
+    where
+        F: FnOnce(
+            FaultyTransport<TestTransport, ManualTimer>,
+        ) -> FaultyTransport<TestTransport, ManualTimer>,
+    {
+        let (transport, server) = test_transport::pair();
+        let transport = faults(FaultyTransport::new(transport, ManualTimer::default()));
+        let (_sink, stream) = transport.io_pair();
+        for message in messages {
+            server.send(message);
+        }
+        drop(server);
+        stream.collect().wait().unwrap()
+    }
+
+    fn stream(seed: u64) -> FaultyStream<<TestTransport as Transport>::Stream, ManualTimer> {
+        let (transport, _server) = test_transport::pair();
+        let transport = FaultyTransport::new(transport, ManualTimer::default()).seed(seed);
+        transport.io_pair().1
+    }
+
+    #[test]
+    fn no_faults_by_default() {
+        assert_eq!(received(&["a", "b", "c"], |t| t), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn drops_messages() {
+        assert!(received(&["a", "b"], |t| t.drop_messages(1.0)).is_empty());
+    }
+
+    #[test]
+    fn corrupts_json() {
+        let messages = received(&[r#"{"id":1}"#, "aé"], |t| t.corrupt_json(1.0));
+        assert_eq!(messages, vec![r#"{"id"#, "a"]);
+    }
+
+    #[test]
+    fn reorders_messages() {
+        let messages = received(&["a", "b", "c", "d", "e"], |t| t.reorder(1.0));
+        // a held message is delivered after the next one, or when the stream ends
+        assert_eq!(messages, vec!["b", "a", "d", "c", "e"]);
+    }
+
+    #[test]
+    fn delays_messages() {
+        let (transport, server) = test_transport::pair();
+        let timer = ManualTimer::default();
+        let transport =
+            FaultyTransport::new(transport, timer.clone()).delay(1.0, Duration::from_secs(5));
+        let (_sink, mut stream) = transport.io_pair();
+        server.send("a");
+        future::lazy(|| {
+            assert_eq!(stream.poll(), Ok(Async::NotReady));
+            timer.advance(Duration::from_secs(4));
+            assert_eq!(stream.poll(), Ok(Async::NotReady));
+            timer.advance(Duration::from_secs(1));
+            assert_eq!(stream.poll(), Ok(Async::Ready(Some("a".to_owned()))));
+            Ok::<_, ()>(())
+        }).wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn faults_happen_with_their_probability() {
+        for &probability in &[0.0, 0.1, 0.5, 0.9, 1.0] {
+            let mut stream = stream(7);
+            let hits = (0..10_000).filter(|_| stream.roll(probability)).count();
+            let expected = probability * 10_000.0;
+            assert!((hits as f64 - expected).abs() < 200.0, "{}: {}", probability, hits);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_faults() {
+        let rolls = |seed| {
+            let mut stream = stream(seed);
+            (0..64).map(|_| stream.roll(0.5)).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(1), rolls(1));
+        assert_ne!(rolls(1), rolls(2));
+        // zero would make xorshift return zero forever
+        assert!(rolls(0).contains(&true));
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversions between the types of this crate and the types of the `jsonrpc-core` server crate.
+//!
+//! This crate already uses the `jsonrpc-core` types on the wire, this module makes the mapping
+//! explicit so projects implementing both the client and the server side can pass errors and
+//! calls between the two without maintaining their own copies of the types.
+
+use super::{Error, ErrorKind, OutgoingMessage, Result};
+use jsonrpc_core;
+use jsonrpc_core::types::{Call, Failure, Id, MethodCall, Notification, Output, Success, Version};
+use serde_json::Value as JsonValue;
+
+pub use jsonrpc_core::types;
+
+impl From<jsonrpc_core::Error> for Error {
+    fn from(error: jsonrpc_core::Error) -> Self {
+        ErrorKind::JsonRpcError(error).into()
+    }
+}
+
+impl Error {
+    /// Returns the JSON-RPC 2.0 error returned by the server, if that is what this error is.
+    pub fn as_rpc_error(&self) -> Option<&jsonrpc_core::Error> {
+        match self.kind() {
+            ErrorKind::JsonRpcError(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Converts this error into a JSON-RPC 2.0 error, for example to forward it from a server
+    /// method that proxies calls to another server. Errors returned by the other server are
+    /// passed on as they are, all other errors become internal errors carrying the error message.
+    pub fn into_rpc_error(self) -> jsonrpc_core::Error {
+        match self {
+            Error(ErrorKind::JsonRpcError(error), _) => error,
+            error => {
+                let mut rpc_error = jsonrpc_core::Error::internal_error();
+                rpc_error.message = error.to_string();
+                rpc_error
+            }
+        }
+    }
+}
+
+/// Converts a JSON-RPC 2.0 response into the result a call resolves to.
+pub fn output_to_result(output: Output) -> Result<JsonValue> {
+    match output {
+        Output::Success(Success { result, .. }) => Ok(result),
+        Output::Failure(Failure { error, .. }) => Err(error.into()),
+    }
+}
+
+/// Converts the result of a call into a JSON-RPC 2.0 response with the given id.
+pub fn result_to_output(id: Id, result: Result<JsonValue>) -> Output {
+    match result {
+        Ok(result) => Output::Success(Success {
+            jsonrpc: Some(Version::V2),
+            result,
+            id,
+        }),
+        Err(error) => Output::Failure(Failure {
+            jsonrpc: Some(Version::V2),
+            error: error.into_rpc_error(),
+            id,
+        }),
+    }
+}
+
+impl OutgoingMessage {
+    /// Returns the `jsonrpc-core` representation of this message. Method calls get the given id,
+    /// it is ignored for notifications. Returns `None` for responses, since they are not calls.
+    pub fn to_call(&self, id: Id) -> Option<Call> {
+        match self {
+            OutgoingMessage::RpcCall(method, params, _)
+            | OutgoingMessage::DetailedRpcCall(method, params, _) => {
+                Some(Call::MethodCall(MethodCall {
+                    jsonrpc: Some(Version::V2),
+                    method: method.clone(),
+                    params: params.clone(),
+                    id,
+                }))
+            }
+            OutgoingMessage::Notification(method, params, _) => {
+                Some(Call::Notification(Notification {
+                    jsonrpc: Some(Version::V2),
+                    method: method.clone(),
+                    params: params.clone(),
+                }))
+            }
+            OutgoingMessage::Response(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::sync::oneshot;
+    use jsonrpc_core::types::{Params, Response};
+
+    fn rpc_error() -> jsonrpc_core::Error {
+        jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(-32000),
+            message: "Not allowed".to_owned(),
+            data: Some(json!({"reason": "read only"})),
+        }
+    }
+
+    #[test]
+    fn rpc_errors() {
+        let error = Error::from(rpc_error());
+        assert_eq!(error.as_rpc_error(), Some(&rpc_error()));
+        assert_eq!(error.into_rpc_error(), rpc_error());
+
+        let error = Error::from(ErrorKind::Shutdown);
+        assert_eq!(error.as_rpc_error(), None);
+        let rpc_error = error.into_rpc_error();
+        assert_eq!(rpc_error.code, jsonrpc_core::ErrorCode::InternalError);
+        assert_eq!(rpc_error.message, Error::from(ErrorKind::Shutdown).to_string());
+        assert_eq!(rpc_error.data, None);
+    }
+
+    #[test]
+    fn outputs() {
+        let id = Id::Num(1);
+        let output = result_to_output(id.clone(), Ok(json!([1, 2])));
+        assert_eq!(
+            output,
+            Output::Success(Success {
+                jsonrpc: Some(Version::V2),
+                result: json!([1, 2]),
+                id: id.clone(),
+            })
+        );
+        assert_eq!(output_to_result(output.clone()).unwrap(), json!([1, 2]));
+        assert_eq!(result_to_output(id.clone(), output_to_result(output.clone())), output);
+
+        let output = result_to_output(id.clone(), Err(rpc_error().into()));
+        assert_eq!(
+            output,
+            Output::Failure(Failure {
+                jsonrpc: Some(Version::V2),
+                error: rpc_error(),
+                id: id.clone(),
+            })
+        );
+        let error = output_to_result(output.clone()).unwrap_err();
+        assert_eq!(error.as_rpc_error(), Some(&rpc_error()));
+        assert_eq!(result_to_output(id.clone(), Err(error)), output);
+
+        match result_to_output(id, Err(ErrorKind::Shutdown.into())) {
+            Output::Failure(failure) => {
+                assert_eq!(failure.error.code, jsonrpc_core::ErrorCode::InternalError)
+            }
+            output => panic!("unexpected output: {:?}", output),
+        }
+    }
+
+    #[test]
+    fn calls() {
+        let params = Some(Params::Array(vec![json!(1)]));
+        let (tx, _rx) = oneshot::channel();
+        let call = OutgoingMessage::RpcCall("add".to_owned(), params.clone(), tx);
+        assert_eq!(
+            call.to_call(Id::Num(3)),
+            Some(Call::MethodCall(MethodCall {
+                jsonrpc: Some(Version::V2),
+                method: "add".to_owned(),
+                params: params.clone(),
+                id: Id::Num(3),
+            }))
+        );
+
+        let (tx, _rx) = oneshot::channel();
+        let call = OutgoingMessage::DetailedRpcCall("add".to_owned(), params.clone(), tx);
+        match call.to_call(Id::Str("a".to_owned())) {
+            Some(Call::MethodCall(call)) => assert_eq!(call.id, Id::Str("a".to_owned())),
+            call => panic!("unexpected call: {:?}", call),
+        }
+
+        let (tx, _rx) = oneshot::channel();
+        let call = OutgoingMessage::Notification("log".to_owned(), params.clone(), tx);
+        assert_eq!(
+            call.to_call(Id::Num(4)),
+            Some(Call::Notification(Notification {
+                jsonrpc: Some(Version::V2),
+                method: "log".to_owned(),
+                params,
+            }))
+        );
+
+        let output = result_to_output(Id::Num(5), Ok(JsonValue::Null));
+        let response = OutgoingMessage::Response(Response::Single(output));
+        assert_eq!(response.to_call(Id::Num(5)), None);
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{ErrorKind, Result};
+
+/// Limits on the payloads received from the server, checked before a payload is parsed. Set with
+/// [`Client::json_limits`](struct.Client.html#method.json_limits). Guards against deeply nested
+/// or huge JSON from untrusted servers. The calls answered by a payload exceeding a limit fail
+/// with `JsonLimitExceeded`. No limits are set by default.
+///
+/// The limits are checked once the transport has received the whole payload, so `max_size` does
+/// not bound the memory used while receiving it. Transports reading from a network should limit
+/// that themselves, like the `max_body_size` of the HTTP transport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonLimits {
+    /// The maximum nesting depth of arrays and objects.
+    pub max_depth: Option<usize>,
+    /// The maximum size of a payload in bytes.
+    pub max_size: Option<usize>,
+    /// The maximum number of values in a payload, counting every array, object, string, number,
+    /// boolean and null, but not object keys.
+    pub max_values: Option<usize>,
+}
+
+impl JsonLimits {
+    /// Returns true if no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == JsonLimits::default()
+    }
+
+    /// Checks `payload` against the limits, in a single pass without recursion. Does not
+    /// validate the JSON, that is left to the parser.
+    pub fn check(&self, payload: &str) -> Result<()> {
+        match self.exceeded(payload) {
+            Some(limit) => bail!(ErrorKind::JsonLimitExceeded(limit)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the name of the first limit `payload` exceeds, if any.
+    pub fn exceeded(&self, payload: &str) -> Option<&'static str> {
+        if self.is_unlimited() {
+            return None;
+        }
+        if self.max_size.map_or(false, |max| payload.len() > max) {
+            return Some("size");
+        }
+        let max_depth = self.max_depth.unwrap_or(usize::max_value());
+        let max_values = self.max_values.unwrap_or(usize::max_value());
+        // for each open container, true if it is an object
+        let mut containers = Vec::new();
+        let mut values = 0;
+        let mut in_string = false;
+        let mut in_scalar = false;
+        let mut escaped = false;
+        let mut expect_key = false;
+        for byte in payload.bytes() {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    in_string = false;
+                }
+                continue;
+            }
+            let starts_value = match byte {
+                b'"' => {
+                    in_string = true;
+                    !expect_key
+                }
+                b'{' | b'[' => {
+                    containers.push(byte == b'{');
+                    if containers.len() > max_depth {
+                        return Some("depth");
+                    }
+                    expect_key = byte == b'{';
+                    true
+                }
+                b'}' | b']' => {
+                    containers.pop();
+                    false
+                }
+                b',' => {
+                    expect_key = containers.last() == Some(&true);
+                    false
+                }
+                b':' => {
+                    expect_key = false;
+                    false
+                }
+                b' ' | b'\t' | b'\n' | b'\r' => false,
+                _ => !in_scalar,
+            };
+            in_scalar = match byte {
+                b'"' | b'{' | b'[' | b'}' | b']' | b',' | b':' | b' ' | b'\t' | b'\n' | b'\r' => {
+                    false
+                }
+                _ => true,
+            };
+            if byte == b'"' {
+                expect_key = false;
+            }
+            if starts_value {
+                values += 1;
+                if values > max_values {
+                    return Some("value count");
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns the raw JSON of the `id` of the response in `payload`, or of every response in a
+/// batch, in a single pass without recursion or parsing anything else. Lets the calls answered
+/// by a payload exceeding the limits be failed without parsing it.
+pub fn response_ids(payload: &str) -> Vec<&str> {
+    let batch = payload.bytes().find(|b| !b.is_ascii_whitespace()) == Some(b'[');
+    let id_depth = if batch { 2 } else { 1 };
+    let mut ids = Vec::new();
+    // for each open container, true if it is an object
+    let mut containers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut expect_key = false;
+    let mut in_key = false;
+    let mut key_is_id = false;
+    let mut string_start = 0;
+    let mut id_start = None;
+    for (index, byte) in payload.bytes().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+                if in_key {
+                    key_is_id = &payload[string_start..index] == "id";
+                }
+            }
+            continue;
+        }
+        match byte {
+            b'"' => {
+                in_string = true;
+                in_key = expect_key && containers.len() == id_depth;
+                string_start = index + 1;
+                expect_key = false;
+            }
+            b'{' | b'[' => {
+                containers.push(byte == b'{');
+                expect_key = byte == b'{';
+            }
+            b'}' | b']' | b',' => {
+                if containers.len() == id_depth {
+                    if let Some(start) = id_start.take() {
+                        ids.push(payload[start..index].trim());
+                    }
+                }
+                if byte == b',' {
+                    expect_key = containers.last() == Some(&true);
+                } else {
+                    containers.pop();
+                }
+            }
+            b':' => {
+                expect_key = false;
+                if key_is_id && containers.len() == id_depth {
+                    id_start = Some(index + 1);
+                }
+                key_is_id = false;
+            }
+            _ => (),
+        }
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(
+        max_depth: Option<usize>,
+        max_size: Option<usize>,
+        max_values: Option<usize>,
+    ) -> JsonLimits {
+        JsonLimits {
+            max_depth,
+            max_size,
+            max_values,
+        }
+    }
+
+    #[test]
+    fn depth() {
+        let limits = limits(Some(2), None, None);
+        assert_eq!(limits.exceeded(r#"{"result":[1,2],"id":1}"#), None);
+        assert_eq!(limits.exceeded(r#"{"result":[[1]],"id":1}"#), Some("depth"));
+        assert_eq!(limits.exceeded(r#"[{"result":{},"id":1}]"#), Some("depth"));
+        // closed containers no longer count
+        assert_eq!(limits.exceeded(r#"{"a":[1],"b":[2],"c":{}}"#), None);
+    }
+
+    #[test]
+    fn size() {
+        let limits = limits(None, Some(10), None);
+        assert_eq!(limits.exceeded(r#"{"id":1}"#), None);
+        assert_eq!(limits.exceeded(r#"{"id":12345}"#), Some("size"));
+        assert!(limits.check(r#"{"id":12345}"#).is_err());
+    }
+
+    #[test]
+    fn value_count() {
+        let limits = limits(None, None, Some(4));
+        // the object, "a", 12 and true, but not the keys
+        assert_eq!(limits.exceeded(r#"{"k":"a","l":12,"m":true}"#), None);
+        assert_eq!(
+            limits.exceeded(r#"{"k":"a","l":12,"m":true,"n":null}"#),
+            Some("value count")
+        );
+        assert_eq!(limits.exceeded(r#"[1, 2.5e3, -4]"#), None);
+    }
+
+    #[test]
+    fn brackets_in_strings() {
+        let limits = limits(Some(1), None, Some(3));
+        assert_eq!(limits.exceeded(r#"{"result":"[[{{","id":"}]"}"#), None);
+        assert_eq!(limits.exceeded(r#"{"[[":1,"{{":2}"#), None);
+    }
+
+    #[test]
+    fn escapes() {
+        let limits = limits(Some(1), None, None);
+        assert_eq!(limits.exceeded(r#"{"result":"\"[[\\","id":1}"#), None);
+        assert_eq!(limits.exceeded(r#"{"result":"\\","id":[1]}"#), Some("depth"));
+    }
+
+    #[test]
+    fn ids_of_responses() {
+        assert_eq!(response_ids(r#"{"jsonrpc":"2.0","result":[1],"id":7}"#), vec!["7"]);
+        assert_eq!(
+            response_ids(r#" [{"id":"a,}","result":{"id":1}}, {"result":null, "id" : 2 }]"#),
+            vec![r#""a,}""#, "2"]
+        );
+        assert_eq!(response_ids(r#"{"result":"\"id\":3","error":null}"#), Vec::<&str>::new());
+    }
+}
@@ -63,12 +63,15 @@ extern crate jsonrpc_core;
 extern crate log;
 #[macro_use]
 extern crate serde;
+#[cfg_attr(test, macro_use)]
 extern crate serde_json;
 
-use futures::future;
+use futures::future::{self, Executor};
 use futures::sync::mpsc;
 pub use futures::sync::oneshot;
 pub use futures::Future;
+#[doc(hidden)]
+pub use serde::de::DeserializeOwned;
 use futures::{Async, AsyncSink};
 use futures::{Sink, Stream};
 use jsonrpc_core::types::{
@@ -77,8 +80,16 @@ use jsonrpc_core::types::{
 };
 use serde_json::Value as JsonValue;
 
+/// A JSON object, the members of which are not known in advance.
+pub type JsonMap = serde_json::Map<String, JsonValue>;
+
 
 use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Contains the main macro of this crate, `jsonrpc_client`.
 #[macro_use]
@@ -93,6 +104,55 @@ use jsonrpc_client_utils::select_weak::{self, SelectWithWeakExt};
 /// various method and notification requests coming in from the server. Does not work with HTTP.
 pub mod server;
 
+/// Module containing the timer abstraction used by the parts of the client that need to wait.
+pub mod timer;
+use timer::Timer;
+
+/// Module containing `PollStream`, turning a polling method into a `Stream` of results.
+pub mod poll_stream;
+
+/// Module containing `Paginate`, turning a paginated method into a `Stream` of items.
+pub mod paginate;
+
+/// Module containing `ServerProbe`, finding out the version of the server.
+pub mod probe;
+
+mod json_limits;
+pub use json_limits::JsonLimits;
+
+mod batching;
+use batching::CallBatching;
+
+mod multiplex;
+pub use multiplex::{MultiplexedTransport, Multiplexer, MultiplexerHandle};
+
+/// Module containing conversions to and from the types of the `jsonrpc-core` server crate.
+#[cfg(feature = "interop")]
+pub mod interop;
+
+/// Module containing serde types for values commonly found in JSON-RPC APIs.
+#[cfg(feature = "types")]
+pub mod types;
+
+/// Module containing a typed client for the standard methods of Ethereum nodes.
+#[cfg(feature = "ethereum")]
+pub mod ethereum;
+
+/// Module containing `FaultyTransport`, injecting faults into a transport for resilience tests.
+#[cfg(feature = "faulty-transport")]
+pub mod faulty;
+
+/// Module containing process wide counters of the work done by clients, for benchmarks.
+#[cfg(feature = "bench")]
+pub mod counters;
+
+/// Module containing checks of the behavior of `Transport` implementations, for their tests.
+#[cfg(feature = "transport-tests")]
+pub mod transport_tests;
+
+#[cfg(test)]
+mod test_transport;
+
 /// Module containing an example client. To show in the docs what a generated struct look like.
 pub mod example;
 
@@ -115,6 +175,11 @@ error_chain! {
             description("Unable to deserialize the response into the desired type")
             display("Unable to deserialize the response: {}", msg)
         }
+        /// A payload from the server exceeded one of the configured `JsonLimits`.
+        JsonLimitExceeded(limit: &'static str) {
+            description("Response exceeds a JSON limit")
+            display("Response exceeds the JSON {} limit", limit)
+        }
         /// The server returned a response with an incorrect version
         InvalidVersion {
             description("Method call returned a response that was not specified as JSON-RPC 2.0")
@@ -124,14 +189,165 @@ error_chain! {
         Shutdown {
             description("RPC Client already shut down")
         }
+        /// The call was made, but the client was dropped or shut down before the response
+        /// arrived.
+        Cancelled {
+            description("RPC call cancelled because the client stopped before the response")
+        }
         /// The request was replied to, but with a JSON-RPC 2.0 error.
         JsonRpcError(error: jsonrpc_core::Error) {
             description("Method call returned JSON-RPC 2.0 error")
             display("JSON-RPC 2.0 Error: {} ({})", error.code.description(), error.message)
         }
+        /// The result of a call was rejected by the validator set for the method with
+        /// `ClientHandle::set_validator`.
+        ValidationError(method: String, reason: String) {
+            description("Method call returned a result that failed validation")
+            display("Invalid result from {}: {}", method, reason)
+        }
+    }
+}
+
+/// What an application can do about a failed call, from
+/// [`Error::recovery_hint`](struct.Error.html#method.recovery_hint). Lets retry loops share one
+/// policy across transports, instead of matching the error kinds of each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecoveryHint {
+    /// The failure is likely temporary, like a timeout or an overloaded server. Make the call
+    /// again, preferably after a backoff.
+    Retry,
+    /// The server rejected the credentials. Renew them before making the call again.
+    Reauthenticate,
+    /// The client or its transport stopped. Create a new client before making the call again.
+    Reconnect,
+    /// Making the call again will fail the same way, like for invalid parameters or a result
+    /// that can't be deserialized.
+    GiveUp,
+}
+
+impl Error {
+    /// Returns what an application can do about this error. Errors of the transport are
+    /// classified by [`Transport::recovery_hint`](trait.Transport.html#method.recovery_hint).
+    pub fn recovery_hint(&self) -> RecoveryHint {
+        match *self.kind() {
+            ErrorKind::TransportError => self
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|error| error.downcast_ref::<TransportFailure>())
+                .map_or(RecoveryHint::Reconnect, |failure| failure.hint),
+            ErrorKind::Shutdown | ErrorKind::Cancelled => RecoveryHint::Reconnect,
+            ErrorKind::JsonRpcError(ref error) => match error.code {
+                jsonrpc_core::ErrorCode::InternalError => RecoveryHint::Retry,
+                _ => RecoveryHint::GiveUp,
+            },
+            _ => RecoveryHint::GiveUp,
+        }
+    }
+
+    /// Calls `f` with the error of the transport if this is a `TransportError`, and returns its
+    /// result. Lets applications tell failures apart by downcasting the error of the transport,
+    /// like a refused connection from a failed DNS lookup. The client and all calls failed by the
+    /// same error of the transport share that error.
+    pub fn with_transport_error<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&(dyn StdError + Send + 'static)) -> R,
+    {
+        match *self.kind() {
+            ErrorKind::TransportError => self
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|error| error.downcast_ref::<TransportFailure>())
+                .map(|failure| f(&**failure.error.lock().unwrap())),
+            _ => None,
+        }
+    }
+}
+
+/// An error of the transport, with the `RecoveryHint` the transport gave for it. Displays as the
+/// error and its causes. Every error of the transport stops the client, so the hint is never
+/// `Retry`.
+///
+/// Errors can't be cloned, so the error is shared by the client and all calls it fails. It is
+/// behind a mutex as errors don't have to be `Sync`.
+#[derive(Debug, Clone)]
+struct TransportFailure {
+    hint: RecoveryHint,
+    message: String,
+    error: Arc<Mutex<Box<dyn StdError + Send>>>,
+}
+
+impl TransportFailure {
+    fn new<T: Transport>(error: T::Error) -> Error {
+        let hint = match T::recovery_hint(&error) {
+            RecoveryHint::Retry => RecoveryHint::Reconnect,
+            hint => hint,
+        };
+        let mut message = error.to_string();
+        let mut cause = error.cause();
+        while let Some(error) = cause {
+            message = format!("{}: {}", message, error);
+            cause = error.cause();
+        }
+        TransportFailure {
+            hint,
+            message,
+            error: Arc::new(Mutex::new(Box::new(error))),
+        }.into_error()
+    }
+
+    fn into_error(self) -> Error {
+        Error::with_chain(self, ErrorKind::TransportError)
+    }
+}
+
+impl fmt::Display for TransportFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for TransportFailure {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A copy of the messages of an error and all its causes. Errors can't be cloned, so this is used
+/// where an error has to be handed to more than one receiver, like when the transport fails while
+/// several calls are waiting for their responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorCopy {
+    message: String,
+    cause: Option<Box<ErrorCopy>>,
+}
+
+impl ErrorCopy {
+    /// Copies the messages of `error` and its causes.
+    pub fn new(error: &dyn StdError) -> Self {
+        ErrorCopy {
+            message: error.to_string(),
+            cause: error.cause().map(|cause| Box::new(ErrorCopy::new(cause))),
+        }
     }
 }
 
+impl fmt::Display for ErrorCopy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for ErrorCopy {
+    fn description(&self) -> &str {
+        &self.message
+    }
+
+    fn cause(&self) -> Option<&dyn StdError> {
+        self.cause.as_ref().map(|cause| &**cause as &dyn StdError)
+    }
+}
 
 /// This handle allows one to create futures for RPC invocations. For the requests to ever be
 /// resolved, the Client future has to be driven.
@@ -139,9 +355,73 @@ error_chain! {
 #[derive(Debug, Clone)]
 pub struct ClientHandle {
     client_handle_tx: mpsc::Sender<OutgoingMessage>,
+    // the method name known to work for methods called with fallbacks, keyed by primary name
+    resolved_methods: Arc<Mutex<HashMap<String, String>>>,
+    validators: Arc<Validators>,
+    dropped_responses: Arc<AtomicUsize>,
+}
+
+/// A check of the result of a method, see
+/// [`ClientHandle::set_validator`](struct.ClientHandle.html#method.set_validator).
+pub type Validator = Arc<dyn Fn(&JsonValue) -> ::std::result::Result<(), String> + Send + Sync>;
+
+/// The validators of a handle and its clones, keyed by method name.
+#[derive(Default)]
+struct Validators(Mutex<HashMap<String, Validator>>);
+
+impl fmt::Debug for Validators {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let validators = self.0.lock().unwrap();
+        f.debug_set().entries(validators.keys()).finish()
+    }
 }
 
 impl ClientHandle {
+    /// Returns the number of responses the `Client` of this handle received but could not
+    /// deliver, because the caller was gone or no call was waiting for them. See
+    /// [`Client::dead_letters`](struct.Client.html#method.dead_letters).
+    pub fn dropped_responses(&self) -> usize {
+        self.dropped_responses.load(Ordering::Relaxed)
+    }
+
+    /// Configure a check of the result of every call to `method` made through this handle and its
+    /// clones, including calls made by clients generated with `jsonrpc_client!` and calls sent in
+    /// a batch by [`Client::batch_calls`](struct.Client.html#method.batch_calls). Calls made with
+    /// [`call_method_with_fallbacks`](#method.call_method_with_fallbacks) are checked by the
+    /// validator of their primary name, whichever name the server answered to, or of the name
+    /// used when none is set for the primary name. The validator gets the result before it is
+    /// deserialized, and a result it rejects fails the call with `ValidationError` carrying the
+    /// returned reason. Replaces any validator already set for the method.
+    pub fn set_validator<F>(&self, method: impl Into<String>, validator: F)
+    where
+        F: Fn(&JsonValue) -> ::std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators
+            .0
+            .lock()
+            .unwrap()
+            .insert(method.into(), Arc::new(validator));
+    }
+
+    /// Runs the validator set for the first of `methods` that has one, if any, on the result of a
+    /// call.
+    fn validate(validators: &Validators, methods: &[&str], result: JsonValue) -> Result<JsonValue> {
+        let validator = {
+            let validators = validators.0.lock().unwrap();
+            methods
+                .iter()
+                .filter_map(|&method| validators.get(method).map(|v| (method, v.clone())))
+                .next()
+        };
+        match validator {
+            Some((method, validator)) => match validator(&result) {
+                Ok(()) => Ok(result),
+                Err(reason) => Err(ErrorKind::ValidationError(method.to_owned(), reason).into()),
+            },
+            None => Ok(result),
+        }
+    }
+
     /// Invokes an RPC and creates a future representing the RPC's result.
     pub fn call_method<T>(
         &self,
@@ -159,6 +439,150 @@ impl ClientHandle {
         })
     }
 
+    /// Invokes an RPC and calls `callback` with its result, for callers that can't hold futures,
+    /// like FFI layers. The call is driven by a future spawned on `executor`. Fails with
+    /// `Shutdown`, without calling `callback`, if the executor refuses the future.
+    pub fn call_with_callback<T, F, E>(
+        &self,
+        executor: &E,
+        method: impl Into<String> + 'static,
+        parameters: &impl serde::Serialize,
+        callback: F,
+    ) -> Result<()>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+        F: FnOnce(Result<T>) + Send + 'static,
+        E: Executor<ResponseDelivery>,
+    {
+        let method = method.into();
+        let call = self.call_method(method, parameters).then(move |result| {
+            callback(result);
+            Ok::<(), ()>(())
+        });
+        executor
+            .execute(Box::new(call))
+            .map_err(|_| ErrorKind::Shutdown.into())
+    }
+
+    /// Invokes an RPC and creates a future representing the RPC's result, together with any
+    /// top-level fields of the response object not defined by JSON-RPC 2.0. Some servers add
+    /// metadata, like the time it took to process the call, to their responses this way.
+    pub fn call_method_detailed<T>(
+        &self,
+        method: impl Into<String> + 'static,
+        parameters: &impl serde::Serialize,
+    ) -> impl Future<Item = DetailedResponse<T>, Error = Error> + 'static
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let rpc_chan = self.client_handle_tx.clone();
+        let validators = self.validators.clone();
+        let method = method.into();
+        let validated_method = method.clone();
+
+        future::result(serialize_parameters(parameters))
+            .and_then(move |params| {
+                rpc_chan
+                    .send(OutgoingMessage::DetailedRpcCall(method, params, tx))
+                    .map_err(|_| ErrorKind::Shutdown.into())
+            }).and_then(|_| rx.map_err(|_| ErrorKind::Cancelled).flatten())
+            .and_then(move |response: DetailedResponse<JsonValue>| -> Result<DetailedResponse<T>> {
+                let result = Self::validate(&validators, &[&validated_method], response.result)?;
+                let result =
+                    serde_json::from_value(result).chain_err(|| ErrorKind::DeserializeError)?;
+                Ok(DetailedResponse {
+                    result,
+                    extra_fields: response.extra_fields,
+                })
+            })
+    }
+
+    /// Invokes an RPC that is known under different names by different servers, such as a new
+    /// name and a legacy alias. The names are tried in order, moving on to the next name whenever
+    /// the server responds with a "method not found" error. The name that worked is remembered
+    /// and tried first on subsequent calls through this handle and its clones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `methods` is empty.
+    pub fn call_method_with_fallbacks<T>(
+        &self,
+        methods: &[&str],
+        parameters: &impl serde::Serialize,
+    ) -> impl Future<Item = T, Error = Error> + 'static
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        assert!(!methods.is_empty(), "At least one method name is required");
+        let primary = methods[0].to_owned();
+        let mut candidates = methods.iter().map(|&method| method.to_owned()).collect::<Vec<_>>();
+        if let Some(resolved) = self.resolved_methods.lock().unwrap().get(&primary) {
+            if let Some(index) = candidates.iter().position(|method| method == resolved) {
+                let resolved = candidates.remove(index);
+                candidates.insert(0, resolved);
+            }
+        }
+
+        let client = self.clone();
+        let validators = self.validators.clone();
+        let validated_primary = primary.clone();
+        future::result(serialize_parameters(parameters))
+            .and_then(move |params| {
+                future::loop_fn(
+                    (candidates.into_iter(), None),
+                    move |(mut candidates, last_error): (_, Option<Error>)| {
+                        let method = match candidates.next() {
+                            Some(method) => method,
+                            None => {
+                                return future::Either::A(future::err(
+                                    last_error.expect("At least one method is always tried"),
+                                ))
+                            }
+                        };
+                        let (tx, rx) = oneshot::channel();
+                        let call = OutgoingMessage::RpcCall(method.clone(), params.clone(), tx);
+                        let client = client.clone();
+                        let primary = primary.clone();
+                        future::Either::B(client.send_unvalidated_call(call, rx).then(
+                            move |result| match result {
+                                Ok(value) => {
+                                    client
+                                        .resolved_methods
+                                        .lock()
+                                        .unwrap()
+                                        .insert(primary, method.clone());
+                                    Ok(future::Loop::Break((method, value)))
+                                }
+                                Err(e) => if is_method_not_found(&e) {
+                                    debug!("Method {} not found, trying next fallback", method);
+                                    Ok(future::Loop::Continue((candidates, Some(e))))
+                                } else {
+                                    Err(e)
+                                },
+                            },
+                        ))
+                    },
+                )
+            }).and_then(move |(method, value)| {
+                let value = Self::validate(&validators, &[&validated_primary, &method], value)?;
+                serde_json::from_value(value).chain_err(|| ErrorKind::DeserializeError)
+            })
+    }
+
+    /// Sends a call to the Client and waits for its result, without validating it.
+    fn send_unvalidated_call(
+        &self,
+        call: OutgoingMessage,
+        rx: oneshot::Receiver<Result<JsonValue>>,
+    ) -> impl Future<Item = JsonValue, Error = Error> {
+        self.client_handle_tx
+            .clone()
+            .send(call)
+            .map_err(|_| ErrorKind::Shutdown.into())
+            .and_then(|_| rx.map_err(|_| ErrorKind::Cancelled).flatten())
+    }
+
     /// Send arbitrary RPC call to Client. Primarily intended to be used from macro
     /// `jsonrpc_client!`.
     #[doc(hidden)]
@@ -167,12 +591,47 @@ impl ClientHandle {
         client_call: Result<OutgoingMessage>,
         rx: oneshot::Receiver<Result<JsonValue>>,
     ) -> impl Future<Item = T, Error = Error> {
-        let rpc_chan = self.client_handle_tx.clone();
+        self.send_client_call_with(client_call, rx, serde_json::from_value)
+    }
+
+    /// Send arbitrary RPC call to Client, deserializing the result with the given function.
+    /// Primarily intended to be used from macro `jsonrpc_client!`.
+    #[doc(hidden)]
+    pub fn send_client_call_with<T, F>(
+        &self,
+        client_call: Result<OutgoingMessage>,
+        rx: oneshot::Receiver<Result<JsonValue>>,
+        deserialize: F,
+    ) -> impl Future<Item = T, Error = Error>
+    where
+        T: Send + Sized,
+        F: FnOnce(JsonValue) -> ::std::result::Result<T, serde_json::Error>,
+    {
+        let client = self.clone();
+        let validators = self.validators.clone();
+        let method = match client_call {
+            Ok(OutgoingMessage::RpcCall(ref method, _, _)) => Some(method.clone()),
+            _ => None,
+        };
 
         future::result(client_call)
-            .and_then(|call| rpc_chan.send(call).map_err(|_| ErrorKind::Shutdown.into()))
-            .and_then(|_| rx.map_err(|_| ErrorKind::Shutdown).flatten())
-            .and_then(|r| serde_json::from_value(r).chain_err(|| ErrorKind::DeserializeError))
+            .and_then(move |call| client.send_unvalidated_call(call, rx))
+            .and_then(move |r| match method {
+                Some(method) => Self::validate(&validators, &[&method], r),
+                None => Ok(r),
+            }).and_then(|r| deserialize(r).chain_err(|| ErrorKind::DeserializeError))
+    }
+
+    /// Send arbitrary RPC call to Client, discarding whatever result the server returns.
+    /// Primarily intended to be used from macro `jsonrpc_client!`.
+    #[doc(hidden)]
+    pub fn send_client_call_ignoring_result(
+        &self,
+        client_call: Result<OutgoingMessage>,
+        rx: oneshot::Receiver<Result<JsonValue>>,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.send_client_call::<serde::de::IgnoredAny>(client_call, rx)
+            .map(|_| ())
     }
 
 
@@ -211,6 +670,20 @@ pub trait Transport: Sized + Send{
     /// Transforms the transport implementation into a sink and a stream.
     fn io_pair(self) -> (Self::Sink, Self::Stream);
 
+    /// Returns what an application can do about a call failed by `error`, for
+    /// [`Error::recovery_hint`](struct.Error.html#method.recovery_hint). Defaults to
+    /// `Reconnect`, as the client stops when its transport fails. For the same reason `Retry` is
+    /// turned into `Reconnect`.
+    fn recovery_hint(_error: &Self::Error) -> RecoveryHint {
+        RecoveryHint::Reconnect
+    }
+
+    /// Describes the server the transport talks to, like its URI, for logging. Must not contain
+    /// credentials. Defaults to `None`.
+    fn endpoint(&self) -> Option<String> {
+        None
+    }
+
     /// Creates a Client and a ClientHandle from a transport implementation.
     fn into_client(self) -> (Client<Self, server::Server>, ClientHandle) {
         Client::new(self)
@@ -244,6 +717,19 @@ pub struct Client<T: Transport, S: server::ServerHandler> {
     id_generator: IdGenerator,
     shutting_down: bool,
     pending_client_requests: HashMap<Id, oneshot::Sender<Result<JsonValue>>>,
+    pending_detailed_requests: HashMap<Id, oneshot::Sender<Result<DetailedResponse<JsonValue>>>>,
+    // the method and start time of calls waiting for a response, for reporting dropped responses
+    call_info: HashMap<Id, CallInfo>,
+    dead_letters: Arc<DeadLetters>,
+    // call coalescing, identical calls made while a call is in flight wait for its response
+    coalesce_calls: bool,
+    in_flight_calls: HashMap<String, Id>,
+    coalesced_calls: HashMap<Id, (String, Vec<oneshot::Sender<Result<JsonValue>>>)>,
+    response_parsing: ResponseParsing,
+    json_limits: JsonLimits,
+    response_executor: Option<ResponseExecutor>,
+    drain_on_shutdown: bool,
+    batching: Option<CallBatching>,
     pending_payload: Option<String>,
     fatal_error: Option<Error>,
 
@@ -255,6 +741,179 @@ pub struct Client<T: Transport, S: server::ServerHandler> {
     transport_rx: T::Stream,
 }
 
+/// A future handing the result of a call to its caller, run on the executor set with
+/// [`Client::response_executor`](struct.Client.html#method.response_executor).
+pub type ResponseDelivery = Box<dyn Future<Item = (), Error = ()> + Send>;
+
+/// A response the `Client` received but could not deliver to a caller, handed to the callback
+/// set with [`Client::dead_letters`](struct.Client.html#method.dead_letters).
+#[derive(Debug)]
+pub struct DroppedResponse {
+    /// Why the response was dropped.
+    pub reason: DropReason,
+    /// The id of the response.
+    pub id: Id,
+    /// The method of the call the response belongs to, unless no call was waiting for it.
+    pub method: Option<String>,
+    /// The server the response came from, as described by `Transport::endpoint`.
+    pub endpoint: Option<String>,
+    /// The time from the call being sent to its response being received, unless no call was
+    /// waiting for it.
+    pub elapsed: Option<Duration>,
+    /// The result or error the response carried.
+    pub result: Result<JsonValue>,
+}
+
+/// Why a `DroppedResponse` could not be delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The future of the call was dropped before the response arrived, usually because the
+    /// caller stopped waiting for it after a timeout.
+    CallerGone,
+    /// No call was waiting for a response with this id, because the server answered a call
+    /// twice or sent an id the client never used.
+    UnknownId,
+}
+
+/// The method of a call waiting for a response and when it was made.
+#[derive(Debug, Clone)]
+struct CallInfo {
+    method: String,
+    started: Instant,
+}
+
+impl CallInfo {
+    fn new(method: &str) -> Self {
+        CallInfo {
+            method: method.to_owned(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Where the `Client` reports the responses it could not deliver: the log, the counter shared
+/// with its handles, and the callback set with `Client::dead_letters`.
+#[derive(Clone)]
+struct DeadLetters {
+    endpoint: Option<String>,
+    count: Arc<AtomicUsize>,
+    callback: Option<Arc<dyn Fn(DroppedResponse) + Send + Sync>>,
+}
+
+impl DeadLetters {
+    fn report(
+        &self,
+        reason: DropReason,
+        id: Id,
+        call: Option<&CallInfo>,
+        result: Result<JsonValue>,
+    ) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let dropped = DroppedResponse {
+            reason,
+            id,
+            method: call.map(|call| call.method.clone()),
+            endpoint: self.endpoint.clone(),
+            elapsed: call.map(|call| call.started.elapsed()),
+            result,
+        };
+        let level = match reason {
+            DropReason::CallerGone => log::Level::Debug,
+            DropReason::UnknownId => log::Level::Warn,
+        };
+        log!(
+            level,
+            "Dropped response {:?} to {} from {} after {:?}: {:?}",
+            dropped.id,
+            dropped.method.as_ref().map_or("an unknown call", String::as_str),
+            dropped.endpoint.as_ref().map_or("the server", String::as_str),
+            dropped.elapsed,
+            reason
+        );
+        if let Some(ref callback) = self.callback {
+            callback(dropped);
+        }
+    }
+}
+
+impl fmt::Debug for DeadLetters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeadLetters")
+            .field("endpoint", &self.endpoint)
+            .field("count", &self.count)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// The result of a call as delivered to its caller, which is reported as is when it is dropped.
+trait CallResult: Send + 'static {
+    fn into_value(self) -> JsonValue;
+}
+
+impl CallResult for JsonValue {
+    fn into_value(self) -> JsonValue {
+        self
+    }
+}
+
+impl CallResult for DetailedResponse<JsonValue> {
+    fn into_value(self) -> JsonValue {
+        self.result
+    }
+}
+
+/// The executor set with `Client::response_executor`.
+struct ResponseExecutor(Box<dyn Executor<ResponseDelivery> + Send>);
+
+impl fmt::Debug for ResponseExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ResponseExecutor")
+    }
+}
+
+/// Sends a result to the caller waiting on `chan`, reporting it as dropped if the caller is gone
+/// and the result is the response to `call`.
+fn deliver_response<V: CallResult>(
+    id: &Id,
+    call: Option<CallInfo>,
+    chan: oneshot::Sender<Result<V>>,
+    value: Result<V>,
+    dead_letters: &DeadLetters,
+) {
+    if let Err(value) = chan.send(value) {
+        match call {
+            Some(ref call) => {
+                let result = value.map(CallResult::into_value);
+                dead_letters.report(DropReason::CallerGone, id.clone(), Some(call), result);
+            }
+            None => trace!("Future for RPC call {:?} dropped already", id),
+        }
+    }
+}
+
+/// How strictly responses from the server are validated. Set with
+/// [`Client::response_parsing`](struct.Client.html#method.response_parsing).
+///
+/// Regardless of mode, a malformed response whose id matches a pending call only fails that call.
+/// Malformed messages that can't be attributed to a call are fatal to the `Client`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseParsing {
+    /// Responses must follow the JSON-RPC 2.0 specification exactly. They must contain the
+    /// `jsonrpc` field, exactly one of `result` and `error`, and fields of the correct types.
+    Strict,
+    /// Tolerates common deviations from the specification. Responses without the `jsonrpc` field
+    /// are treated as JSON-RPC 2.0, and an `error` or `result` field that is `null` is ignored when
+    /// the other one is present.
+    Lenient,
+}
+
+impl Default for ResponseParsing {
+    fn default() -> Self {
+        ResponseParsing::Strict
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 enum IncomingMessage {
@@ -286,6 +945,12 @@ impl<T: DuplexTransport, S: server::ServerHandler> Client<T, S> {
 
 impl<T: Transport, S: server::ServerHandler> Client<T, S> {
     fn new_with_server(transport: T, server_handler: S) -> (Self, ClientHandle) {
+        let dead_letters = Arc::new(DeadLetters {
+            endpoint: transport.endpoint(),
+            count: Arc::new(AtomicUsize::new(0)),
+            callback: None,
+        });
+        let dropped_responses = dead_letters.count.clone();
         let (transport_tx, transport_rx) = transport.io_pair();
         let (client_handle_tx, client_handle_rx) = mpsc::channel(0);
         let (server_response_tx, server_response_rx) = mpsc::channel(0);
@@ -304,6 +969,17 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
                 shutting_down: false,
                 fatal_error: None,
                 pending_client_requests: HashMap::new(),
+                pending_detailed_requests: HashMap::new(),
+                call_info: HashMap::new(),
+                dead_letters,
+                coalesce_calls: false,
+                in_flight_calls: HashMap::new(),
+                coalesced_calls: HashMap::new(),
+                response_parsing: ResponseParsing::default(),
+                json_limits: JsonLimits::default(),
+                response_executor: None,
+                drain_on_shutdown: false,
+                batching: None,
 
                 // server handlers
                 server_handler,
@@ -313,10 +989,89 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
                 transport_tx,
                 transport_rx,
             },
-            ClientHandle { client_handle_tx },
+            ClientHandle {
+                client_handle_tx,
+                resolved_methods: Arc::new(Mutex::new(HashMap::new())),
+                validators: Arc::new(Validators::default()),
+                dropped_responses,
+            },
         )
     }
 
+    /// Enables or disables coalescing of identical calls. When enabled, a call with the same
+    /// method and parameters as a call that is still waiting for its response is not sent to the
+    /// server. Instead it resolves to the response of the call already in flight. Useful when
+    /// many tasks independently poll the same value from the server. Disabled by default.
+    pub fn coalesce_calls(mut self, enabled: bool) -> Self {
+        self.coalesce_calls = enabled;
+        self
+    }
+
+    /// Configures how strictly responses from the server are validated. Defaults to
+    /// [`ResponseParsing::Strict`](enum.ResponseParsing.html#variant.Strict).
+    pub fn response_parsing(mut self, response_parsing: ResponseParsing) -> Self {
+        self.response_parsing = response_parsing;
+        self
+    }
+
+    /// Configures limits on the size and complexity of the payloads received from the server.
+    /// A payload exceeding them is not parsed. The calls it answers fail with `JsonLimitExceeded`,
+    /// and a payload answering none of the calls waiting for a response is fatal to the `Client`
+    /// like any other payload that can't be parsed.
+    pub fn json_limits(mut self, json_limits: JsonLimits) -> Self {
+        self.json_limits = json_limits;
+        self
+    }
+
+    /// Completes the futures of calls by running a `ResponseDelivery` future for each result on
+    /// `executor`, instead of completing them directly on the task driving the `Client`. Combined
+    /// with spawning the futures of calls on the same executor, for example one running on the
+    /// event loop of a user interface, the results are handled entirely on that executor. Results
+    /// are delivered directly if the executor refuses a future.
+    pub fn response_executor<E>(mut self, executor: E) -> Self
+    where
+        E: Executor<ResponseDelivery> + Send + 'static,
+    {
+        self.response_executor = Some(ResponseExecutor(Box::new(executor)));
+        self
+    }
+
+    /// Hands every response that could not be delivered to `callback`, for debugging. A response
+    /// is dropped when the future of its call was dropped before it arrived, or when no call was
+    /// waiting for it. Dropped responses are logged and counted in
+    /// [`ClientHandle::dropped_responses`](struct.ClientHandle.html#method.dropped_responses)
+    /// either way. The callback runs on the task driving the `Client`, or on the executor set
+    /// with [`response_executor`](#method.response_executor), so it must not block.
+    pub fn dead_letters<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(DroppedResponse) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.dead_letters).callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Configures if the `Client` keeps receiving responses when it shuts down because all
+    /// handles are dropped, until no call waits for a response or the transport stream ends.
+    /// Calls that are still waiting when the `Client` is dropped fail with `Cancelled`. Disabled
+    /// by default, so calls still waiting when the handles are dropped fail right away.
+    pub fn drain_on_shutdown(mut self, enabled: bool) -> Self {
+        self.drain_on_shutdown = enabled;
+        self
+    }
+
+    /// Sends the calls made within `window` of each other, or until `max_calls` are waiting, as
+    /// a single JSON-RPC 2.0 batch, without any change to the code making the calls. The window
+    /// starts with the first call after a batch was sent, and is measured with `timer`.
+    /// Notifications are batched with the calls to keep their order. The server must support
+    /// batches. Disabled by default.
+    pub fn batch_calls<M>(mut self, window: Duration, max_calls: usize, timer: M) -> Self
+    where
+        M: Timer + Send + 'static,
+    {
+        self.batching = Some(CallBatching::new(Box::new(timer), window, max_calls));
+        self
+    }
+
     fn should_shut_down(&mut self) -> bool {
         self.fatal_error.is_some() || self.shutting_down
     }
@@ -334,6 +1089,8 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
         self.poll_transport_rx()?;
         // drain incoming rpc requests, only if the writing pipe is ready
         self.poll_outgoing_messages()?;
+        // send the batch of calls once its window has passed
+        self.poll_batch()?;
         // poll transport tx to drive sending
         self.poll_transport_tx()?;
         Ok(())
@@ -341,13 +1098,51 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
 
     fn send_payload(&mut self, json_string: String) -> Result<()> {
         ensure!(self.fatal_error.is_none(), ErrorKind::TransportError);
+        #[cfg(feature = "bench")]
+        let len = json_string.len();
         match self.transport_tx.start_send(json_string) {
-            Ok(AsyncSink::Ready) => Ok(()),
+            Ok(AsyncSink::Ready) => {
+                #[cfg(feature = "bench")]
+                counters::payload_sent(len);
+                Ok(())
+            }
             Ok(AsyncSink::NotReady(payload)) => {
                 self.pending_payload = Some(payload);
                 Ok(())
             }
-            Err(e) => Err(e).chain_err(|| ErrorKind::TransportError),
+            Err(e) => Err(TransportFailure::new::<T>(e)),
+        }
+    }
+
+    /// Sends the payload of a call or notification, or adds it to the batch being collected.
+    fn queue_payload(&mut self, payload: String) -> Result<()> {
+        match self.batching {
+            Some(ref mut batching) => batching.push(payload),
+            None => return self.send_payload(payload),
+        }
+        self.poll_batch()
+    }
+
+    /// Sends the batch being collected if it is due and nothing else is waiting to be sent.
+    fn poll_batch(&mut self) -> Result<()> {
+        let due = match self.batching {
+            Some(ref mut batching) => batching.poll_due(),
+            None => false,
+        };
+        if due && self.pending_payload.is_none() {
+            self.send_batch()?;
+        }
+        Ok(())
+    }
+
+    fn send_batch(&mut self) -> Result<()> {
+        let payload = match self.batching {
+            Some(ref mut batching) => batching.take_payload(),
+            None => None,
+        };
+        match payload {
+            Some(payload) => self.send_payload(payload),
+            None => Ok(()),
         }
     }
 
@@ -356,7 +1151,7 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
             match self
                 .transport_rx
                 .poll()
-                .chain_err(|| ErrorKind::TransportError)?
+                .map_err(TransportFailure::new::<T>)?
             {
                 Async::Ready(Some(new_payload)) => {
                     self.handle_transport_rx_payload(&new_payload)?;
@@ -372,32 +1167,220 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
     }
 
     fn handle_transport_rx_payload(&mut self, payload: &str) -> Result<()> {
-        let msg: IncomingMessage =
+        #[cfg(feature = "bench")]
+        counters::payload_received(payload.len());
+        if let Some(limit) = self.json_limits.exceeded(payload) {
+            return self.fail_limited_calls(payload, limit);
+        }
+        if payload.bytes().find(|b| !b.is_ascii_whitespace()) == Some(b'[') {
+            return self.handle_batch_payload(payload);
+        }
+        let value: JsonValue =
             serde_json::from_str(&payload).chain_err(|| ErrorKind::DeserializeError)?;
-        match msg {
-            IncomingMessage::Request(req) => self
+        match self.parse_incoming(value)? {
+            Some((IncomingMessage::Request(req), _)) => self
                 .server_handler
                 .process_request(req, self.server_response_tx.clone()),
-            IncomingMessage::Response(response) => self.handle_response(response),
+            Some((IncomingMessage::Response(response), extra_fields)) => {
+                self.handle_response(response, extra_fields)
+            }
+            None => Ok(()),
         }
     }
 
-    fn handle_response(&mut self, output: Output) -> Result<()> {
-        if output.version() != Some(jsonrpc_core::types::Version::V2) {
-            return Err(ErrorKind::InvalidVersion.into());
+    /// Validates an incoming message according to the configured `ResponseParsing`. Returns
+    /// `None` if the message was a malformed response that has been delivered as an error to the
+    /// call it belongs to.
+    ///
+    /// Top-level fields of responses that are not part of JSON-RPC 2.0 are removed before
+    /// validation, and returned alongside the message.
+    fn parse_incoming(
+        &mut self,
+        mut value: JsonValue,
+    ) -> Result<Option<(IncomingMessage, JsonMap)>> {
+        if self.response_parsing == ResponseParsing::Lenient {
+            make_lenient(&mut value);
+        }
+        let extra_fields = take_extra_fields(&mut value);
+        let id = response_id(&value);
+        let error = match serde_json::from_value(value) {
+            Ok(IncomingMessage::Response(ref output)) if output.version() != Some(Version::V2) => {
+                Error::from(ErrorKind::InvalidVersion)
+            }
+            Ok(message) => return Ok(Some((message, extra_fields))),
+            Err(e) => Error::with_chain(e, ErrorKind::DeserializeError),
         };
+        match id.filter(|id| self.is_pending(id)) {
+            Some(id) => {
+                debug!("Malformed response to RPC call {:?}: {}", id, error);
+                self.complete_call(id, Err(error), extra_fields);
+                Ok(None)
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Fails the calls answered by a payload exceeding the `JsonLimits`, without parsing it. The
+    /// payload is fatal to the client if it answers none of the calls waiting for a response.
+    fn fail_limited_calls(&mut self, payload: &str, limit: &'static str) -> Result<()> {
+        let ids = json_limits::response_ids(payload)
+            .into_iter()
+            .filter_map(|id| serde_json::from_str::<Id>(id).ok())
+            .filter(|id| self.is_pending(id))
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            bail!(ErrorKind::JsonLimitExceeded(limit));
+        }
+        for id in ids {
+            debug!("Response to RPC call {:?} exceeds the {} limit", id, limit);
+            let error = ErrorKind::JsonLimitExceeded(limit).into();
+            self.complete_call(id, Err(error), JsonMap::new());
+        }
+        Ok(())
+    }
+
+    fn is_pending(&self, id: &Id) -> bool {
+        self.pending_client_requests.contains_key(id)
+            || self.pending_detailed_requests.contains_key(id)
+    }
+
+    /// Handles a payload containing an array of messages. The responses in the batch are
+    /// dispatched to their callers, and the requests from the server in it are handed to the
+    /// server handler as one batch. Transports deliver whole payloads, so the array is parsed at
+    /// once.
+    fn handle_batch_payload(&mut self, payload: &str) -> Result<()> {
+        let values: Vec<JsonValue> =
+            serde_json::from_str(payload).chain_err(|| ErrorKind::DeserializeError)?;
+        let mut calls = Vec::new();
+        for value in values {
+            match self.parse_incoming(value)? {
+                Some((IncomingMessage::Response(output), extra_fields)) => {
+                    self.handle_response(output, extra_fields)?
+                }
+                Some((IncomingMessage::Request(Request::Single(call)), _)) => calls.push(call),
+                Some((IncomingMessage::Request(Request::Batch(_)), _)) => {
+                    bail!(ErrorKind::DeserializeError)
+                }
+                None => (),
+            }
+        }
+        if calls.is_empty() {
+            return Ok(());
+        }
+        self.server_handler
+            .process_request(Request::Batch(calls), self.server_response_tx.clone())
+    }
+
+    fn handle_response(&mut self, output: Output, extra_fields: JsonMap) -> Result<()> {
         let (id, result): (Id, Result<JsonValue>) = match output {
             Output::Success(RpcSuccess { result, id, .. }) => (id, Ok(result)),
             Output::Failure(RpcFailure { id, error, .. }) => {
                 (id, Err(ErrorKind::JsonRpcError(error).into()))
             }
         };
+        self.complete_call(id, result, extra_fields);
+        Ok(())
+    }
+
+    /// Delivers the result of a call to its caller, and to all calls coalesced with it.
+    fn complete_call(&mut self, id: Id, result: Result<JsonValue>, extra_fields: JsonMap) {
+        let call = self.call_info.remove(&id);
+        if let Some(completion_chan) = self.pending_detailed_requests.remove(&id) {
+            let response = result.map(|result| DetailedResponse {
+                result,
+                extra_fields,
+            });
+            self.send_rpc_response(&id, call, completion_chan, response);
+            return;
+        }
+
+        if let Some((key, waiters)) = self.coalesced_calls.remove(&id) {
+            self.in_flight_calls.remove(&key);
+            for completion_chan in waiters {
+                let result = duplicate_result(&result);
+                self.send_rpc_response(&id, call.clone(), completion_chan, result);
+            }
+        }
 
         match self.pending_client_requests.remove(&id) {
-            Some(completion_chan) => Self::send_rpc_response(&id, completion_chan, result),
-            None => trace!("Received response with an invalid id {:?}", id),
+            Some(completion_chan) => {
+                self.send_rpc_response(&id, call, completion_chan, result)
+            }
+            None => self
+                .dead_letters
+                .report(DropReason::UnknownId, id, None, result),
         };
-        Ok(())
+    }
+
+    /// Fails all calls waiting for a response with the error that stopped the client, so the
+    /// callers see why instead of just that the client shut down. An error of the transport is
+    /// shared with the calls, other errors are copied.
+    fn fail_pending_calls(&mut self, error: &Error) {
+        let ids = self
+            .pending_client_requests
+            .keys()
+            .chain(self.pending_detailed_requests.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        let shared = match *error.kind() {
+            ErrorKind::TransportError => error
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|error| error.downcast_ref::<TransportFailure>())
+                .cloned(),
+            _ => None,
+        };
+        let mut failure = shared.unwrap_or_else(|| {
+            let copy = ErrorCopy::new(error);
+            TransportFailure {
+                hint: RecoveryHint::Reconnect,
+                message: copy.to_string(),
+                error: Arc::new(Mutex::new(Box::new(copy))),
+            }
+        });
+        // the client is stopping, so the calls can at best be made again on a new client
+        if failure.hint == RecoveryHint::Retry {
+            failure.hint = RecoveryHint::Reconnect;
+        }
+        // no response was received for these calls, so there is nothing to report if the
+        // callers are gone
+        self.call_info.clear();
+        for id in ids {
+            self.complete_call(id, Err(failure.clone().into_error()), JsonMap::new());
+        }
+    }
+
+    fn has_pending_calls(&self) -> bool {
+        !self.pending_client_requests.is_empty() || !self.pending_detailed_requests.is_empty()
+    }
+
+    /// Fails all calls waiting for a response with `Cancelled`.
+    fn cancel_pending_calls(&mut self) {
+        let ids = self
+            .pending_client_requests
+            .keys()
+            .chain(self.pending_detailed_requests.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        self.call_info.clear();
+        for id in ids {
+            self.complete_call(id, Err(ErrorKind::Cancelled.into()), JsonMap::new());
+        }
+    }
+
+    /// Sends what is left to send and receives responses while shutting down. Returns true while
+    /// calls are still waiting for a response.
+    fn drain(&mut self) -> Result<bool> {
+        if let Some(payload) = self.pending_payload.take() {
+            self.send_payload(payload)?;
+        }
+        if self.pending_payload.is_none() {
+            self.send_batch()?;
+        }
+        self.poll_transport_tx()?;
+        self.poll_transport_rx()?;
+        Ok(self.has_pending_calls())
     }
 
     fn poll_outgoing_messages(&mut self) -> Result<()> {
@@ -424,24 +1407,59 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
     fn handle_client_payload(&mut self, message: OutgoingMessage) -> Result<()> {
         match message {
             OutgoingMessage::RpcCall(method, parameters, completion) => {
+                let coalescing_key = if self.coalesce_calls {
+                    let key = serde_json::to_string(&(&method, &parameters))
+                        .chain_err(|| ErrorKind::SerializeError)?;
+                    if let Some(id) = self.in_flight_calls.get(&key) {
+                        trace!("Coalescing call to {} with in-flight call {:?}", method, id);
+                        if let Some((_, waiters)) = self.coalesced_calls.get_mut(id) {
+                            waiters.push(completion);
+                        }
+                        return Ok(());
+                    }
+                    Some(key)
+                } else {
+                    None
+                };
                 let new_id = self.id_generator.next();
-                match serialize_method_request(new_id.clone(), method, &parameters) {
+                let call = CallInfo::new(&method);
+                match serialize_method_request(new_id.clone(), method, parameters) {
                     Ok(payload) => {
+                        if let Some(key) = coalescing_key {
+                            self.in_flight_calls.insert(key.clone(), new_id.clone());
+                            self.coalesced_calls
+                                .insert(new_id.clone(), (key, Vec::new()));
+                        }
+                        self.call_info.insert(new_id.clone(), call);
                         self.add_new_call(new_id, completion);
-                        self.send_payload(payload)?;
+                        self.queue_payload(payload)?;
+                    }
+                    Err(e) => {
+                        self.send_rpc_response(&new_id, None, completion, Err(e));
+                    }
+                };
+            }
+            OutgoingMessage::DetailedRpcCall(method, parameters, completion) => {
+                let new_id = self.id_generator.next();
+                let call = CallInfo::new(&method);
+                match serialize_method_request(new_id.clone(), method, parameters) {
+                    Ok(payload) => {
+                        self.call_info.insert(new_id.clone(), call);
+                        self.pending_detailed_requests.insert(new_id, completion);
+                        self.queue_payload(payload)?;
                     }
                     Err(e) => {
-                        Self::send_rpc_response(&new_id, completion, Err(e));
+                        self.send_rpc_response(&new_id, None, completion, Err(e));
                     }
                 };
             }
             OutgoingMessage::Notification(method, parameters, completion) => {
-                match serialize_notification_request(method, &parameters) {
+                match serialize_notification_request(method, parameters) {
                     Ok(payload) => {
                         if completion.send(Ok(())).is_err() {
                             trace!("future for notification dopped already");
                         }
-                        self.send_payload(payload)?;
+                        self.queue_payload(payload)?;
                     }
                     Err(e) => {
                         if completion.send(Err(e)).is_err() {
@@ -469,13 +1487,40 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
         Ok(())
     }
 
-    fn send_rpc_response<V>(id: &Id, chan: oneshot::Sender<Result<V>>, value: Result<V>) {
-        if chan.send(value).is_err() {
-            trace!("Future for RPC call {:?} dropped already", id);
+    /// Delivers a result to a caller, on the response executor if there is one. `call` is the
+    /// call a response was received for, and the response is reported as dropped if the caller is
+    /// gone. It is `None` for results made up by the client, like errors of calls that could not
+    /// be sent.
+    fn send_rpc_response<V: CallResult>(
+        &self,
+        id: &Id,
+        call: Option<CallInfo>,
+        chan: oneshot::Sender<Result<V>>,
+        value: Result<V>,
+    ) {
+        let executor = match self.response_executor {
+            Some(ResponseExecutor(ref executor)) => executor,
+            None => return deliver_response(id, call, chan, value, &self.dead_letters),
+        };
+        let id = id.clone();
+        let dead_letters = self.dead_letters.clone();
+        let delivery: ResponseDelivery = Box::new(future::lazy(move || {
+            deliver_response(&id, call, chan, value, &dead_letters);
+            Ok::<(), ()>(())
+        }));
+        if let Err(e) = executor.execute(delivery) {
+            warn!("Response executor refused a response, delivering it directly");
+            let _ = e.into_future().poll();
         }
     }
 
     fn handle_shutdown(&mut self) -> futures::Poll<(), Error> {
+        // calls still waiting in a batch were made before the shutdown, send them
+        if self.fatal_error.is_none() && self.pending_payload.is_none() {
+            if let Err(e) = self.send_batch() {
+                warn!("Failed to send the last batch of calls: {}", e.description());
+            }
+        }
         if let Err(e) = self.poll_transport_rx() {
             trace!(
                 "Failed to drain incoming messages from transport whilst shutting down: {}",
@@ -485,7 +1530,7 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
         match self
             .transport_tx
             .close()
-            .chain_err(|| ErrorKind::TransportError)
+            .map_err(TransportFailure::new::<T>)
         {
             Ok(Async::NotReady) => {
                 return Ok(Async::NotReady);
@@ -513,7 +1558,7 @@ impl<T: Transport, S: server::ServerHandler> Client<T, S> {
         if self.fatal_error.is_none() {
             self.transport_tx
                 .poll_complete()
-                .chain_err(|| ErrorKind::TransportError)?;
+                .map_err(TransportFailure::new::<T>)?;
         }
         Ok(())
     }
@@ -529,10 +1574,35 @@ impl<T: Transport, S: server::ServerHandler> Future for Client<T, S> {
             match self.handle_messages() {
                 Ok(()) => return Ok(Async::NotReady),
                 Err(Error(ErrorKind::Shutdown, _)) => self.shutting_down = true,
-                Err(e) => self.fatal_error = Some(e),
+                Err(e) => {
+                    self.fail_pending_calls(&e);
+                    self.fatal_error = Some(e);
+                }
+            }
+        }
+        if self.drain_on_shutdown && self.fatal_error.is_none() && self.has_pending_calls() {
+            match self.drain() {
+                Ok(true) => return Ok(Async::NotReady),
+                Ok(false) | Err(Error(ErrorKind::Shutdown, _)) => (),
+                Err(e) => {
+                    self.fail_pending_calls(&e);
+                    self.fatal_error = Some(e);
+                }
+            }
+        }
+        match self.handle_shutdown() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            result => {
+                self.cancel_pending_calls();
+                result
             }
         }
-        self.handle_shutdown()
+    }
+}
+
+impl<T: Transport, S: server::ServerHandler> Drop for Client<T, S> {
+    fn drop(&mut self) {
+        self.cancel_pending_calls();
     }
 }
 
@@ -543,23 +1613,144 @@ impl<T: Transport, S: server::ServerHandler> Future for Client<T, S> {
 pub enum OutgoingMessage {
     /// Invoke an RPC
     RpcCall(String, Option<Params>, oneshot::Sender<Result<JsonValue>>),
+    /// Invoke an RPC, keeping the non-standard fields of the response
+    DetailedRpcCall(
+        String,
+        Option<Params>,
+        oneshot::Sender<Result<DetailedResponse<JsonValue>>>,
+    ),
     /// Send a notification
     Notification(String, Option<Params>, oneshot::Sender<Result<()>>),
     /// Send a response response
     Response(Response),
 }
 
-/// Creates a JSON-RPC 2.0 request to the given method with the given parameters.
-fn serialize_method_request(
-    id: Id,
-    method: String,
-    params: &impl serde::Serialize,
-) -> Result<String> {
-    let serialized_params = serialize_parameters(params)?;
+/// The result of a call made with
+/// [`ClientHandle::call_method_detailed`](struct.ClientHandle.html#method.call_method_detailed).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetailedResponse<T> {
+    /// The result of the call.
+    pub result: T,
+    /// The top-level fields of the response object other than `jsonrpc`, `result`, `error` and
+    /// `id`.
+    pub extra_fields: JsonMap,
+}
+
+/// Removes and returns the top-level fields of a response object that are not defined by
+/// JSON-RPC 2.0, so that they don't make the response fail to deserialize.
+fn take_extra_fields(value: &mut JsonValue) -> JsonMap {
+    let mut extra_fields = JsonMap::new();
+    if let JsonValue::Object(ref mut object) = *value {
+        if object.contains_key("method") {
+            return extra_fields;
+        }
+        let extra_keys = object
+            .keys()
+            .filter(|key| !["jsonrpc", "result", "error", "id"].contains(&key.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in extra_keys {
+            if let Some(field) = object.remove(&key) {
+                extra_fields.insert(key, field);
+            }
+        }
+    }
+    extra_fields
+}
+
+/// Rewrites common deviations from the specification in a response object into their compliant
+/// equivalents. Requests from the server are left untouched.
+fn make_lenient(value: &mut JsonValue) {
+    if let JsonValue::Object(ref mut object) = *value {
+        if object.contains_key("method") {
+            return;
+        }
+        object
+            .entry("jsonrpc")
+            .or_insert_with(|| JsonValue::from("2.0"));
+        let has_result = object.contains_key("result");
+        let has_error = object.contains_key("error");
+        if has_result && has_error {
+            if object["error"].is_null() {
+                object.remove("error");
+            } else if object["result"].is_null() {
+                object.remove("result");
+            }
+        }
+    }
+}
+
+/// Returns the id of a message if it looks like a response, even if it is otherwise malformed.
+fn response_id(value: &JsonValue) -> Option<Id> {
+    let object = value.as_object()?;
+    if object.contains_key("method") {
+        return None;
+    }
+    object
+        .get("id")
+        .and_then(|id| serde_json::from_value(id.clone()).ok())
+}
+
+/// Returns true if the error is the server responding that the called method does not exist.
+fn is_method_not_found(error: &Error) -> bool {
+    match error.kind() {
+        ErrorKind::JsonRpcError(rpc_error) => {
+            rpc_error.code == jsonrpc_core::ErrorCode::MethodNotFound
+        }
+        _ => false,
+    }
+}
+
+/// Creates a copy of a call result, to hand out to every caller waiting for a coalesced call.
+fn duplicate_result(result: &Result<JsonValue>) -> Result<JsonValue> {
+    match result {
+        Ok(value) => Ok(value.clone()),
+        Err(error) => Err(duplicate_error(error)),
+    }
+}
+
+/// Creates a copy of an error with the same kind. A failure of the transport is shared, and
+/// other causes are copied with `ErrorCopy`.
+fn duplicate_error(error: &Error) -> Error {
+    let kind = match *error.kind() {
+        ErrorKind::Msg(ref msg) => ErrorKind::Msg(msg.clone()),
+        ErrorKind::TransportError => {
+            let failure = error
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|error| error.downcast_ref::<TransportFailure>());
+            if let Some(failure) = failure {
+                return failure.clone().into_error();
+            }
+            ErrorKind::TransportError
+        }
+        ErrorKind::SerializeError => ErrorKind::SerializeError,
+        ErrorKind::DeserializeError => ErrorKind::DeserializeError,
+        ErrorKind::ResponseError(msg) => ErrorKind::ResponseError(msg),
+        ErrorKind::JsonLimitExceeded(limit) => ErrorKind::JsonLimitExceeded(limit),
+        ErrorKind::InvalidVersion => ErrorKind::InvalidVersion,
+        ErrorKind::Shutdown => ErrorKind::Shutdown,
+        ErrorKind::Cancelled => ErrorKind::Cancelled,
+        ErrorKind::JsonRpcError(ref error) => ErrorKind::JsonRpcError(error.clone()),
+        ErrorKind::ValidationError(ref method, ref reason) => {
+            ErrorKind::ValidationError(method.clone(), reason.clone())
+        }
+        _ => ErrorKind::Msg(error.to_string()),
+    };
+    match error.cause() {
+        Some(cause) => Error::with_chain(ErrorCopy::new(cause), kind),
+        None => kind.into(),
+    }
+}
+
+/// Creates a JSON-RPC 2.0 request to the given method with the given, already serialized,
+/// parameters.
+fn serialize_method_request(id: Id, method: String, params: Option<Params>) -> Result<String> {
     let method_call = MethodCall {
         jsonrpc: Some(Version::V2),
         method,
-        params: serialized_params,
+        params,
         id,
     };
     serde_json::to_string(&method_call).chain_err(|| ErrorKind::SerializeError)
@@ -576,16 +1767,538 @@ pub fn serialize_parameters(params: &impl serde::Serialize) -> Result<Option<Par
     Ok(parameters)
 }
 
-/// Creates a JSON-RPC 2.0 notification request to the given method with the given parameters.
-fn serialize_notification_request(
-    method: String,
-    params: &impl serde::Serialize,
-) -> Result<String> {
-    let serialized_params = serialize_parameters(params)?;
+/// Creates a JSON-RPC 2.0 notification request to the given method with the given, already
+/// serialized, parameters.
+fn serialize_notification_request(method: String, params: Option<Params>) -> Result<String> {
     let notification = Notification {
         jsonrpc: Some(Version::V2),
         method,
-        params: serialized_params,
+        params,
     };
     serde_json::to_string(&notification).chain_err(|| ErrorKind::SerializeError)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{ExecuteError, ExecuteErrorKind};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc as std_mpsc;
+    use std::thread;
+    use test_transport::{self, TestError, TestServer};
+    use timer::ManualTimer;
+
+    #[test]
+    fn transport_failure_stops_client() {
+        let hints = [
+            (RecoveryHint::Retry, RecoveryHint::Reconnect),
+            (RecoveryHint::Reauthenticate, RecoveryHint::Reauthenticate),
+            (RecoveryHint::GiveUp, RecoveryHint::GiveUp),
+        ];
+        for &(transport_hint, hint) in &hints {
+            let (transport, server) = test_transport::pair();
+            let (client, handle) = transport.into_client();
+            let client = test_transport::spawn(client);
+            let call = handle.call_method::<JsonValue>("method", &());
+            let call = thread::spawn(move || call.wait());
+            server.request();
+            let transport_error = TestError {
+                message: "connection reset",
+                hint: transport_hint,
+            };
+            server.fail(transport_error.clone());
+
+            let error = call.join().unwrap().unwrap_err();
+            match *error.kind() {
+                ErrorKind::TransportError => (),
+                ref kind => panic!("unexpected error: {:?}", kind),
+            }
+            assert_eq!(error.recovery_hint(), hint);
+
+            let error = test_transport::client_result(&client).unwrap_err();
+            assert_eq!(error.recovery_hint(), hint);
+            let failure = error.with_transport_error(|error| error.downcast_ref().cloned());
+            assert_eq!(failure, Some(Some(transport_error)));
+        }
+    }
+
+    #[test]
+    fn transport_error_shared_with_pending_calls() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let client = test_transport::spawn(client);
+        let calls = (0..2)
+            .map(|i| {
+                let call = handle.call_method::<JsonValue>("method", &(i,));
+                thread::spawn(move || call.wait())
+            }).collect::<Vec<_>>();
+        server.request();
+        server.request();
+        let transport_error = TestError {
+            message: "connection refused",
+            hint: RecoveryHint::Reconnect,
+        };
+        server.fail(transport_error.clone());
+
+        let mut errors = calls
+            .into_iter()
+            .map(|call| call.join().unwrap().unwrap_err())
+            .collect::<Vec<_>>();
+        errors.push(test_transport::client_result(&client).unwrap_err());
+        for error in errors {
+            assert_eq!(error.to_string(), "Unable to send the JSON-RPC 2.0 request");
+            assert_eq!(error.iter().nth(1).unwrap().to_string(), "connection refused");
+            let failure = error.with_transport_error(|error| error.downcast_ref().cloned());
+            assert_eq!(failure, Some(Some(transport_error.clone())));
+        }
+        let error = Error::from(ErrorKind::Shutdown);
+        assert!(error.with_transport_error(|_| ()).is_none());
+    }
+
+    #[test]
+    fn results_delivered_on_executor() {
+        for &refuse in &[false, true] {
+            let executed = Arc::new(AtomicUsize::new(0));
+            let executor = ThreadExecutor {
+                executed: executed.clone(),
+                refuse,
+            };
+            let (transport, server) = test_transport::pair();
+            let (client, handle) = transport.into_client();
+            let _client = test_transport::spawn(client.response_executor(executor));
+            let call = handle.call_method::<JsonValue>("echo", &("a",));
+            let call = thread::spawn(move || call.wait());
+            server.echo();
+
+            assert_eq!(call.join().unwrap().unwrap(), json!(["a"]));
+            let expected = if refuse { 0 } else { 1 };
+            assert_eq!(executed.load(Ordering::SeqCst), expected);
+        }
+    }
+
+    #[test]
+    fn batch_responses() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let client = test_transport::spawn(client);
+        let call = |i: i32| {
+            handle
+                .call_method::<JsonValue>("method", &(i,))
+                .then(Ok::<_, ()>)
+        };
+        let calls = call(0).join(call(1));
+        let calls = thread::spawn(move || calls.wait().unwrap());
+        let requests = vec![server.request(), server.request()];
+        server.send(
+            &json!([
+                test_transport::response(&requests[1], json!(1)),
+                test_transport::response(&requests[0], json!(0)),
+            ]).to_string(),
+        );
+        let (first, second) = calls.join().unwrap();
+        assert_eq!(first.unwrap(), json!(0));
+        assert_eq!(second.unwrap(), json!(1));
+
+        // A response that belongs to no call fails the client with its own error, after the
+        // responses before it have been dispatched.
+        let calls = call(0).join(call(1));
+        let calls = thread::spawn(move || calls.wait().unwrap());
+        let requests = vec![server.request(), server.request()];
+        server.send(
+            &json!([
+                test_transport::response(&requests[0], json!(0)),
+                {"result": 1, "id": 1000},
+                test_transport::response(&requests[1], json!(1)),
+            ]).to_string(),
+        );
+        let (first, second) = calls.join().unwrap();
+        assert_eq!(first.unwrap(), json!(0));
+        assert!(second.is_err());
+        match test_transport::client_result(&client) {
+            Err(Error(ErrorKind::InvalidVersion, _)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn json_limits_fail_calls() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let client = client.json_limits(JsonLimits {
+            max_depth: Some(2),
+            ..JsonLimits::default()
+        });
+        let client = test_transport::spawn(client);
+
+        // Only the call answered by a payload exceeding a limit fails.
+        let call = handle.call_method::<JsonValue>("method", &());
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        server.respond(&request, json!([[1]]));
+        match call.join().unwrap() {
+            Err(Error(ErrorKind::JsonLimitExceeded("depth"), _)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        let call = handle.call_method::<JsonValue>("method", &());
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        server.respond(&request, json!([1]));
+        assert_eq!(call.join().unwrap().unwrap(), json!([1]));
+
+        // A payload answering no call fails the client.
+        server.send(r#"{"jsonrpc":"2.0","result":[[1]],"id":1000}"#);
+        match test_transport::client_result(&client) {
+            Err(Error(ErrorKind::JsonLimitExceeded("depth"), _)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn validators() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+        handle.set_validator("positive", |result: &JsonValue| match result.as_i64() {
+            Some(value) if value > 0 => Ok(()),
+            _ => Err("not positive".to_owned()),
+        });
+
+        let calls = handle
+            .call_method::<i64>("positive", &())
+            .join3(
+                handle.call_method::<i64>("positive", &()),
+                handle.call_method::<i64>("other", &()),
+            );
+        let calls = thread::spawn(move || calls.wait());
+        let requests = vec![server.request(), server.request(), server.request()];
+        server.respond(&requests[0], json!(1));
+        server.respond(&requests[1], json!(-1));
+        server.respond(&requests[2], json!(-1));
+        match calls.join().unwrap() {
+            Err(Error(ErrorKind::ValidationError(method, reason), _)) => {
+                assert_eq!(method, "positive");
+                assert_eq!(reason, "not positive");
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // The validator of the primary name checks results of the fallback names.
+        let call = handle.call_method_with_fallbacks::<i64>(&["positive", "legacy"], &());
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        server.respond_error(&request, -32601, "Method not found");
+        let request = server.request();
+        assert_eq!(request["method"], "legacy");
+        server.respond(&request, json!(-1));
+        match call.join().unwrap() {
+            Err(Error(ErrorKind::ValidationError(method, _), _)) => assert_eq!(method, "positive"),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn validators_check_batched_calls() {
+        let (handle, server, _timer, _client) = batching_client(2);
+        handle.set_validator("positive", |result: &JsonValue| match result.as_i64() {
+            Some(value) if value > 0 => Ok(()),
+            _ => Err("not positive".to_owned()),
+        });
+        let calls = handle
+            .call_method::<i64>("positive", &())
+            .then(Ok::<_, ()>)
+            .join(handle.call_method::<i64>("positive", &()).then(Ok::<_, ()>));
+        let calls = thread::spawn(move || calls.wait().unwrap());
+        let batch = server.request();
+        server.send(
+            &json!([
+                test_transport::response(&batch[0], json!(1)),
+                test_transport::response(&batch[1], json!(0)),
+            ]).to_string(),
+        );
+        let (first, second) = calls.join().unwrap();
+        assert_eq!(first.unwrap(), 1);
+        match second {
+            Err(Error(ErrorKind::ValidationError(..), _)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn coalesced_calls() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client.coalesce_calls(true));
+
+        let calls = handle.call_method::<JsonValue>("method", &(1,)).join3(
+            handle.call_method::<JsonValue>("method", &(1,)),
+            handle.call_method::<JsonValue>("method", &(2,)),
+        );
+        let calls = thread::spawn(move || calls.wait());
+        let requests = vec![server.request(), server.request()];
+        assert_eq!(requests[1]["params"], json!([2]));
+        server.respond(&requests[1], json!(2));
+        server.respond(&requests[0], json!(1));
+        assert_eq!(calls.join().unwrap().unwrap(), (json!(1), json!(1), json!(2)));
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+
+        // Once answered, the same call is sent again.
+        let call = handle.call_method::<JsonValue>("method", &(1,));
+        let call = thread::spawn(move || call.wait());
+        server.echo();
+        assert_eq!(call.join().unwrap().unwrap(), json!([1]));
+    }
+
+    #[test]
+    fn coalesced_calls_share_error_kinds() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let client = test_transport::spawn(client.coalesce_calls(true));
+
+        let call = |handle: &ClientHandle, method: &'static str| {
+            handle
+                .call_method::<JsonValue>(method, &())
+                .then(Ok::<_, ()>)
+        };
+        // The call of another method is sent once the coalesced calls have reached the client, and
+        // both are read before answering, so the client can't take the response first.
+        let calls = call(&handle, "method")
+            .join3(call(&handle, "method"), call(&handle, "other"));
+        let calls = thread::spawn(move || calls.wait().unwrap());
+        let request = server.request();
+        let other = server.request();
+        server.respond_error(&request, -32000, "failed");
+        server.respond(&other, json!(null));
+        let (first, second, _) = calls.join().unwrap();
+        for result in vec![first, second] {
+            match result {
+                Err(Error(ErrorKind::JsonRpcError(ref error), _)) => {
+                    assert_eq!(error.message, "failed")
+                }
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+
+        let calls = call(&handle, "method")
+            .join3(call(&handle, "method"), call(&handle, "other"));
+        let calls = thread::spawn(move || calls.wait().unwrap());
+        server.request();
+        server.request();
+        server.fail(TestError {
+            message: "connection reset",
+            hint: RecoveryHint::Retry,
+        });
+        let (first, second, other) = calls.join().unwrap();
+        for result in vec![first, second, other] {
+            let error = result.unwrap_err();
+            assert_eq!(error.recovery_hint(), RecoveryHint::Reconnect);
+            let message = error.with_transport_error(|error| error.to_string());
+            assert_eq!(message.unwrap(), "connection reset");
+        }
+        assert!(test_transport::client_result(&client).is_err());
+    }
+
+    #[test]
+    fn duplicated_errors_keep_their_kind() {
+        let errors = vec![
+            Error::from(ErrorKind::InvalidVersion),
+            ErrorKind::ValidationError("method".to_owned(), "reason".to_owned()).into(),
+            Error::with_chain(
+                serde_json::from_str::<JsonValue>("{").unwrap_err(),
+                ErrorKind::DeserializeError,
+            ),
+            "message".into(),
+        ];
+        for error in errors {
+            let result = Err(error);
+            let copy = duplicate_result(&result).unwrap_err();
+            let error = result.unwrap_err();
+            assert_eq!(format!("{:?}", copy.kind()), format!("{:?}", error.kind()));
+            assert_eq!(copy.to_string(), error.to_string());
+            assert_eq!(
+                copy.cause().map(ToString::to_string),
+                error.cause().map(ToString::to_string)
+            );
+        }
+    }
+
+    #[test]
+    fn batch_sent_when_window_passes() {
+        let (handle, server, timer, _client) = batching_client(10);
+        let calls = handle
+            .call_method::<JsonValue>("a", &(0,))
+            .join(handle.call_method::<JsonValue>("b", &(1,)));
+        let calls = thread::spawn(move || calls.wait());
+        wait_for_window(&timer);
+        assert_eq!(server.try_payload(Duration::from_millis(50)), None);
+
+        timer.advance(Duration::from_secs(1));
+        let batch = server.request();
+        let batch = batch.as_array().unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!((&batch[0]["method"], &batch[1]["method"]), (&json!("a"), &json!("b")));
+        server.send(
+            &json!([
+                test_transport::response(&batch[1], json!(1)),
+                test_transport::response(&batch[0], json!(0)),
+            ]).to_string(),
+        );
+        assert_eq!(calls.join().unwrap().unwrap(), (json!(0), json!(1)));
+    }
+
+    #[test]
+    fn batch_sent_when_full() {
+        let (handle, server, timer, _client) = batching_client(2);
+        let calls = handle
+            .call_method::<JsonValue>("a", &(0,))
+            .join(handle.call_method::<JsonValue>("b", &(1,)));
+        let _calls = thread::spawn(move || calls.wait());
+
+        assert_eq!(server.request().as_array().unwrap().len(), 2);
+        // The window of the batch sent early does not carry over to the next call.
+        let call = handle.call_method::<JsonValue>("c", &(2,));
+        let _call = thread::spawn(move || call.wait());
+        wait_for_window(&timer);
+        assert_eq!(server.try_payload(Duration::from_millis(50)), None);
+        timer.advance(Duration::from_secs(1));
+        assert_eq!(server.request()["method"], "c");
+    }
+
+    #[test]
+    fn single_call_sent_unwrapped() {
+        let (handle, server, timer, _client) = batching_client(10);
+        let call = handle.call_method::<JsonValue>("a", &(0,));
+        let call = thread::spawn(move || call.wait());
+        wait_for_window(&timer);
+
+        timer.advance(Duration::from_secs(1));
+        let request = server.request();
+        assert_eq!(request["method"], "a");
+        server.respond(&request, json!(0));
+        assert_eq!(call.join().unwrap().unwrap(), json!(0));
+    }
+
+    #[test]
+    fn notifications_batched_in_order() {
+        let (handle, server, _timer, _client) = batching_client(3);
+        let calls = handle.call_method::<JsonValue>("a", &(0,)).join3(
+            handle.send_notification("n".to_owned(), &(1,)),
+            handle.call_method::<JsonValue>("b", &(2,)),
+        );
+        let _calls = thread::spawn(move || calls.wait());
+
+        let batch = server.request();
+        let methods = batch
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| (message["method"].clone(), message.get("id").is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            vec![(json!("a"), true), (json!("n"), false), (json!("b"), true)]
+        );
+    }
+
+    #[test]
+    fn batch_sent_on_shutdown() {
+        let (handle, server, timer, client) = batching_client(10);
+        let call = handle.call_method::<JsonValue>("a", &(0,));
+        let call = thread::spawn(move || call.wait());
+        wait_for_window(&timer);
+
+        drop(handle);
+        assert_eq!(server.request()["method"], "a");
+        assert!(call.join().unwrap().is_err());
+        test_transport::client_result(&client).unwrap();
+    }
+
+    #[test]
+    fn dropped_client_cancels_calls() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let driver = thread::spawn(move || {
+            let _ = client.select2(stop_rx).wait();
+        });
+        let call = handle.call_method::<JsonValue>("a", &(0,));
+        let call = thread::spawn(move || call.wait());
+        server.request();
+
+        stop_tx.send(()).unwrap();
+        driver.join().unwrap();
+        match call.join().unwrap() {
+            Err(Error(ErrorKind::Cancelled, _)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn drain_on_shutdown() {
+        for &drain in &[false, true] {
+            let (transport, server) = test_transport::pair();
+            let (client, handle) = transport.into_client();
+            let client = test_transport::spawn(client.drain_on_shutdown(drain));
+            let call = handle.call_method::<JsonValue>("a", &(0,));
+            let call = thread::spawn(move || call.wait());
+            let request = server.request();
+
+            drop(handle);
+            if drain {
+                // Gives the client time to see that all handles are gone.
+                thread::sleep(Duration::from_millis(50));
+                server.respond(&request, json!(1));
+                assert_eq!(call.join().unwrap().unwrap(), json!(1));
+            } else {
+                match call.join().unwrap() {
+                    Err(Error(ErrorKind::Cancelled, _)) => (),
+                    result => panic!("Unexpected result: {:?}", result),
+                }
+            }
+            test_transport::client_result(&client).unwrap();
+        }
+    }
+
+    /// Creates a client batching the calls made within a second of each other, or until
+    /// `max_calls` are waiting, measuring time with the returned timer.
+    fn batching_client(
+        max_calls: usize,
+    ) -> (
+        ClientHandle,
+        TestServer,
+        ManualTimer,
+        std_mpsc::Receiver<Result<()>>,
+    ) {
+        let timer = ManualTimer::default();
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let client = client.batch_calls(Duration::from_secs(1), max_calls, timer.clone());
+        (handle, server, timer, test_transport::spawn(client))
+    }
+
+    /// Waits until the client has received a call and started the window of a batch.
+    fn wait_for_window(timer: &ManualTimer) {
+        while timer.pending() == 0 {
+            thread::yield_now();
+        }
+    }
+
+    /// Runs every future on a thread of its own and counts them, or refuses them all.
+    struct ThreadExecutor {
+        executed: Arc<AtomicUsize>,
+        refuse: bool,
+    }
+
+    impl Executor<ResponseDelivery> for ThreadExecutor {
+        fn execute(
+            &self,
+            future: ResponseDelivery,
+        ) -> ::std::result::Result<(), ExecuteError<ResponseDelivery>> {
+            if self.refuse {
+                return Err(ExecuteError::new(ExecuteErrorKind::NoCapacity, future));
+            }
+            self.executed.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || future.wait());
+            Ok(())
+        }
+    }
+}
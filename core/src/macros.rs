@@ -8,15 +8,34 @@
 
 /// The main macro of this crate. Generates JSON-RPC 2.0 client structs with automatic serialization
 /// and deserialization. Method calls get correct types automatically.
+///
+/// Methods declared with a return type, `-> Future<T>`, resolve to the result deserialized into
+/// `T`. Methods declared without a return type resolve to `()` whatever the result returned by the
+/// server is, for methods where the result is null or not interesting.
+///
+/// Methods can have type parameters, to let the caller decide what type to deserialize the
+/// result into. Useful when the shape of the result depends on the parameters, like the
+/// verbosity parameter of `getblock` in Bitcoin Core:
+///
+/// ```rust,ignore
+/// pub fn getblock<T>(&mut self, hash: String, verbosity: u8) -> Future<T>;
+/// ```
+///
+/// The result can also be deserialized with a custom function, by placing a
+/// `#[rpc(with = path::to::function)]` attribute directly after the documentation of the method.
+/// The function has the same signature as functions used with serde's `deserialize_with`
+/// attribute, for example `fn<'de, D: Deserializer<'de>>(D) -> Result<T, D::Error>`. Note that
+/// the path is not quoted.
+///
+/// Alternative names for a method, tried in order if the server does not know the method by its
+/// declared name, are given with `#[rpc(fallback = "legacy_name", "older_name")]` in the same
+/// position. See
+/// [`ClientHandle::call_method_with_fallbacks`](struct.ClientHandle.html#method.call_method_with_fallbacks).
 #[macro_export]
 macro_rules! jsonrpc_client {
     (
         $(#[$struct_attr:meta])*
-        pub struct $struct_name:ident {$(
-            $(#[$attr:meta])*
-            pub fn $method:ident(&mut $selff:ident $(, $arg_name:ident: $arg_ty:ty)*)
-                -> Future<$return_ty:ty>;
-        )*}
+        pub struct $struct_name:ident {$($methods:tt)*}
     ) => (
         $(#[$struct_attr])*
         pub struct $struct_name {
@@ -29,23 +48,125 @@ macro_rules! jsonrpc_client {
                 $struct_name { client }
             }
 
-            $(
-                $(#[$attr])*
-                pub fn $method(&mut $selff $(, $arg_name: $arg_ty)*)
-                    -> impl $crate::Future<Item = $return_ty, Error = $crate::Error> + 'static
-                {
-                    let method = String::from(stringify!($method));
-                    let raw_params = expand_params!($($arg_name,)*);
-                    let params = $crate::serialize_parameters(&raw_params);
-                    let (tx, rx) = $crate::oneshot::channel();
-                    let client_call = params.map(|p| $crate::OutgoingMessage::RpcCall(method, p, tx));
-                    $selff.client.send_client_call(client_call, rx)
-                }
-            )*
+            jsonrpc_client_methods!($($methods)*);
         }
     )
 }
 
+/// Generates the methods of a struct generated by `jsonrpc_client`, one declaration at a time.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! jsonrpc_client_methods {
+    () => ();
+    (
+        $(#[doc = $doc:expr])*
+        #[rpc(with = $with:path)]
+        $(#[$attr:meta])*
+        pub fn $method:ident(&mut $selff:ident $(, $arg_name:ident: $arg_ty:ty)*)
+            -> Future<$return_ty:ty>;
+        $($rest:tt)*
+    ) => (
+        $(#[doc = $doc])*
+        $(#[$attr])*
+        pub fn $method(&mut $selff $(, $arg_name: $arg_ty)*)
+            -> impl $crate::Future<Item = $return_ty, Error = $crate::Error> + 'static
+        {
+            let method = String::from(stringify!($method));
+            let raw_params = expand_params!($($arg_name,)*);
+            let params = $crate::serialize_parameters(&raw_params);
+            let (tx, rx) = $crate::oneshot::channel();
+            let client_call = params.map(|p| $crate::OutgoingMessage::RpcCall(method, p, tx));
+            $selff.client.send_client_call_with(client_call, rx, $with)
+        }
+
+        jsonrpc_client_methods!($($rest)*);
+    );
+    (
+        $(#[doc = $doc:expr])*
+        #[rpc(fallback = $($fallback:expr),+)]
+        $(#[$attr:meta])*
+        pub fn $method:ident(&mut $selff:ident $(, $arg_name:ident: $arg_ty:ty)*)
+            -> Future<$return_ty:ty>;
+        $($rest:tt)*
+    ) => (
+        $(#[doc = $doc])*
+        $(#[$attr])*
+        pub fn $method(&mut $selff $(, $arg_name: $arg_ty)*)
+            -> impl $crate::Future<Item = $return_ty, Error = $crate::Error> + 'static
+        {
+            let raw_params = expand_params!($($arg_name,)*);
+            $selff.client.call_method_with_fallbacks(
+                &[stringify!($method), $($fallback),+],
+                &raw_params,
+            )
+        }
+
+        jsonrpc_client_methods!($($rest)*);
+    );
+    (
+        $(#[$attr:meta])*
+        pub fn $method:ident(&mut $selff:ident $(, $arg_name:ident: $arg_ty:ty)*)
+            -> Future<$return_ty:ty>;
+        $($rest:tt)*
+    ) => (
+        $(#[$attr])*
+        pub fn $method(&mut $selff $(, $arg_name: $arg_ty)*)
+            -> impl $crate::Future<Item = $return_ty, Error = $crate::Error> + 'static
+        {
+            let method = String::from(stringify!($method));
+            let raw_params = expand_params!($($arg_name,)*);
+            let params = $crate::serialize_parameters(&raw_params);
+            let (tx, rx) = $crate::oneshot::channel();
+            let client_call = params.map(|p| $crate::OutgoingMessage::RpcCall(method, p, tx));
+            $selff.client.send_client_call(client_call, rx)
+        }
+
+        jsonrpc_client_methods!($($rest)*);
+    );
+    (
+        $(#[$attr:meta])*
+        pub fn $method:ident<$($type_param:ident),+>(
+            &mut $selff:ident $(, $arg_name:ident: $arg_ty:ty)*
+        ) -> Future<$return_ty:ty>;
+        $($rest:tt)*
+    ) => (
+        $(#[$attr])*
+        pub fn $method<$($type_param),+>(&mut $selff $(, $arg_name: $arg_ty)*)
+            -> impl $crate::Future<Item = $return_ty, Error = $crate::Error> + 'static
+        where
+            $($type_param: $crate::DeserializeOwned + Send + 'static),+
+        {
+            let method = String::from(stringify!($method));
+            let raw_params = expand_params!($($arg_name,)*);
+            let params = $crate::serialize_parameters(&raw_params);
+            let (tx, rx) = $crate::oneshot::channel();
+            let client_call = params.map(|p| $crate::OutgoingMessage::RpcCall(method, p, tx));
+            $selff.client.send_client_call(client_call, rx)
+        }
+
+        jsonrpc_client_methods!($($rest)*);
+    );
+    (
+        $(#[$attr:meta])*
+        pub fn $method:ident(&mut $selff:ident $(, $arg_name:ident: $arg_ty:ty)*);
+        $($rest:tt)*
+    ) => (
+        $(#[$attr])*
+        pub fn $method(&mut $selff $(, $arg_name: $arg_ty)*)
+            -> impl $crate::Future<Item = (), Error = $crate::Error> + 'static
+        {
+            let method = String::from(stringify!($method));
+            let raw_params = expand_params!($($arg_name,)*);
+            let params = $crate::serialize_parameters(&raw_params);
+            let (tx, rx) = $crate::oneshot::channel();
+            let client_call = params.map(|p| $crate::OutgoingMessage::RpcCall(method, p, tx));
+            $selff.client.send_client_call_ignoring_result(client_call, rx)
+        }
+
+        jsonrpc_client_methods!($($rest)*);
+    );
+}
+
 
 /// Expands a variable list of parameters into its serializable form. Is needed to make the params
 /// of a nullary method equal to `[]` instead of `()` and thus make sure it serializes to `[]`
@@ -56,3 +177,153 @@ macro_rules! expand_params {
     () => ([] as [(); 0]);
     ($($arg_name:ident,)+) => (($($arg_name,)+))
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use serde::{Deserialize, Deserializer};
+    use serde_json::Value as JsonValue;
+    use std::thread;
+    use test_transport;
+    use Transport;
+
+    jsonrpc_client!(pub struct TestClient {
+        /// Returns the number of blocks, known as `blocks` by older servers.
+        #[rpc(fallback = "blocks")]
+        pub fn block_count(&mut self, verbose: bool) -> Future<u64>;
+
+        /// Returns the gas price, sent as a string by the server.
+        #[rpc(with = from_string)]
+        pub fn gas_price(&mut self) -> Future<u64>;
+
+        /// Returns the block at `height`, in the shape the caller asks for.
+        pub fn block<T>(&mut self, height: u64) -> Future<T>;
+
+        /// Stops the server.
+        pub fn stop(&mut self, delay: u64);
+    });
+
+    fn from_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(::serde::de::Error::custom)
+    }
+
+    #[test]
+    fn fallback_names() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+        let mut rpc = TestClient::new(handle);
+
+        let call = rpc.block_count(true);
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "block_count");
+        assert_eq!(request["params"], json!([true]));
+        server.respond_error(&request, -32601, "Method not found");
+        let request = server.request();
+        assert_eq!(request["method"], "blocks");
+        assert_eq!(request["params"], json!([true]));
+        server.respond(&request, json!(7));
+        assert_eq!(call.join().unwrap().unwrap(), 7);
+
+        // the name that worked is tried first from now on
+        let call = rpc.block_count(false);
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "blocks");
+        server.respond(&request, json!(8));
+        assert_eq!(call.join().unwrap().unwrap(), 8);
+    }
+
+    #[test]
+    fn custom_deserialization() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+        let mut rpc = TestClient::new(handle);
+
+        let call = rpc.gas_price();
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "gas_price");
+        assert_eq!(request["params"], json!([]));
+        server.respond(&request, json!("436"));
+        assert_eq!(call.join().unwrap().unwrap(), 436);
+
+        let call = rpc.gas_price();
+        let call = thread::spawn(move || call.wait());
+        server.respond(&server.request(), json!(436));
+        match *call.join().unwrap().unwrap_err().kind() {
+            ::ErrorKind::DeserializeError => (),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn generic_methods() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+        let mut rpc = TestClient::new(handle);
+
+        let call = rpc.block::<(u64, String)>(7);
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "block");
+        assert_eq!(request["params"], json!([7]));
+        server.respond(&request, json!([7, "0xab"]));
+        assert_eq!(call.join().unwrap().unwrap(), (7, "0xab".to_owned()));
+
+        let call = rpc.block::<JsonValue>(8);
+        let call = thread::spawn(move || call.wait());
+        server.respond(&server.request(), json!({"height": 8}));
+        assert_eq!(call.join().unwrap().unwrap(), json!({"height": 8}));
+    }
+
+    #[test]
+    fn methods_without_result() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+        let mut rpc = TestClient::new(handle);
+
+        let call = rpc.stop(5);
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "stop");
+        assert_eq!(request["params"], json!([5]));
+        server.respond(&request, JsonValue::Null);
+        let () = call.join().unwrap().unwrap();
+
+        let call = rpc.stop(5);
+        let call = thread::spawn(move || call.wait());
+        server.respond_error(&server.request(), -32000, "Not allowed");
+        match *call.join().unwrap().unwrap_err().kind() {
+            ::ErrorKind::JsonRpcError(ref error) => assert_eq!(error.message, "Not allowed"),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn fallbacks_stop_at_other_errors() {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        let _client = test_transport::spawn(client);
+
+        let call = handle.call_method_with_fallbacks::<u64>(&["a", "b", "c"], &());
+        let call = thread::spawn(move || call.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "a");
+        server.respond_error(&request, -32601, "Method not found");
+        let request = server.request();
+        assert_eq!(request["method"], "b");
+        server.respond_error(&request, -32000, "Server error");
+        let error = call.join().unwrap().unwrap_err();
+        match *error.kind() {
+            ::ErrorKind::JsonRpcError(ref error) => assert_eq!(error.message, "Server error"),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+}
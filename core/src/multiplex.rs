@@ -0,0 +1,680 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{DuplexTransport, Error, ErrorKind, RecoveryHint, Result, Transport, TransportFailure};
+use futures::sync::mpsc;
+use futures::{stream, Async, AsyncSink, Future, Poll, Sink, Stream};
+use serde_json::{self, Value as JsonValue};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// The two halves of a channel, handed from its `MultiplexedTransport` to the `Multiplexer`.
+#[derive(Debug)]
+struct Registration {
+    outgoing: mpsc::Receiver<String>,
+    incoming: mpsc::UnboundedSender<String>,
+}
+
+/// The error that stopped a `Multiplexer`, shared with its transports so that their clients fail
+/// with it, and its recovery hint, instead of just `Shutdown`.
+#[derive(Debug, Clone, Default)]
+struct SharedFailure(Arc<Mutex<Option<TransportFailure>>>);
+
+impl SharedFailure {
+    /// Records `error` of the connection, and returns it as the error of the `Multiplexer`.
+    fn record<T: Transport>(&self, error: T::Error) -> Error {
+        let error = TransportFailure::new::<T>(error);
+        *self.0.lock().unwrap() = error
+            .1
+            .next_error
+            .as_ref()
+            .and_then(|error| error.downcast_ref::<TransportFailure>())
+            .cloned();
+        error
+    }
+
+    /// Returns the recorded error, if the connection failed.
+    fn error(&self) -> Option<Error> {
+        self.0
+            .lock()
+            .unwrap()
+            .clone()
+            .map(TransportFailure::into_error)
+    }
+}
+
+/// A request sent through the connection, waiting for its response.
+struct PendingRequest {
+    channel: usize,
+    id: JsonValue,
+    subscribe: bool,
+}
+
+/// Shares one connection between many clients. Every client gets a
+/// [`MultiplexedTransport`](struct.MultiplexedTransport.html) from the
+/// [`MultiplexerHandle`](struct.MultiplexerHandle.html), and has its own id space, calls and
+/// subscriptions, as if it had a connection of its own.
+///
+/// The ids of outgoing requests are replaced with ids unique on the connection, and changed back
+/// in the responses before they are delivered to the client that sent the request. Notifications
+/// from the server belonging to a subscription are delivered to the client that created the
+/// subscription, other notifications to all clients, and requests from the server to the oldest
+/// client. A subscription is recognized by the response to a method named `subscribe`, or ending
+/// in `_subscribe` or `.subscribe` like `eth_subscribe`, and ended by the matching `unsubscribe`
+/// method.
+///
+/// Outgoing messages are taken from the clients in turn, one at a time, so a client sending many
+/// messages can't keep the others from sending. A client is removed from the connection when it
+/// is dropped, without affecting the others. The `Multiplexer` future must be polled for any
+/// messages to be sent or received. It resolves when all handles and clients are dropped, or with
+/// an error when the connection fails.
+pub struct Multiplexer<T: Transport> {
+    transport_tx: T::Sink,
+    transport_rx: T::Stream,
+    registrations: mpsc::UnboundedReceiver<Registration>,
+    registrations_closed: bool,
+    channels: BTreeMap<usize, Registration>,
+    next_channel: usize,
+    // the channel to take the first outgoing message from in the next round
+    turn: usize,
+    buffered: Option<String>,
+    next_id: u64,
+    pending: HashMap<u64, PendingRequest>,
+    // the channel owning each subscription, keyed by the subscription id as JSON
+    subscriptions: HashMap<String, usize>,
+    failure: SharedFailure,
+}
+
+/// Creates new channels on a `Multiplexer`.
+#[derive(Debug, Clone)]
+pub struct MultiplexerHandle {
+    registration_tx: mpsc::UnboundedSender<Registration>,
+    failure: SharedFailure,
+}
+
+/// A transport sending and receiving messages through a channel of a `Multiplexer`.
+#[derive(Debug)]
+pub struct MultiplexedTransport {
+    outgoing: mpsc::Sender<String>,
+    incoming: mpsc::UnboundedReceiver<String>,
+    failure: SharedFailure,
+}
+
+impl<T: Transport> Multiplexer<T> {
+    /// Creates a new `Multiplexer` sharing the connection of `transport`.
+    pub fn new(transport: T) -> (Self, MultiplexerHandle) {
+        let (transport_tx, transport_rx) = transport.io_pair();
+        let (registration_tx, registrations) = mpsc::unbounded();
+        let failure = SharedFailure::default();
+        (
+            Multiplexer {
+                transport_tx,
+                transport_rx,
+                registrations,
+                registrations_closed: false,
+                channels: BTreeMap::new(),
+                next_channel: 0,
+                turn: 0,
+                buffered: None,
+                next_id: 1,
+                pending: HashMap::new(),
+                subscriptions: HashMap::new(),
+                failure: failure.clone(),
+            },
+            MultiplexerHandle {
+                registration_tx,
+                failure,
+            },
+        )
+    }
+
+    fn poll_registrations(&mut self) {
+        while !self.registrations_closed {
+            match self.registrations.poll() {
+                Ok(Async::Ready(Some(registration))) => {
+                    trace!("Opening multiplexed channel {}", self.next_channel);
+                    self.channels.insert(self.next_channel, registration);
+                    self.next_channel += 1;
+                }
+                Ok(Async::Ready(None)) | Err(()) => self.registrations_closed = true,
+                Ok(Async::NotReady) => break,
+            }
+        }
+    }
+
+    /// Forwards outgoing messages to the connection until it, or all channels, are not ready.
+    fn poll_outgoing(&mut self) -> Result<()> {
+        loop {
+            if let Some(message) = self.buffered.take() {
+                match self.transport_tx.start_send(message) {
+                    Ok(AsyncSink::Ready) => (),
+                    Ok(AsyncSink::NotReady(message)) => {
+                        self.buffered = Some(message);
+                        break;
+                    }
+                    Err(e) => return Err(self.failure.record::<T>(e)),
+                }
+            }
+            match self.next_outgoing() {
+                Some(message) => self.buffered = Some(message),
+                None => break,
+            }
+        }
+        match self.transport_tx.poll_complete() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(self.failure.record::<T>(e)),
+        }
+    }
+
+    /// Takes the next outgoing message from the channels, in turn, with its ids rewritten.
+    /// Channels whose clients are gone are removed.
+    fn next_outgoing(&mut self) -> Option<String> {
+        let order: Vec<usize> = self
+            .channels
+            .range(self.turn..)
+            .chain(self.channels.range(..self.turn))
+            .map(|(&channel, _)| channel)
+            .collect();
+        for channel in order {
+            let polled = self
+                .channels
+                .get_mut(&channel)
+                .map(|registration| registration.outgoing.poll());
+            match polled {
+                Some(Ok(Async::Ready(Some(message)))) => {
+                    self.turn = channel + 1;
+                    if let Some(message) = self.rewrite_outgoing(channel, message) {
+                        return Some(message);
+                    }
+                }
+                Some(Ok(Async::Ready(None))) | Some(Err(())) => self.close_channel(channel),
+                Some(Ok(Async::NotReady)) | None => (),
+            }
+        }
+        None
+    }
+
+    /// Replaces the ids of the requests in an outgoing message with ids unique on the connection.
+    fn rewrite_outgoing(&mut self, channel: usize, message: String) -> Option<String> {
+        let mut value: JsonValue = match serde_json::from_str(&message) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("Dropping invalid message from channel {}: {}", channel, e);
+                return None;
+            }
+        };
+        match value {
+            JsonValue::Array(ref mut calls) => {
+                for call in calls {
+                    self.rewrite_request(channel, call);
+                }
+            }
+            ref mut call => self.rewrite_request(channel, call),
+        }
+        Some(value.to_string())
+    }
+
+    fn rewrite_request(&mut self, channel: usize, call: &mut JsonValue) {
+        let object = match call.as_object_mut() {
+            Some(object) => object,
+            None => return,
+        };
+        // Responses to requests from the server are passed through untouched.
+        let method = match object.get("method").and_then(JsonValue::as_str) {
+            Some(method) => method.to_owned(),
+            None => return,
+        };
+        if is_method(&method, "unsubscribe") {
+            let subscription = object
+                .get("params")
+                .and_then(|params| params.get(0))
+                .map(JsonValue::to_string);
+            if let Some(subscription) = subscription {
+                self.subscriptions.remove(&subscription);
+            }
+        }
+        if let Some(id) = object.remove("id") {
+            let mux_id = self.next_id;
+            self.next_id += 1;
+            self.pending.insert(
+                mux_id,
+                PendingRequest {
+                    channel,
+                    id,
+                    subscribe: is_method(&method, "subscribe"),
+                },
+            );
+            object.insert("id".to_owned(), JsonValue::from(mux_id));
+        }
+    }
+
+    /// Delivers incoming messages to their channels until the connection has no more messages
+    /// ready. Returns true if the connection has been closed by the server.
+    fn poll_incoming(&mut self) -> Result<bool> {
+        loop {
+            match self.transport_rx.poll() {
+                Ok(Async::Ready(Some(message))) => self.route_incoming(message),
+                Ok(Async::Ready(None)) => return Ok(true),
+                Ok(Async::NotReady) => return Ok(false),
+                Err(e) => return Err(self.failure.record::<T>(e)),
+            }
+        }
+    }
+
+    fn route_incoming(&mut self, message: String) {
+        let value: JsonValue = match serde_json::from_str(&message) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("Dropping invalid message from the server: {}", e);
+                return;
+            }
+        };
+        match value {
+            // The elements of a batch are delivered one by one, since they can belong to
+            // different channels.
+            JsonValue::Array(messages) => {
+                for message in messages {
+                    self.route_message(message);
+                }
+            }
+            message => self.route_message(message),
+        }
+    }
+
+    fn route_message(&mut self, mut message: JsonValue) {
+        let is_request = message.get("method").is_some();
+        let has_id = message.get("id").map_or(false, |id| !id.is_null());
+        if is_request && has_id {
+            match self.channels.keys().next().cloned() {
+                Some(channel) => self.deliver(channel, &message),
+                None => warn!("Dropping request from the server, no channel is open"),
+            }
+        } else if is_request {
+            let owner = message
+                .get("params")
+                .and_then(|params| params.get("subscription"))
+                .and_then(|subscription| self.subscriptions.get(&subscription.to_string()))
+                .cloned();
+            match owner {
+                Some(channel) => self.deliver(channel, &message),
+                None => {
+                    let channels: Vec<usize> = self.channels.keys().cloned().collect();
+                    for channel in channels {
+                        self.deliver(channel, &message);
+                    }
+                }
+            }
+        } else {
+            let request = message
+                .get("id")
+                .and_then(JsonValue::as_u64)
+                .and_then(|mux_id| self.pending.remove(&mux_id));
+            let request = match request {
+                Some(request) => request,
+                None => {
+                    warn!("Dropping response without a matching request: {}", message);
+                    return;
+                }
+            };
+            if request.subscribe {
+                if let Some(result) = message.get("result") {
+                    self.subscriptions
+                        .insert(result.to_string(), request.channel);
+                }
+            }
+            if let Some(object) = message.as_object_mut() {
+                object.insert("id".to_owned(), request.id);
+            }
+            self.deliver(request.channel, &message);
+        }
+    }
+
+    fn deliver(&mut self, channel: usize, message: &JsonValue) {
+        let delivered = match self.channels.get(&channel) {
+            Some(registration) => registration
+                .incoming
+                .unbounded_send(message.to_string())
+                .is_ok(),
+            None => {
+                debug!("Dropping message to closed channel {}", channel);
+                return;
+            }
+        };
+        if !delivered {
+            self.close_channel(channel);
+        }
+    }
+
+    /// Removes a channel and everything waiting for it. The connection stays open for the others.
+    fn close_channel(&mut self, channel: usize) {
+        trace!("Closing multiplexed channel {}", channel);
+        self.channels.remove(&channel);
+        self.pending.retain(|_, request| request.channel != channel);
+        self.subscriptions.retain(|_, &mut owner| owner != channel);
+    }
+}
+
+/// Returns true if `method` is `name`, or ends with `name` after a `_` or `.` namespace separator.
+fn is_method(method: &str, name: &str) -> bool {
+    method.rsplit(|c| c == '_' || c == '.').next() == Some(name)
+}
+
+impl<T: Transport> Future for Multiplexer<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        self.poll_registrations();
+        if self.poll_incoming()? {
+            debug!("Connection closed by the server, closing all multiplexed channels");
+            return Ok(Async::Ready(()));
+        }
+        self.poll_outgoing()?;
+        if self.registrations_closed && self.channels.is_empty() && self.buffered.is_none() {
+            return match self.transport_tx.close() {
+                Ok(closed) => Ok(closed),
+                Err(e) => Err(self.failure.record::<T>(e)),
+            };
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl MultiplexerHandle {
+    /// Opens a new channel on the connection, returning a transport for a client. Fails with
+    /// `Shutdown` if the `Multiplexer` is gone.
+    pub fn transport(&self) -> Result<MultiplexedTransport> {
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(0);
+        let (incoming_tx, incoming_rx) = mpsc::unbounded();
+        let registration = Registration {
+            outgoing: outgoing_rx,
+            incoming: incoming_tx,
+        };
+        self.registration_tx
+            .unbounded_send(registration)
+            .map_err(|_| Error::from(ErrorKind::Shutdown))?;
+        Ok(MultiplexedTransport {
+            outgoing: outgoing_tx,
+            incoming: incoming_rx,
+            failure: self.failure.clone(),
+        })
+    }
+}
+
+impl Transport for MultiplexedTransport {
+    type Error = Error;
+    type Sink = Box<dyn Sink<SinkItem = String, SinkError = Error> + Send>;
+    type Stream = Box<dyn Stream<Item = String, Error = Error> + Send>;
+
+    fn recovery_hint(error: &Error) -> RecoveryHint {
+        error.recovery_hint()
+    }
+
+    /// The sink and stream fail with the error of the connection once the `Multiplexer` has
+    /// failed, and with `Shutdown` if it is gone otherwise. The stream ends without an error when
+    /// the server closed the connection.
+    fn io_pair(self) -> (Self::Sink, Self::Stream) {
+        let failure = self.failure;
+        let sink_failure = failure.clone();
+        let sink = self.outgoing.sink_map_err(move |_| {
+            sink_failure
+                .error()
+                .unwrap_or_else(|| ErrorKind::Shutdown.into())
+        });
+        let stream = self
+            .incoming
+            .map_err(|()| Error::from(ErrorKind::Shutdown))
+            .chain(stream::poll_fn(move || match failure.error() {
+                Some(error) => Err(error),
+                None => Ok(Async::Ready(None)),
+            }));
+        (Box::new(sink), Box::new(stream))
+    }
+}
+
+impl DuplexTransport for MultiplexedTransport {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::sync::mpsc as std_mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use test_transport::{self, TestError, TestServer};
+
+    #[test]
+    fn connection_error_reaches_clients() {
+        let (handle, server) = multiplexer();
+        let (client, client_handle) = handle.transport().unwrap().into_client();
+        let client = test_transport::spawn(client);
+        let call = client_handle.call_method::<JsonValue>("method", &());
+        let call = thread::spawn(move || call.wait());
+        server.request();
+        server.fail(TestError {
+            message: "token expired",
+            hint: RecoveryHint::Reauthenticate,
+        });
+
+        let errors = vec![
+            call.join().unwrap().unwrap_err(),
+            test_transport::client_result(&client).unwrap_err(),
+        ];
+        for error in errors {
+            assert_eq!(error.recovery_hint(), RecoveryHint::Reauthenticate);
+            assert_eq!(
+                error.iter().nth(1).unwrap().to_string(),
+                "Unable to send the JSON-RPC 2.0 request: token expired"
+            );
+            let inner = error.with_transport_error(|error| {
+                error
+                    .downcast_ref::<Error>()
+                    .and_then(|error| error.with_transport_error(|error| error.to_string()))
+            });
+            assert_eq!(inner, Some(Some("token expired".to_owned())));
+        }
+    }
+
+    #[test]
+    fn rewrites_ids() {
+        let (handle, server) = multiplexer();
+        let mut channels = vec![TestChannel::open(&handle), TestChannel::open(&handle)];
+        for (i, channel) in channels.iter_mut().enumerate() {
+            channel.send(json!({"jsonrpc": "2.0", "method": "a", "params": [i], "id": 1}));
+        }
+
+        let requests = vec![server.request(), server.request()];
+        assert_ne!(requests[0]["id"], requests[1]["id"]);
+        for request in requests.iter().rev() {
+            server.respond(request, request["params"][0].clone());
+        }
+        for (i, channel) in channels.iter().enumerate() {
+            let response = channel.receive();
+            assert_eq!(response["id"], 1);
+            assert_eq!(response["result"], i);
+        }
+    }
+
+    #[test]
+    fn routes_subscription_notifications() {
+        let (handle, server) = multiplexer();
+        let mut owner = TestChannel::open(&handle);
+        let mut other = TestChannel::open(&handle);
+        owner.send(json!({"jsonrpc": "2.0", "method": "eth_subscribe", "params": [], "id": 1}));
+        server.respond(&server.request(), json!("0xa"));
+        owner.receive();
+        // Methods merely containing "subscribe" do not create subscriptions.
+        other.send(json!({"jsonrpc": "2.0", "method": "get_subscribers", "params": [], "id": 1}));
+        server.respond(&server.request(), json!("0xb"));
+        other.receive();
+
+        server.send(&notification("0xa").to_string());
+        assert_eq!(owner.receive(), notification("0xa"));
+        assert_eq!(other.try_receive(), None);
+        server.send(&notification("0xb").to_string());
+        assert_eq!(owner.receive(), notification("0xb"));
+        assert_eq!(other.receive(), notification("0xb"));
+
+        owner.send(json!({
+            "jsonrpc": "2.0",
+            "method": "eth_unsubscribe",
+            "params": ["0xa"],
+            "id": 2,
+        }));
+        server.respond(&server.request(), json!(true));
+        owner.receive();
+        server.send(&notification("0xa").to_string());
+        assert_eq!(owner.receive(), notification("0xa"));
+        assert_eq!(other.receive(), notification("0xa"));
+    }
+
+    #[test]
+    fn splits_batch_responses() {
+        let (handle, server) = multiplexer();
+        let mut batching = TestChannel::open(&handle);
+        let mut single = TestChannel::open(&handle);
+        batching.send(json!([
+            {"jsonrpc": "2.0", "method": "a", "params": [], "id": 1},
+            {"jsonrpc": "2.0", "method": "b", "params": [], "id": 2},
+        ]));
+        single.send(json!({"jsonrpc": "2.0", "method": "c", "params": [], "id": 1}));
+
+        let mut requests = Vec::new();
+        for _ in 0..2 {
+            match server.request() {
+                JsonValue::Array(batch) => requests.extend(batch),
+                request => requests.push(request),
+            }
+        }
+        let responses = requests
+            .iter()
+            .map(|request| test_transport::response(request, request["method"].clone()))
+            .collect::<Vec<_>>();
+        server.send(&JsonValue::Array(responses).to_string());
+
+        let mut received = vec![batching.receive(), batching.receive()];
+        received.sort_by_key(|response| response["id"].as_u64());
+        assert_eq!(received[0]["result"], "a");
+        assert_eq!(received[1]["result"], "b");
+        let response = single.receive();
+        assert_eq!((&response["id"], &response["result"]), (&json!(1), &json!("c")));
+    }
+
+    #[test]
+    fn takes_messages_in_turn() {
+        let (transport, _server) = test_transport::pair();
+        let (mut multiplexer, handle) = Multiplexer::new(transport);
+        let mut sinks = (0..3)
+            .map(|_| handle.transport().unwrap().io_pair().0)
+            .collect::<Vec<_>>();
+        let order = future::lazy(move || {
+            multiplexer.poll_registrations();
+            let mut order = Vec::new();
+            for (channel, sink) in sinks.iter_mut().enumerate() {
+                queue(sink, channel);
+            }
+            for _ in 0..2 {
+                order.push(multiplexer.next_outgoing().unwrap());
+            }
+            // The first channel sends again, but has to wait for the last one.
+            queue(&mut sinks[0], 0);
+            for _ in 0..2 {
+                order.push(multiplexer.next_outgoing().unwrap());
+            }
+            Ok::<_, ()>(order)
+        }).wait()
+        .unwrap();
+
+        let methods = order
+            .iter()
+            .map(|message| serde_json::from_str::<JsonValue>(message).unwrap()["method"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(methods, vec![json!("0"), json!("1"), json!("2"), json!("0")]);
+    }
+
+    #[test]
+    fn closed_channel_leaves_others_open() {
+        let (handle, server) = multiplexer();
+        let mut closed = TestChannel::open(&handle);
+        let mut open = TestChannel::open(&handle);
+        closed.send(json!({"jsonrpc": "2.0", "method": "a", "params": [], "id": 1}));
+        let request = server.request();
+
+        drop(closed);
+        server.respond(&request, json!("a"));
+        open.send(json!({"jsonrpc": "2.0", "method": "b", "params": [], "id": 1}));
+        let request = server.request();
+        server.respond(&request, json!("b"));
+        assert_eq!(open.receive()["result"], "b");
+    }
+
+    /// Starts a multiplexer on a connection to a test server.
+    fn multiplexer() -> (MultiplexerHandle, TestServer) {
+        let (transport, server) = test_transport::pair();
+        let (multiplexer, handle) = Multiplexer::new(transport);
+        thread::spawn(move || multiplexer.wait());
+        (handle, server)
+    }
+
+    fn notification(subscription: &str) -> JsonValue {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "eth_subscription",
+            "params": {"subscription": subscription, "result": 1},
+        })
+    }
+
+    /// Queues a call named after the channel sending it.
+    fn queue(sink: &mut <MultiplexedTransport as Transport>::Sink, channel: usize) {
+        let call = json!({"jsonrpc": "2.0", "method": channel.to_string(), "id": 1});
+        match sink.start_send(call.to_string()) {
+            Ok(AsyncSink::Ready) => (),
+            _ => panic!("channel {} not ready", channel),
+        }
+    }
+
+    /// The end of a channel a client would use, sending and receiving parsed messages.
+    struct TestChannel {
+        sink: Option<<MultiplexedTransport as Transport>::Sink>,
+        received: std_mpsc::Receiver<JsonValue>,
+    }
+
+    impl TestChannel {
+        fn open(handle: &MultiplexerHandle) -> Self {
+            let (sink, stream) = handle.transport().unwrap().io_pair();
+            let (received_tx, received) = std_mpsc::channel();
+            thread::spawn(move || {
+                for message in stream.wait() {
+                    let message = serde_json::from_str(&message.unwrap()).unwrap();
+                    if received_tx.send(message).is_err() {
+                        break;
+                    }
+                }
+            });
+            TestChannel {
+                sink: Some(sink),
+                received,
+            }
+        }
+
+        fn send(&mut self, message: JsonValue) {
+            let sink = self.sink.take().unwrap();
+            self.sink = Some(sink.send(message.to_string()).wait().unwrap());
+        }
+
+        fn receive(&self) -> JsonValue {
+            self.received
+                .recv_timeout(Duration::from_secs(10))
+                .expect("nothing received")
+        }
+
+        fn try_receive(&self) -> Option<JsonValue> {
+            self.received.recv_timeout(Duration::from_millis(50)).ok()
+        }
+    }
+}
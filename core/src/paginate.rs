@@ -0,0 +1,360 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{ClientHandle, Error, ErrorKind, Result, ResultExt};
+use futures::{Async, Future, Poll, Stream};
+use serde;
+use serde_json::{self, Value as JsonValue};
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::Duration;
+use timer::{Delay, Timer};
+
+type PendingPage = Box<dyn Future<Item = JsonValue, Error = Error> + Send>;
+
+/// A page of results returned by a paginated method: the items on the page, and the cursor of
+/// the next page, or `None` on the last page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    /// The items on the page.
+    pub items: Vec<T>,
+    /// The cursor, offset or token to request the next page with.
+    pub next: Option<JsonValue>,
+}
+
+impl<T: serde::de::DeserializeOwned> Page<T> {
+    /// Reads a page from a result object with the items in the array `items_field` and the
+    /// cursor of the next page in `next_field`. A missing or `null` cursor marks the last page.
+    pub fn from_fields(result: JsonValue, items_field: &str, next_field: &str) -> Result<Self> {
+        let mut result = match result {
+            JsonValue::Object(result) => result,
+            _ => bail!(ErrorKind::ResponseError("the page is not an object")),
+        };
+        let items = result
+            .remove(items_field)
+            .ok_or(ErrorKind::ResponseError("the page has no items"))?;
+        let next = result
+            .remove(next_field)
+            .filter(|next| !next.is_null());
+        Ok(Page {
+            items: serde_json::from_value(items).chain_err(|| ErrorKind::DeserializeError)?,
+            next,
+        })
+    }
+}
+
+enum PageState {
+    /// The next page is requested once the items of the previous one have been yielded.
+    Next,
+    /// Waiting for the response to the call for a page.
+    Calling(PendingPage),
+    /// Waiting for the backoff after an error to pass.
+    Waiting(Delay),
+    /// The last page has been received.
+    Done,
+}
+
+/// A `Stream` of the items of a method returning its results in pages, such as listings in
+/// exchange and indexer APIs. Calls the method for the next page, with the cursor from the
+/// previous one, once the items of the previous page have been yielded.
+///
+/// Two closures describe the method: one building the parameters from the cursor, `None` for
+/// the first page, and one splitting a result into a [`Page`](struct.Page.html). The stream ends
+/// after a page without a next cursor, or without items.
+///
+/// A failed call is retried for the same page after a backoff, doubling for every consecutive
+/// error up to a maximum. Errors are only yielded from the stream once the configured number of
+/// consecutive errors has been reached, earlier errors are just logged. A page that can't be read,
+/// failing with `DeserializeError` or `ResponseError`, is not retried, as it would fail the same
+/// way again. Its error is yielded right away and ends the stream.
+pub struct Paginate<T, M: Timer> {
+    client: ClientHandle,
+    method: String,
+    params: Box<dyn Fn(Option<&JsonValue>) -> JsonValue + Send>,
+    page: Box<dyn Fn(JsonValue) -> Result<Page<T>> + Send>,
+    timer: M,
+    retry_delay: Duration,
+    max_backoff: Duration,
+    max_consecutive_errors: usize,
+
+    state: PageState,
+    cursor: Option<JsonValue>,
+    items: VecDeque<T>,
+    consecutive_errors: u32,
+}
+
+impl<T, M: Timer> Paginate<T, M> {
+    /// Creates a stream of the items returned by `method` through `client`, calling it with the
+    /// parameters made by `params` and reading pages with `page`. Uses `timer` to wait before
+    /// retrying failed calls.
+    pub fn new<P, F>(
+        client: ClientHandle,
+        method: impl Into<String>,
+        params: P,
+        page: F,
+        timer: M,
+    ) -> Self
+    where
+        P: Fn(Option<&JsonValue>) -> JsonValue + Send + 'static,
+        F: Fn(JsonValue) -> Result<Page<T>> + Send + 'static,
+    {
+        Paginate {
+            client,
+            method: method.into(),
+            params: Box::new(params),
+            page: Box::new(page),
+            timer,
+            retry_delay: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_consecutive_errors: 1,
+
+            state: PageState::Next,
+            cursor: None,
+            items: VecDeque::new(),
+            consecutive_errors: 0,
+        }
+    }
+
+    /// Sets the time to wait before retrying after the first failed call. Defaults to one second.
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Sets the upper limit for the backoff after failed calls. Defaults to one minute.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets how many calls in a row have to fail before the error is yielded from the stream.
+    /// Defaults to one, meaning every error is yielded.
+    pub fn max_consecutive_errors(mut self, errors: usize) -> Self {
+        self.max_consecutive_errors = cmp::max(errors, 1);
+        self
+    }
+
+    fn call(&self) -> PendingPage {
+        let params = (self.params)(self.cursor.as_ref());
+        Box::new(
+            self.client
+                .call_method(self.method.clone(), &params)
+                .map(|value: JsonValue| value),
+        )
+    }
+
+    fn backoff(&self) -> Duration {
+        let factor = 1u32
+            .checked_shl(self.consecutive_errors - 1)
+            .unwrap_or(u32::max_value());
+        self.retry_delay
+            .checked_mul(factor)
+            .map(|backoff| cmp::min(backoff, self.max_backoff))
+            .unwrap_or(self.max_backoff)
+    }
+}
+
+impl<T, M: Timer> Stream for Paginate<T, M> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<T>, Error> {
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Ok(Async::Ready(Some(item)));
+            }
+            // `None` when it is time to call again, otherwise the result of the last call.
+            let call_result = match self.state {
+                PageState::Next => None,
+                PageState::Done => return Ok(Async::Ready(None)),
+                PageState::Waiting(ref mut delay) => match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    _ => None,
+                },
+                PageState::Calling(ref mut call) => match call.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(value)) => Some((self.page)(value)),
+                    Err(e) => Some(Err(e)),
+                },
+            };
+
+            match call_result {
+                None => self.state = PageState::Calling(self.call()),
+                Some(Ok(page)) => {
+                    self.consecutive_errors = 0;
+                    let last = page.items.is_empty() || page.next.is_none();
+                    self.items.extend(page.items);
+                    if last {
+                        self.state = PageState::Done;
+                    } else {
+                        self.cursor = page.next;
+                        self.state = PageState::Next;
+                    }
+                }
+                Some(Err(e)) if is_unreadable_page(&e) => {
+                    self.state = PageState::Done;
+                    return Err(e);
+                }
+                Some(Err(e)) => {
+                    self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+                    let backoff = self.backoff();
+                    debug!(
+                        "Fetching a page from {} failed, retrying in {:?}: {}",
+                        self.method, backoff, e
+                    );
+                    self.state = PageState::Waiting(self.timer.delay(backoff));
+                    if self.consecutive_errors as usize % self.max_consecutive_errors == 0 {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns true for errors of results that do not make a valid page.
+fn is_unreadable_page(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::DeserializeError | ErrorKind::ResponseError(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::sync::oneshot;
+    use std::thread;
+    use test_transport::{self, TestServer};
+    use timer::ManualTimer;
+    use Transport;
+
+    fn paginate(
+        max_consecutive_errors: usize,
+    ) -> (Paginate<u32, ManualTimer>, TestServer, ManualTimer) {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        test_transport::spawn(client);
+        let timer = ManualTimer::default();
+        let paginate = Paginate::new(
+            handle,
+            "list",
+            |cursor| json!([cursor]),
+            |result| Page::from_fields(result, "items", "next"),
+            timer.clone(),
+        ).max_consecutive_errors(max_consecutive_errors);
+        (paginate, server, timer)
+    }
+
+    /// Collects the results yielded by `paginate` on a thread, until it ends.
+    fn collect(paginate: Paginate<u32, ManualTimer>) -> oneshot::Receiver<Vec<Result<u32>>> {
+        let (results_tx, results_rx) = oneshot::channel();
+        thread::spawn(move || {
+            let results = paginate.then(Ok::<_, ()>).collect().wait().unwrap();
+            let _ = results_tx.send(results);
+        });
+        results_rx
+    }
+
+    /// Waits until the stream waits for a backoff to pass.
+    fn wait_for_backoff(timer: &ManualTimer) {
+        while timer.pending() == 0 {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn threads_cursor() {
+        let (paginate, server, _timer) = paginate(1);
+        let results = collect(paginate);
+        let request = server.request();
+        assert_eq!(request["params"], json!([null]));
+        server.respond(&request, json!({"items": [1, 2], "next": "c1"}));
+        let request = server.request();
+        assert_eq!(request["params"], json!(["c1"]));
+        server.respond(&request, json!({"items": [3], "next": 4}));
+        let request = server.request();
+        assert_eq!(request["params"], json!([4]));
+        server.respond(&request, json!({"items": [4]}));
+
+        let results = results.wait().unwrap();
+        let items = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(items, vec![1, 2, 3, 4]);
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn ends_after_empty_page() {
+        let (paginate, server, _timer) = paginate(1);
+        let results = collect(paginate);
+        let request = server.request();
+        server.respond(&request, json!({"items": [], "next": "c1"}));
+        assert!(results.wait().unwrap().is_empty());
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn retries_with_backoff() {
+        let (paginate, server, timer) = paginate(2);
+        let results = collect(paginate.retry_delay(Duration::from_secs(1)));
+        let request = server.request();
+        server.respond_error(&request, -32000, "busy");
+        wait_for_backoff(&timer);
+        timer.advance(Duration::from_secs(1));
+
+        // The same page is requested again, and the second error in a row is yielded.
+        let retry = server.request();
+        assert_eq!(retry["params"], request["params"]);
+        server.respond_error(&retry, -32000, "busy");
+        wait_for_backoff(&timer);
+        timer.advance(Duration::from_secs(1));
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+        timer.advance(Duration::from_secs(1));
+        let request = server.request();
+        server.respond(&request, json!({"items": [1], "next": null}));
+
+        let mut results = results.wait().unwrap().into_iter();
+        match results.next() {
+            Some(Err(Error(ErrorKind::JsonRpcError(_), _))) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(results.next().unwrap().unwrap(), 1);
+        assert!(results.next().is_none());
+    }
+
+    #[test]
+    fn unreadable_page_not_retried() {
+        let (paginate, server, timer) = paginate(3);
+        let results = collect(paginate);
+        let request = server.request();
+        server.respond(&request, json!({"items": ["a"]}));
+        let results = results.wait().unwrap();
+        assert_eq!(results.len(), 1);
+        match results[0] {
+            Err(Error(ErrorKind::DeserializeError, _)) => (),
+            ref result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(timer.pending(), 0);
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let (paginate, _server, _timer) = paginate(1);
+        let mut paginate = paginate
+            .retry_delay(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(60));
+        let backoffs = [1, 2, 3, 6, 7, 32, 33, 1000]
+            .iter()
+            .map(|&errors| {
+                paginate.consecutive_errors = errors;
+                paginate.backoff().as_secs()
+            }).collect::<Vec<_>>();
+        assert_eq!(backoffs, vec![1, 2, 4, 32, 60, 60, 60, 60]);
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{serialize_parameters, ClientHandle, Error, ErrorKind, Result, ResultExt};
+use futures::{Async, Future, Poll, Stream};
+use jsonrpc_core::types::Params;
+use serde;
+use serde_json::{self, Value as JsonValue};
+use std::cmp;
+use std::marker::PhantomData;
+use std::time::Duration;
+use timer::{Delay, Timer};
+
+type PendingPoll = Box<dyn Future<Item = JsonValue, Error = Error> + Send>;
+
+enum PollState {
+    /// Nothing has been sent yet, the first call goes out on the first poll.
+    Start,
+    /// Waiting for the response to a call.
+    Calling(PendingPoll),
+    /// Waiting for the interval, or the backoff after an error, to pass.
+    Waiting(Delay),
+}
+
+/// A `Stream` that repeatedly calls a polling method on the server, such as
+/// `eth_getFilterChanges`, and yields the results. For servers that cannot push notifications.
+///
+/// After every successful call the stream waits for the configured interval before calling again.
+/// After a failed call it backs off exponentially, waiting for the interval after the first error
+/// and doubling the wait for every following consecutive error, up to a maximum. Errors are only
+/// yielded from the stream once the configured number of consecutive errors has been reached,
+/// earlier errors are just logged. The stream never ends on its own, it keeps polling for as long
+/// as it is polled.
+pub struct PollStream<T, M: Timer> {
+    client: ClientHandle,
+    method: String,
+    params: Option<Params>,
+    timer: M,
+    interval: Duration,
+    max_backoff: Duration,
+    max_consecutive_errors: usize,
+    dedup: bool,
+
+    state: PollState,
+    last_value: Option<JsonValue>,
+    consecutive_errors: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T: serde::de::DeserializeOwned, M: Timer> PollStream<T, M> {
+    /// Creates a new stream calling `method` with `params` through `client`, using `timer` to wait
+    /// between the calls. Polls once every second by default.
+    pub fn new(
+        client: ClientHandle,
+        method: impl Into<String>,
+        params: &impl serde::Serialize,
+        timer: M,
+    ) -> Result<Self> {
+        Ok(PollStream {
+            client,
+            method: method.into(),
+            params: serialize_parameters(params)?,
+            timer,
+            interval: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_consecutive_errors: 1,
+            dedup: false,
+
+            state: PollState::Start,
+            last_value: None,
+            consecutive_errors: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Sets the time to wait between two successful calls.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the upper limit for the backoff after failed calls. Defaults to one minute.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets how many calls in a row have to fail before the error is yielded from the stream.
+    /// Defaults to one, meaning every error is yielded.
+    pub fn max_consecutive_errors(mut self, errors: usize) -> Self {
+        self.max_consecutive_errors = cmp::max(errors, 1);
+        self
+    }
+
+    /// When enabled, a result equal to the previous result is not yielded again.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    fn call(&self) -> PendingPoll {
+        Box::new(
+            self.client
+                .call_method(self.method.clone(), &self.params)
+                .map(|value: JsonValue| value),
+        )
+    }
+
+    fn backoff(&self) -> Duration {
+        let factor = 1u32
+            .checked_shl(self.consecutive_errors - 1)
+            .unwrap_or(u32::max_value());
+        self.interval
+            .checked_mul(factor)
+            .map(|backoff| cmp::min(backoff, self.max_backoff))
+            .unwrap_or(self.max_backoff)
+    }
+}
+
+impl<T: serde::de::DeserializeOwned, M: Timer> Stream for PollStream<T, M> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<T>, Error> {
+        loop {
+            // `None` when it is time to make a new call, otherwise the result of the last call.
+            let call_result = match self.state {
+                PollState::Start => None,
+                PollState::Waiting(ref mut delay) => match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    _ => None,
+                },
+                PollState::Calling(ref mut call) => match call.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(value)) => Some(Ok(value)),
+                    Err(e) => Some(Err(e)),
+                },
+            };
+
+            match call_result {
+                None => self.state = PollState::Calling(self.call()),
+                Some(Ok(value)) => {
+                    self.consecutive_errors = 0;
+                    self.state = PollState::Waiting(self.timer.delay(self.interval));
+                    if self.dedup {
+                        if self.last_value.as_ref() == Some(&value) {
+                            continue;
+                        }
+                        self.last_value = Some(value.clone());
+                    }
+                    let item =
+                        serde_json::from_value(value).chain_err(|| ErrorKind::DeserializeError)?;
+                    return Ok(Async::Ready(Some(item)));
+                }
+                Some(Err(e)) => {
+                    self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+                    let backoff = self.backoff();
+                    debug!(
+                        "Polling {} failed, retrying in {:?}: {}",
+                        self.method, backoff, e
+                    );
+                    self.state = PollState::Waiting(self.timer.delay(backoff));
+                    if self.consecutive_errors as usize % self.max_consecutive_errors == 0 {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use test_transport::{self, TestServer};
+    use timer::ManualTimer;
+    use Transport;
+
+    fn poll_stream() -> (PollStream<u32, ManualTimer>, TestServer, ManualTimer) {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        test_transport::spawn(client);
+        let timer = ManualTimer::default();
+        let stream = PollStream::new(handle, "changes", &(), timer.clone())
+            .unwrap()
+            .interval(Duration::from_secs(1));
+        (stream, server, timer)
+    }
+
+    /// Forwards the results yielded by `stream` on a thread to the returned channel.
+    fn forward(stream: PollStream<u32, ManualTimer>) -> mpsc::Receiver<Result<u32>> {
+        let (results_tx, results_rx) = mpsc::channel();
+        thread::spawn(move || {
+            stream
+                .then(Ok::<_, ()>)
+                .for_each(|result| results_tx.send(result).map_err(|_| ()))
+                .wait()
+        });
+        results_rx
+    }
+
+    /// Waits until the stream waits for the interval or a backoff to pass.
+    fn wait_for_delay(timer: &ManualTimer) {
+        while timer.pending() == 0 {
+            thread::yield_now();
+        }
+    }
+
+    fn no_result(results: &mpsc::Receiver<Result<u32>>) -> bool {
+        results.recv_timeout(Duration::from_millis(10)).is_err()
+    }
+
+    #[test]
+    fn polls_at_interval() {
+        let (stream, server, timer) = poll_stream();
+        let results = forward(stream);
+        let request = server.request();
+        assert_eq!(request["method"], "changes");
+        server.respond(&request, json!(1));
+        assert_eq!(results.recv().unwrap().unwrap(), 1);
+
+        wait_for_delay(&timer);
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+        timer.advance(Duration::from_secs(1));
+        let request = server.request();
+        server.respond(&request, json!(1));
+        assert_eq!(results.recv().unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn dedup_skips_repeated_results() {
+        let (stream, server, timer) = poll_stream();
+        let results = forward(stream.dedup(true));
+        for &value in &[1, 1, 2] {
+            let request = server.request();
+            server.respond(&request, json!(value));
+            wait_for_delay(&timer);
+            timer.advance(Duration::from_secs(1));
+        }
+        assert_eq!(results.recv().unwrap().unwrap(), 1);
+        assert_eq!(results.recv().unwrap().unwrap(), 2);
+        assert!(no_result(&results));
+    }
+
+    #[test]
+    fn errors_yielded_after_max_consecutive() {
+        let (stream, server, timer) = poll_stream();
+        let results = forward(stream.max_consecutive_errors(2));
+        let request = server.request();
+        server.respond_error(&request, -32000, "busy");
+        wait_for_delay(&timer);
+        assert!(no_result(&results));
+
+        // The first retry waits for the interval, the second for twice the interval.
+        timer.advance(Duration::from_secs(1));
+        let request = server.request();
+        server.respond_error(&request, -32000, "busy");
+        match results.recv().unwrap() {
+            Err(Error(ErrorKind::JsonRpcError(_), _)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        wait_for_delay(&timer);
+        timer.advance(Duration::from_secs(1));
+        assert_eq!(server.try_payload(Duration::from_millis(10)), None);
+        timer.advance(Duration::from_secs(1));
+        let request = server.request();
+        server.respond(&request, json!(3));
+        assert_eq!(results.recv().unwrap().unwrap(), 3);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let (stream, _server, _timer) = poll_stream();
+        let mut stream = stream.max_backoff(Duration::from_secs(60));
+        let backoffs = [1, 2, 3, 6, 7, 32, 33, 1000]
+            .iter()
+            .map(|&errors| {
+                stream.consecutive_errors = errors;
+                stream.backoff().as_secs()
+            }).collect::<Vec<_>>();
+        assert_eq!(backoffs, vec![1, 2, 4, 32, 60, 60, 60, 60]);
+    }
+}
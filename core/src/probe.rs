@@ -0,0 +1,290 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{serialize_parameters, ClientHandle, Error, ErrorKind, Result};
+use futures::{future, Future};
+use jsonrpc_core::types::Params;
+use serde;
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// What a server reported about itself from its version method.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    /// The version string of the server, like `Geth/v1.8.2-stable/linux-amd64/go1.10`.
+    pub version: String,
+    /// The numbers of the first dotted version in the version string, like `[1, 8, 2]`. Empty if
+    /// the string contains no number.
+    pub version_numbers: Vec<u64>,
+    /// The full result of the version method, for servers reporting more than their version.
+    pub raw: JsonValue,
+}
+
+impl ServerInfo {
+    /// Reads the version from the result of a version method. The result is either the version
+    /// string itself, or an object with the version string in `subversion` or `version`, which
+    /// covers `web3_clientVersion` and Bitcoin Core's `getnetworkinfo`.
+    pub fn from_result(raw: JsonValue) -> Result<Self> {
+        let version = {
+            let version = match raw {
+                JsonValue::Object(ref fields) => fields
+                    .get("subversion")
+                    .and_then(JsonValue::as_str)
+                    .or_else(|| fields.get("version").and_then(JsonValue::as_str)),
+                ref version => version.as_str(),
+            };
+            match version {
+                Some(version) => version.to_owned(),
+                None => bail!(ErrorKind::ResponseError("no version string in the result")),
+            }
+        };
+        Ok(ServerInfo::new(version, raw))
+    }
+
+    /// Creates the info of a server with the given version string.
+    pub fn new(version: String, raw: JsonValue) -> Self {
+        ServerInfo {
+            version_numbers: version_numbers(&version),
+            version,
+            raw,
+        }
+    }
+
+    /// Returns true if the version of the server is `version` or later, comparing the version
+    /// numbers from left to right. A missing number counts as zero.
+    pub fn at_least(&self, version: &[u64]) -> bool {
+        let len = self.version_numbers.len().max(version.len());
+        for i in 0..len {
+            let ours = self.version_numbers.get(i).cloned().unwrap_or(0);
+            let theirs = version.get(i).cloned().unwrap_or(0);
+            if ours != theirs {
+                return ours > theirs;
+            }
+        }
+        true
+    }
+}
+
+/// Returns the numbers of the first run of digits and dots in `version`.
+fn version_numbers(version: &str) -> Vec<u64> {
+    let start = match version.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+    let dotted = version[start..]
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()
+        .unwrap_or("");
+    let mut numbers = Vec::new();
+    for part in dotted.split('.') {
+        match part.parse() {
+            Ok(number) => numbers.push(number),
+            Err(_) => break,
+        }
+    }
+    numbers
+}
+
+type Parser = Arc<dyn Fn(JsonValue) -> Result<ServerInfo> + Send + Sync>;
+
+struct ProbeState {
+    client: ClientHandle,
+    info: Option<ServerInfo>,
+    // Incremented on every reconnect, so that a probe through an old client is not cached.
+    generation: u64,
+}
+
+/// Finds out which server the client talks to by calling its version method, like
+/// `web3_clientVersion` or `getnetworkinfo`, so optional features can be enabled for the servers
+/// supporting them. The `ServerInfo` is cached after the first successful probe, until
+/// [`reconnected`](#method.reconnected) is called. The probe can't tell when the client
+/// reconnects, so the application has to call it with the handle of the new client.
+///
+/// Clones share the cache.
+#[derive(Clone)]
+pub struct ServerProbe {
+    state: Arc<Mutex<ProbeState>>,
+    method: String,
+    params: Option<Params>,
+    parser: Parser,
+}
+
+impl ServerProbe {
+    /// Creates a probe calling `method` with `params` through `client`, reading the result with
+    /// [`ServerInfo::from_result`](struct.ServerInfo.html#method.from_result).
+    pub fn new(
+        client: ClientHandle,
+        method: impl Into<String>,
+        params: &impl serde::Serialize,
+    ) -> Result<Self> {
+        Ok(ServerProbe {
+            state: Arc::new(Mutex::new(ProbeState {
+                client,
+                info: None,
+                generation: 0,
+            })),
+            method: method.into(),
+            params: serialize_parameters(params)?,
+            parser: Arc::new(ServerInfo::from_result),
+        })
+    }
+
+    /// Sets the function reading the `ServerInfo` from the result of the version method, for
+    /// servers reporting their version in other ways.
+    pub fn parser<F>(mut self, parser: F) -> Self
+    where
+        F: Fn(JsonValue) -> Result<ServerInfo> + Send + Sync + 'static,
+    {
+        self.parser = Arc::new(parser);
+        self
+    }
+
+    /// Returns the info of the server, probing it unless it is cached.
+    pub fn info(&self) -> Box<dyn Future<Item = ServerInfo, Error = Error> + Send> {
+        let (client, generation) = {
+            let state = self.state.lock().unwrap();
+            if let Some(ref info) = state.info {
+                return Box::new(future::ok(info.clone()));
+            }
+            (state.client.clone(), state.generation)
+        };
+        let state = self.state.clone();
+        let parser = self.parser.clone();
+        let probe = client
+            .call_method(self.method.clone(), &self.params)
+            .and_then(move |result: JsonValue| parser(result))
+            .map(move |info| {
+                let mut state = state.lock().unwrap();
+                if state.generation == generation {
+                    state.info = Some(info.clone());
+                }
+                info
+            });
+        Box::new(probe)
+    }
+
+    /// Returns the cached info of the server, if it has been probed.
+    pub fn cached(&self) -> Option<ServerInfo> {
+        self.state.lock().unwrap().info.clone()
+    }
+
+    /// Forgets the cached info and probes through `client` from now on. Call it whenever a new
+    /// client replaces the old one, as the server may have been upgraded or replaced in the
+    /// meantime. A probe still running through the old client is not cached.
+    pub fn reconnected(&self, client: ClientHandle) {
+        let mut state = self.state.lock().unwrap();
+        state.client = client;
+        state.info = None;
+        state.generation += 1;
+    }
+}
+
+impl fmt::Debug for ServerProbe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServerProbe")
+            .field("method", &self.method)
+            .field("info", &self.cached())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use test_transport::{self, TestServer};
+    use Transport;
+
+    fn client() -> (ClientHandle, TestServer) {
+        let (transport, server) = test_transport::pair();
+        let (client, handle) = transport.into_client();
+        test_transport::spawn(client);
+        (handle, server)
+    }
+
+    #[test]
+    fn version_numbers_of_servers() {
+        let cases: &[(&str, &[u64])] = &[
+            ("Geth/v1.8.2-stable-b8b9f7f4/linux-amd64/go1.10", &[1, 8, 2]),
+            ("/Satoshi:0.16.0/", &[0, 16, 0]),
+            ("Parity//v1.10.0-stable", &[1, 10, 0]),
+            ("v2", &[2]),
+            ("1.2.", &[1, 2]),
+            ("unknown", &[]),
+        ];
+        for &(version, numbers) in cases {
+            assert_eq!(version_numbers(version), numbers, "{}", version);
+        }
+    }
+
+    #[test]
+    fn at_least() {
+        let info = ServerInfo::new("Geth/v1.8.2-stable".to_owned(), JsonValue::Null);
+        assert!(info.at_least(&[1, 8, 2]));
+        assert!(info.at_least(&[1, 8]));
+        assert!(info.at_least(&[1, 7, 9]));
+        assert!(info.at_least(&[]));
+        assert!(!info.at_least(&[1, 8, 2, 1]));
+        assert!(!info.at_least(&[1, 10]));
+        assert!(!info.at_least(&[2]));
+        let unknown = ServerInfo::new("unknown".to_owned(), JsonValue::Null);
+        assert!(!unknown.at_least(&[0, 1]));
+        assert!(unknown.at_least(&[0]));
+    }
+
+    #[test]
+    fn from_result() {
+        let info = ServerInfo::from_result(json!("Geth/v1.8.2-stable/linux-amd64/go1.10")).unwrap();
+        assert_eq!(info.version_numbers, vec![1, 8, 2]);
+
+        // Bitcoin Core's getnetworkinfo, where `version` is a number.
+        let raw = json!({"version": 160000, "subversion": "/Satoshi:0.16.0/"});
+        let info = ServerInfo::from_result(raw.clone()).unwrap();
+        assert_eq!(info.version, "/Satoshi:0.16.0/");
+        assert_eq!(info.version_numbers, vec![0, 16, 0]);
+        assert_eq!(info.raw, raw);
+
+        let info = ServerInfo::from_result(json!({"version": "2.1"})).unwrap();
+        assert_eq!(info.version_numbers, vec![2, 1]);
+
+        for raw in vec![json!({"version": 160000}), json!(1), json!(null)] {
+            match ServerInfo::from_result(raw) {
+                Err(Error(ErrorKind::ResponseError(_), _)) => (),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn caches_until_reconnected() {
+        let (handle, server) = client();
+        let probe = ServerProbe::new(handle, "web3_clientVersion", &()).unwrap();
+        let info = probe.info();
+        let info = thread::spawn(move || info.wait());
+        let request = server.request();
+        assert_eq!(request["method"], "web3_clientVersion");
+
+        // The probe through the old client finishes after the reconnect, and is not cached.
+        let (new_handle, new_server) = client();
+        probe.reconnected(new_handle);
+        server.respond(&request, json!("Geth/v1.8.2"));
+        assert_eq!(info.join().unwrap().unwrap().version, "Geth/v1.8.2");
+        assert!(probe.cached().is_none());
+
+        let info = probe.info();
+        let info = thread::spawn(move || info.wait());
+        let request = new_server.request();
+        new_server.respond(&request, json!("Geth/v1.9.0"));
+        assert_eq!(info.join().unwrap().unwrap().version_numbers, vec![1, 9, 0]);
+        assert_eq!(probe.cached().unwrap().version, "Geth/v1.9.0");
+        assert_eq!(probe.info().wait().unwrap().version, "Geth/v1.9.0");
+        assert_eq!(new_server.try_payload(Duration::from_millis(10)), None);
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A transport connected to the test using it instead of to a server, for the tests of the
+//! client. The test sees every payload the client sends, and decides what the client receives.
+
+#![allow(dead_code)]
+
+use futures::sync::mpsc;
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use serde_json::{self, Value as JsonValue};
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use std::time::Duration;
+
+use super::{server, Client, RecoveryHint, Result, Transport};
+
+/// The time to wait for the client to send a payload before failing the test.
+const TIMEOUT_SECS: u64 = 10;
+
+/// Creates a transport and the end of it the test plays the server with.
+pub fn pair() -> (TestTransport, TestServer) {
+    let (sent_tx, sent_rx) = std_mpsc::channel();
+    let (received_tx, received_rx) = mpsc::unbounded();
+    let transport = TestTransport {
+        sent_tx,
+        received_rx,
+    };
+    let server = TestServer {
+        sent_rx,
+        received_tx,
+    };
+    (transport, server)
+}
+
+/// Drives `client` on a thread of its own. The receiver gets the result of the client once it
+/// stops.
+pub fn spawn<T, S>(client: Client<T, S>) -> std_mpsc::Receiver<Result<()>>
+where
+    T: Transport + 'static,
+    S: server::ServerHandler + Send + 'static,
+{
+    let (result_tx, result_rx) = std_mpsc::channel();
+    thread::spawn(move || {
+        let _ = result_tx.send(client.wait());
+    });
+    result_rx
+}
+
+/// Waits for the result of a client driven with `spawn`.
+pub fn client_result(result_rx: &std_mpsc::Receiver<Result<()>>) -> Result<()> {
+    result_rx
+        .recv_timeout(Duration::from_secs(TIMEOUT_SECS))
+        .expect("client did not stop")
+}
+
+/// The error of a `TestTransport`, carrying the hint the transport gives for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestError {
+    pub message: &'static str,
+    pub hint: RecoveryHint,
+}
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+impl StdError for TestError {
+    fn description(&self) -> &str {
+        self.message
+    }
+}
+
+/// The transport end of `pair`.
+pub struct TestTransport {
+    sent_tx: std_mpsc::Sender<String>,
+    received_rx: mpsc::UnboundedReceiver<::std::result::Result<String, TestError>>,
+}
+
+impl Transport for TestTransport {
+    type Error = TestError;
+    type Sink = TestSink;
+    type Stream = Box<dyn Stream<Item = String, Error = TestError> + Send>;
+
+    fn io_pair(self) -> (Self::Sink, Self::Stream) {
+        let stream = self.received_rx.then(|received| match received {
+            Ok(received) => received,
+            Err(()) => unreachable!("unbounded receivers never fail"),
+        });
+        (TestSink(self.sent_tx), Box::new(stream))
+    }
+
+    fn recovery_hint(error: &TestError) -> RecoveryHint {
+        error.hint
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some("test".to_owned())
+    }
+}
+
+/// The sink of a `TestTransport`, always ready to send.
+pub struct TestSink(std_mpsc::Sender<String>);
+
+impl Sink for TestSink {
+    type SinkItem = String;
+    type SinkError = TestError;
+
+    fn start_send(&mut self, payload: String) -> StartSend<String, TestError> {
+        self.0.send(payload).map_err(|_| TestError {
+            message: "test server dropped",
+            hint: RecoveryHint::Reconnect,
+        })?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), TestError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// The server end of `pair`. Dropping it ends the stream of the transport.
+pub struct TestServer {
+    sent_rx: std_mpsc::Receiver<String>,
+    received_tx: mpsc::UnboundedSender<::std::result::Result<String, TestError>>,
+}
+
+impl TestServer {
+    /// Returns the next payload sent by the client, failing the test if none is sent in time.
+    pub fn payload(&self) -> String {
+        self.sent_rx
+            .recv_timeout(Duration::from_secs(TIMEOUT_SECS))
+            .expect("client sent nothing")
+    }
+
+    /// Returns the next payload sent by the client, parsed.
+    pub fn request(&self) -> JsonValue {
+        serde_json::from_str(&self.payload()).expect("client sent invalid JSON")
+    }
+
+    /// Returns the next payload sent by the client within `wait`, if any.
+    pub fn try_payload(&self, wait: Duration) -> Option<String> {
+        self.sent_rx.recv_timeout(wait).ok()
+    }
+
+    /// Makes the client receive `payload`.
+    pub fn send(&self, payload: &str) {
+        self.received_tx
+            .unbounded_send(Ok(payload.to_owned()))
+            .expect("client dropped");
+    }
+
+    /// Answers `request` with `result`.
+    pub fn respond(&self, request: &JsonValue, result: JsonValue) {
+        self.send(&response(request, result).to_string());
+    }
+
+    /// Answers `request` with a JSON-RPC error.
+    pub fn respond_error(&self, request: &JsonValue, code: i64, message: &str) {
+        self.send(&error_response(request, code, message).to_string());
+    }
+
+    /// Answers the next call with its parameters, and returns the call.
+    pub fn echo(&self) -> JsonValue {
+        let request = self.request();
+        self.respond(&request, request["params"].clone());
+        request
+    }
+
+    /// Makes the stream of the transport fail with `error`.
+    pub fn fail(&self, error: TestError) {
+        let _ = self.received_tx.unbounded_send(Err(error));
+    }
+}
+
+/// Creates the response answering `request` with `result`.
+pub fn response(request: &JsonValue, result: JsonValue) -> JsonValue {
+    json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+}
+
+/// Creates the response answering `request` with a JSON-RPC error.
+pub fn error_response(request: &JsonValue, code: i64, message: &str) -> JsonValue {
+    json!({
+        "jsonrpc": "2.0",
+        "id": request["id"],
+        "error": {"code": code, "message": message},
+    })
+}
@@ -0,0 +1,137 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::sync::oneshot;
+use futures::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A future resolving once a delay has expired.
+pub type Delay = Box<dyn Future<Item = (), Error = ()> + Send>;
+
+/// Source of delays for the parts of the client that need to wait, such as
+/// [`PollStream`](../poll_stream/struct.PollStream.html).
+///
+/// The core crate is not tied to any event loop, so the user supplies the timer. Any closure
+/// taking a `Duration` and returning a `Delay` implements this trait, so a Tokio based timer can
+/// be plugged in with something like:
+///
+/// ```rust,ignore
+/// let timer = move |duration| {
+///     Box::new(Timeout::new(duration, &handle).unwrap().map_err(|_| ())) as Delay
+/// };
+/// ```
+pub trait Timer {
+    /// Returns a future that resolves after `duration` has passed. An error from the future is
+    /// treated the same as the delay expiring.
+    fn delay(&self, duration: Duration) -> Delay;
+}
+
+impl<F> Timer for F
+where
+    F: Fn(Duration) -> Delay,
+{
+    fn delay(&self, duration: Duration) -> Delay {
+        (self)(duration)
+    }
+}
+
+/// Source of the current time, for the parts of the client that compare against wall clock
+/// time, such as `Retry-After` dates.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// A `Clock` returning the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `Timer` and `Clock` driven by virtual time, for tests of code that waits for timeouts and
+/// backoff delays. Time stands still until it is moved forward with
+/// [`advance`](#method.advance), which resolves all delays that have expired by then.
+///
+/// Clones share the same virtual time.
+#[derive(Debug, Clone)]
+pub struct ManualTimer {
+    state: Arc<Mutex<ManualTimerState>>,
+}
+
+#[derive(Debug)]
+struct ManualTimerState {
+    now: SystemTime,
+    pending: Vec<(SystemTime, oneshot::Sender<()>)>,
+}
+
+impl ManualTimer {
+    /// Creates a new timer with its virtual time starting at `start`.
+    pub fn new(start: SystemTime) -> Self {
+        ManualTimer {
+            state: Arc::new(Mutex::new(ManualTimerState {
+                now: start,
+                pending: Vec::new(),
+            })),
+        }
+    }
+
+    /// Moves the virtual time forward by `duration` and resolves all delays that expire within
+    /// that time.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().expect("ManualTimer lock poisoned");
+        state.now += duration;
+        let now = state.now;
+        let (expired, pending): (Vec<_>, Vec<_>) = state
+            .pending
+            .drain(..)
+            .partition(|&(deadline, _)| deadline <= now);
+        state.pending = pending;
+        for (_, sender) in expired {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Returns the number of delays that have not yet expired. Delays whose future has been
+    /// dropped are not counted.
+    pub fn pending(&self) -> usize {
+        let mut state = self.state.lock().expect("ManualTimer lock poisoned");
+        state.pending.retain(|&(_, ref sender)| !sender.is_canceled());
+        state.pending.len()
+    }
+}
+
+impl Default for ManualTimer {
+    fn default() -> Self {
+        ManualTimer::new(UNIX_EPOCH)
+    }
+}
+
+impl Timer for ManualTimer {
+    fn delay(&self, duration: Duration) -> Delay {
+        let (sender, receiver) = oneshot::channel();
+        let mut state = self.state.lock().expect("ManualTimer lock poisoned");
+        if duration == Duration::from_secs(0) {
+            let _ = sender.send(());
+        } else {
+            let deadline = state.now + duration;
+            state.pending.push((deadline, sender));
+        }
+        Box::new(receiver.map_err(|_| ()))
+    }
+}
+
+impl Clock for ManualTimer {
+    fn now(&self) -> SystemTime {
+        self.state.lock().expect("ManualTimer lock poisoned").now
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checks that a `Transport` behaves the way the `Client` expects, for the tests of transport
+//! implementations. Call [`run_all`](fn.run_all.html) from a test, with a function creating a
+//! transport connected to a server that:
+//!
+//! * answers calls to [`ECHO_METHOD`](constant.ECHO_METHOD.html) with their parameters as result,
+//! * answers calls to [`ERROR_METHOD`](constant.ERROR_METHOD.html) with any JSON-RPC error.
+//!
+//! Each check runs on its own client, driven on a thread of its own, and panics with the name of
+//! the check and a description of the misbehavior if the transport fails it, or if it takes
+//! longer than 30 seconds.
+
+use futures::{future, Async, Future};
+use serde_json::Value as JsonValue;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use super::{ClientHandle, Error, ErrorKind, Result, Transport};
+
+/// The method the server must answer with the parameters of the call as result.
+pub const ECHO_METHOD: &str = "echo";
+
+/// The method the server must answer with a JSON-RPC error.
+pub const ERROR_METHOD: &str = "fail";
+
+/// The time a check may take, including shutting down its client.
+const TIMEOUT_SECS: u64 = 30;
+
+/// The number of calls in flight at once in the concurrency check.
+const CONCURRENT_CALLS: usize = 100;
+
+/// The size in bytes of the string echoed by the large payload check.
+const LARGE_PAYLOAD: usize = 4 * 1024 * 1024;
+
+/// Runs all checks, each with a new transport from `new_transport`.
+pub fn run_all<T, F>(new_transport: F)
+where
+    T: Transport + 'static,
+    F: Fn() -> T,
+{
+    id_handling(new_transport());
+    concurrency(new_transport());
+    dropped_calls(new_transport());
+    cancellation(new_transport());
+    large_payloads(new_transport());
+    error_propagation(new_transport());
+}
+
+/// Checks that responses to calls made one after another reach the right call.
+pub fn id_handling<T: Transport + 'static>(transport: T) {
+    check("id handling", transport, |handle| {
+        for i in 0..10u64 {
+            assert_eq!(echo(handle, i), Ok(i), "wrong result of sequential call {}", i);
+        }
+    });
+}
+
+/// Checks that the responses to many calls in flight at once reach the right calls, in whatever
+/// order the transport delivers them.
+pub fn concurrency<T: Transport + 'static>(transport: T) {
+    check("concurrency", transport, |handle| {
+        let calls = (0..CONCURRENT_CALLS)
+            .map(|i| handle.call_method(ECHO_METHOD, &(i,)))
+            .collect::<Vec<_>>();
+        let results: Vec<(usize,)> = future::join_all(calls)
+            .wait()
+            .expect("concurrent calls failed");
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result, (i,), "wrong result of concurrent call {}", i);
+        }
+    });
+}
+
+/// Checks that dropping the future of a call after it was sent does not affect other calls.
+pub fn dropped_calls<T: Transport + 'static>(transport: T) {
+    check("dropped calls", transport, |handle| {
+        let mut dropped = handle.call_method::<JsonValue>(ECHO_METHOD, &("dropped",));
+        poll_once(&mut dropped);
+        drop(dropped);
+        assert_eq!(echo(handle, 1), Ok(1), "wrong result of a call after a dropped call");
+    });
+}
+
+/// Checks that a call still waiting for its response when the client stops fails with
+/// `Cancelled`, and that the client stops cleanly.
+pub fn cancellation<T: Transport + 'static>(transport: T) {
+    let name = "cancellation";
+    let (client, handle) = transport.into_client();
+    let mut call = handle.call_method::<JsonValue>(ECHO_METHOD, &("cancelled",));
+    // Hands the call to the client before it runs, so that it sees the call and that all handles
+    // are gone before the response can arrive.
+    poll_once(&mut call);
+    drop(handle);
+    let client_rx = spawn_client(client);
+    run_test(name, move || match call.wait() {
+        Err(Error(ErrorKind::Cancelled, _)) => (),
+        result => panic!("expected the call to be cancelled, got {:?}", result),
+    });
+    wait_for_client(name, &client_rx);
+}
+
+/// Checks that a payload of several megabytes is sent and received intact.
+pub fn large_payloads<T: Transport + 'static>(transport: T) {
+    check("large payloads", transport, |handle| {
+        let text = "x".repeat(LARGE_PAYLOAD);
+        let result: (String,) = handle
+            .call_method(ECHO_METHOD, &(&text,))
+            .wait()
+            .expect("large call failed");
+        assert!(result.0 == text, "large payload changed on the way");
+    });
+}
+
+/// Checks that a JSON-RPC error response fails only its call, with `JsonRpcError`.
+pub fn error_propagation<T: Transport + 'static>(transport: T) {
+    check("error propagation", transport, |handle| {
+        match handle.call_method::<JsonValue>(ERROR_METHOD, &()).wait() {
+            Err(Error(ErrorKind::JsonRpcError(_), _)) => (),
+            result => panic!("expected a JSON-RPC error, got {:?}", result),
+        }
+        assert_eq!(echo(handle, 1), Ok(1), "wrong result of a call after an error");
+    });
+}
+
+fn echo(handle: &ClientHandle, i: u64) -> ::std::result::Result<u64, String> {
+    handle
+        .call_method(ECHO_METHOD, &(i,))
+        .wait()
+        .map(|(result,): (u64,)| result)
+        .map_err(|e| e.to_string())
+}
+
+/// Polls `call` once, which hands it to the client.
+fn poll_once<F: Future>(call: &mut F) {
+    future::poll_fn(|| {
+        let _ = call.poll();
+        Ok::<_, ()>(Async::Ready(()))
+    }).wait()
+    .unwrap();
+}
+
+/// Runs `test` with the handle of a client using `transport`, then checks that the client shuts
+/// down cleanly once the handle is dropped.
+fn check<T: Transport + 'static>(name: &str, transport: T, test: fn(&ClientHandle)) {
+    let (client, handle) = transport.into_client();
+    let client_rx = spawn_client(client);
+    run_test(name, move || test(&handle));
+    wait_for_client(name, &client_rx);
+}
+
+/// Drives `client` on a thread of its own, sending its result to the returned receiver.
+fn spawn_client<F>(client: F) -> mpsc::Receiver<Result<()>>
+where
+    F: Future<Item = (), Error = Error> + Send + 'static,
+{
+    let (client_tx, client_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = client_tx.send(client.wait());
+    });
+    client_rx
+}
+
+/// Runs `test` on a thread of its own, and panics with the name of the check if it panics or
+/// takes longer than the timeout.
+fn run_test<F: FnOnce() + Send + 'static>(name: &str, test: F) {
+    let (done_tx, done_rx) = mpsc::channel();
+    let test_thread = thread::spawn(move || {
+        test();
+        let _ = done_tx.send(());
+    });
+    match done_rx.recv_timeout(Duration::from_secs(TIMEOUT_SECS)) {
+        Ok(()) => (),
+        Err(RecvTimeoutError::Timeout) => panic!("{}: timed out", name),
+        Err(RecvTimeoutError::Disconnected) => match test_thread.join() {
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<String>()
+                    .map(String::as_str)
+                    .or_else(|| panic.downcast_ref::<&str>().cloned())
+                    .unwrap_or("panicked");
+                panic!("{}: {}", name, message)
+            }
+            Ok(()) => unreachable!("test finished without saying so"),
+        },
+    }
+}
+
+/// Checks that the client driven by `spawn_client` stops cleanly within the timeout.
+fn wait_for_client(name: &str, client_rx: &mpsc::Receiver<Result<()>>) {
+    match client_rx.recv_timeout(Duration::from_secs(TIMEOUT_SECS)) {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => panic!("{}: client failed: {}", name, e),
+        Err(RecvTimeoutError::Timeout) => {
+            panic!("{}: client did not stop after its handle was dropped", name)
+        }
+        Err(RecvTimeoutError::Disconnected) => panic!("{}: client panicked", name),
+    }
+}
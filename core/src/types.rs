@@ -0,0 +1,316 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serde types for values many JSON-RPC APIs encode the same way, such as the `0x` prefixed hex
+//! strings used by Ethereum nodes.
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The error returned when parsing a hex string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHex(String);
+
+impl fmt::Display for InvalidHex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid hex string: {}", self.0)
+    }
+}
+
+impl StdError for InvalidHex {
+    fn description(&self) -> &str {
+        "Invalid hex string"
+    }
+}
+
+/// Bytes encoded as a `0x` prefixed hex string, like `"0x00ff"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("0x")?;
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Bytes {
+    type Err = InvalidHex;
+
+    fn from_str(s: &str) -> Result<Self, InvalidHex> {
+        let digits = strip_prefix(s)?;
+        if digits.len() % 2 != 0 {
+            return Err(InvalidHex(s.to_owned()));
+        }
+        (0..digits.len() / 2)
+            .map(|index| u8::from_str_radix(&digits[2 * index..2 * index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map(Bytes)
+            .map_err(|_| InvalidHex(s.to_owned()))
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+/// An unsigned integer encoded as a `0x` prefixed hex string without leading zeros, like
+/// `"0x1b4"`. Zero is encoded as `"0x0"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quantity(pub u64);
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:x}", self.0)
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = InvalidHex;
+
+    fn from_str(s: &str) -> Result<Self, InvalidHex> {
+        let digits = strip_prefix(s)?;
+        if digits.is_empty() {
+            return Err(InvalidHex(s.to_owned()));
+        }
+        u64::from_str_radix(digits, 16)
+            .map(Quantity)
+            .map_err(|_| InvalidHex(s.to_owned()))
+    }
+}
+
+impl From<u64> for Quantity {
+    fn from(value: u64) -> Self {
+        Quantity(value)
+    }
+}
+
+/// A 256 bit unsigned integer, stored as big endian bytes, encoded like a `Quantity`. Used for
+/// values like balances that do not fit in a `u64`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U256(pub [u8; 32]);
+
+impl U256 {
+    /// Returns the value if it fits in a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        if self.0[..24].iter().any(|&byte| byte != 0) {
+            return None;
+        }
+        Some(
+            self.0[24..]
+                .iter()
+                .fold(0, |value, &byte| (value << 8) | u64::from(byte)),
+        )
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self
+            .0
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        match digits.trim_left_matches('0') {
+            "" => f.write_str("0x0"),
+            digits => write!(f, "0x{}", digits),
+        }
+    }
+}
+
+impl FromStr for U256 {
+    type Err = InvalidHex;
+
+    fn from_str(s: &str) -> Result<Self, InvalidHex> {
+        let digits = strip_prefix(s)?;
+        if digits.is_empty() || digits.len() > 64 {
+            return Err(InvalidHex(s.to_owned()));
+        }
+        let mut bytes = [0u8; 32];
+        // Parse from the end, two digits at a time, so an odd number of digits works.
+        let mut end = digits.len();
+        for byte in bytes.iter_mut().rev() {
+            if end == 0 {
+                break;
+            }
+            let start = end.saturating_sub(2);
+            *byte = u8::from_str_radix(&digits[start..end], 16)
+                .map_err(|_| InvalidHex(s.to_owned()))?;
+            end = start;
+        }
+        Ok(U256(bytes))
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        let mut bytes = [0u8; 32];
+        for (index, byte) in bytes[24..].iter_mut().enumerate() {
+            *byte = (value >> (8 * (7 - index))) as u8;
+        }
+        U256(bytes)
+    }
+}
+
+/// A point in time encoded as the number of seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixTimestamp(pub u64);
+
+impl UnixTimestamp {
+    /// Returns the timestamp as a `SystemTime`.
+    pub fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.0)
+    }
+}
+
+impl From<SystemTime> for UnixTimestamp {
+    /// Converts a `SystemTime`, truncating it to whole seconds. Times before the epoch become
+    /// zero.
+    fn from(time: SystemTime) -> Self {
+        UnixTimestamp(
+            time.duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl Serialize for UnixTimestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for UnixTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(UnixTimestamp)
+    }
+}
+
+/// Implements `Serialize` and `Deserialize` through `Display` and `FromStr`.
+macro_rules! impl_serde_via_string {
+    ($($name:ident),*) => {
+        $(
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    String::deserialize(deserializer)?
+                        .parse()
+                        .map_err(de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+impl_serde_via_string!(Bytes, Quantity, U256);
+
+/// Returns the digits of a `0x` prefixed hex string, checking that they are all hex digits.
+fn strip_prefix(s: &str) -> Result<&str, InvalidHex> {
+    if !s.starts_with("0x") && !s.starts_with("0X") {
+        return Err(InvalidHex(s.to_owned()));
+    }
+    let digits = &s[2..];
+    if digits.chars().all(|c| c.is_digit(16)) {
+        Ok(digits)
+    } else {
+        Err(InvalidHex(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn bytes() {
+        assert_eq!("0x00ff".parse(), Ok(Bytes(vec![0, 0xff])));
+        assert_eq!("0X".parse(), Ok(Bytes(Vec::new())));
+        assert_eq!(Bytes(vec![0, 0xab]).to_string(), "0x00ab");
+        for invalid in &["0x0", "0x123", "00ff", "ff", "0xgg", ""] {
+            assert!(invalid.parse::<Bytes>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn quantity() {
+        assert_eq!("0x0".parse(), Ok(Quantity(0)));
+        assert_eq!("0x1b4".parse(), Ok(Quantity(0x1b4)));
+        assert_eq!("0xffffffffffffffff".parse(), Ok(Quantity(u64::max_value())));
+        assert_eq!(Quantity(0).to_string(), "0x0");
+        assert_eq!(Quantity(0x1b4).to_string(), "0x1b4");
+        for invalid in &["0x", "0x10000000000000000", "1b4", "0x+1", "0x-1", "0x1b4 "] {
+            assert!(invalid.parse::<Quantity>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn u256() {
+        let value: U256 = "0x1b4".parse().unwrap();
+        assert_eq!(value, U256::from(0x1b4));
+        assert_eq!(value.as_u64(), Some(0x1b4));
+        assert_eq!("0x0".parse(), Ok(U256::default()));
+        assert_eq!(U256::default().to_string(), "0x0");
+
+        let max = format!("0x{}", "f".repeat(64));
+        let value: U256 = max.parse().unwrap();
+        assert_eq!(value, U256([0xff; 32]));
+        assert_eq!(value.as_u64(), None);
+        assert_eq!(value.to_string(), max);
+
+        // 2^64 has 17 digits, an odd number
+        let value: U256 = "0x10000000000000000".parse().unwrap();
+        assert_eq!(value.as_u64(), None);
+        assert_eq!(value.to_string(), "0x10000000000000000");
+
+        let too_large = format!("0x1{}", "0".repeat(64));
+        for invalid in &["0x", too_large.as_str(), "1b4", "0xz"] {
+            assert!(invalid.parse::<U256>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn serde_as_strings() {
+        assert_eq!(serde_json::to_value(Quantity(16)).unwrap(), json!("0x10"));
+        assert_eq!(
+            serde_json::from_value::<Bytes>(json!("0x0102")).unwrap(),
+            Bytes(vec![1, 2])
+        );
+        assert!(serde_json::from_value::<Quantity>(json!(16)).is_err());
+        assert!(serde_json::from_value::<U256>(json!("16")).is_err());
+    }
+
+    #[test]
+    fn unix_timestamp() {
+        let timestamp: UnixTimestamp = serde_json::from_value(json!(1_500_000_000)).unwrap();
+        assert_eq!(timestamp, UnixTimestamp(1_500_000_000));
+        assert_eq!(serde_json::to_value(timestamp).unwrap(), json!(1_500_000_000));
+        assert_eq!(UnixTimestamp::from(timestamp.to_system_time()), timestamp);
+        assert!(serde_json::from_value::<UnixTimestamp>(json!(-1)).is_err());
+        assert!(serde_json::from_value::<UnixTimestamp>(json!(1.5)).is_err());
+
+        // Times before the epoch become zero.
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(10);
+        assert_eq!(UnixTimestamp::from(before_epoch), UnixTimestamp(0));
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measures the latency and throughput of calls through the HTTP transport, against the in-process
+//! test server, and through an in-memory loopback transport, showing the overhead of the client
+//! itself. Run with `cargo bench --features "test-server bench"`.
+
+#[macro_use]
+extern crate criterion;
+extern crate futures;
+extern crate jsonrpc_client_core;
+extern crate jsonrpc_client_http;
+extern crate serde_json;
+extern crate tokio_core;
+
+use criterion::{Benchmark, Criterion, Throughput};
+use futures::sync::mpsc;
+use futures::{future, Future, Sink, Stream};
+use jsonrpc_client_core::{counters, ClientHandle, Transport};
+use jsonrpc_client_http::test_server::TestServer;
+use jsonrpc_client_http::{HttpHandle, HttpTransport};
+use serde_json::Value;
+use std::fmt::Debug;
+use std::{io, thread};
+use tokio_core::reactor::Core;
+
+/// The number of calls in a batch, and of calls in flight at once in the concurrent benchmarks.
+const CALLS: usize = 100;
+
+/// The size in bytes of the string sent, and echoed back, by the large payload benchmarks.
+const LARGE_PAYLOAD: usize = 1024 * 1024;
+
+/// An event loop driving a client, with the handle and the server of the HTTP transport when the
+/// client uses it.
+struct Context {
+    core: Core,
+    client: ClientHandle,
+    http: Option<(HttpHandle, TestServer)>,
+}
+
+impl Context {
+    fn http() -> Self {
+        let server = TestServer::echo().unwrap();
+        let core = Core::new().unwrap();
+        let handle = HttpTransport::new()
+            .shared(&core.handle())
+            .unwrap()
+            .handle(&server.uri())
+            .unwrap();
+        Self::new(core, handle.clone(), Some((handle, server)))
+    }
+
+    fn loopback() -> Self {
+        Self::new(Core::new().unwrap(), Loopback, None)
+    }
+
+    fn new<T: Transport + 'static>(
+        core: Core,
+        transport: T,
+        http: Option<(HttpHandle, TestServer)>,
+    ) -> Self {
+        let (client, client_handle) = transport.into_client();
+        core.handle().spawn(client.map_err(|_| ()));
+        Context {
+            core,
+            client: client_handle,
+            http,
+        }
+    }
+
+    fn run<F>(&mut self, future: F) -> F::Item
+    where
+        F: Future,
+        F::Error: Debug,
+    {
+        self.core.run(future).unwrap()
+    }
+
+    fn echo(&self, text: &str) -> impl Future<Item = Value, Error = jsonrpc_client_core::Error> {
+        self.client.call_method("echo", &(text,))
+    }
+}
+
+/// A transport echoing the parameters of every call back as the result, from a thread standing
+/// in for the server.
+struct Loopback;
+
+impl Transport for Loopback {
+    type Error = io::Error;
+    type Sink = Box<dyn Sink<SinkItem = String, SinkError = io::Error> + Send>;
+    type Stream = Box<dyn Stream<Item = String, Error = io::Error> + Send>;
+
+    fn io_pair(self) -> (Self::Sink, Self::Stream) {
+        let (request_tx, request_rx) = mpsc::unbounded::<String>();
+        let (response_tx, response_rx) = mpsc::unbounded();
+        thread::spawn(move || {
+            for request in request_rx.wait().filter_map(|request| request.ok()) {
+                if response_tx.unbounded_send(echo_response(&request)).is_err() {
+                    break;
+                }
+            }
+        });
+        let sink = request_tx
+            .sink_map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Loopback closed"));
+        let stream =
+            response_rx.map_err(|()| io::Error::new(io::ErrorKind::Other, "Loopback failed"));
+        (Box::new(sink), Box::new(stream))
+    }
+}
+
+fn echo_response(request: &str) -> String {
+    let request: Value = serde_json::from_str(request).unwrap();
+    serde_json::to_string(&json_response(&request)).unwrap()
+}
+
+fn json_response(request: &Value) -> Value {
+    let mut response = serde_json::Map::new();
+    response.insert("jsonrpc".to_owned(), Value::from("2.0"));
+    response.insert("result".to_owned(), request["params"].clone());
+    response.insert("id".to_owned(), request["id"].clone());
+    Value::Object(response)
+}
+
+/// A batch of `CALLS` echo calls, as sent over HTTP.
+fn batch_body() -> Vec<u8> {
+    let calls = (0..CALLS)
+        .map(|id| {
+            let mut call = serde_json::Map::new();
+            call.insert("jsonrpc".to_owned(), Value::from("2.0"));
+            call.insert("method".to_owned(), Value::from("echo"));
+            call.insert("params".to_owned(), Value::from(vec![id]));
+            call.insert("id".to_owned(), Value::from(id));
+            Value::Object(call)
+        }).collect();
+    serde_json::to_vec(&Value::Array(calls)).unwrap()
+}
+
+/// Prints the payloads counted by the client since the last report, to put the timings of a
+/// group of benchmarks in relation to the data moved.
+fn report_counters(group: &str) {
+    let counters = counters::snapshot();
+    counters::reset();
+    if counters.payloads_sent > 0 && counters.payloads_received > 0 {
+        println!(
+            "{}: {} payloads sent, {} bytes on average; {} received, {} bytes on average",
+            group,
+            counters.payloads_sent,
+            counters.bytes_sent / counters.payloads_sent,
+            counters.payloads_received,
+            counters.bytes_received / counters.payloads_received,
+        );
+    }
+}
+
+fn bench_transport(c: &mut Criterion, group: &str, new_context: fn() -> Context) {
+    counters::reset();
+    let mut context = new_context();
+    let small = Benchmark::new("small_call", move |b| {
+        b.iter(|| {
+            let call = context.echo("hello");
+            context.run(call)
+        })
+    });
+
+    let mut context = new_context();
+    let text = "x".repeat(LARGE_PAYLOAD);
+    let large = Benchmark::new("large_payload", move |b| {
+        b.iter(|| {
+            let call = context.echo(&text);
+            context.run(call)
+        })
+    }).throughput(Throughput::Bytes(LARGE_PAYLOAD as u32));
+
+    let mut context = new_context();
+    let concurrent = Benchmark::new("concurrent_calls", move |b| {
+        b.iter(|| {
+            let calls = (0..CALLS).map(|_| context.echo("hello")).collect::<Vec<_>>();
+            context.run(future::join_all(calls))
+        })
+    }).throughput(Throughput::Elements(CALLS as u32));
+
+    c.bench(group, small);
+    c.bench(group, large);
+    c.bench(group, concurrent);
+    report_counters(group);
+}
+
+fn http(c: &mut Criterion) {
+    bench_transport(c, "http", Context::http);
+
+    let mut context = Context::http();
+    let body = batch_body();
+    c.bench(
+        "http",
+        Benchmark::new("batch", move |b| {
+            b.iter(|| {
+                let send = context.http.as_ref().unwrap().0.send(body.clone());
+                context.run(send)
+            })
+        }).throughput(Throughput::Elements(CALLS as u32)),
+    );
+}
+
+fn loopback(c: &mut Criterion) {
+    bench_transport(c, "loopback", Context::loopback);
+}
+
+criterion_group!(benches, http, loopback);
+criterion_main!(benches);
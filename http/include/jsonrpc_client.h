@@ -0,0 +1,48 @@
+/*
+ * Copyright 2017 Amagicom AB.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+/*
+ * The C API of jsonrpc-client-http, enabled with the "ffi" feature. See the documentation of the
+ * ffi module of the crate for the ownership rules.
+ */
+
+#ifndef JSONRPC_CLIENT_H
+#define JSONRPC_CLIENT_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct JsonRpcClient JsonRpcClient;
+
+/*
+ * Receives the result of a call. Exactly one of result and error is non-null. The strings are
+ * only valid until the callback returns.
+ */
+typedef void (*JsonRpcCallback)(void *user_data, const char *result, const char *error);
+
+/* Creates a client sending calls to uri. Returns NULL on failure. */
+JsonRpcClient *jsonrpc_client_new(const char *uri);
+
+/*
+ * Calls method with the JSON parameters in params_json, or without parameters if it is NULL.
+ * Returns 0 if the call was started and callback will be called, -1 otherwise.
+ */
+int jsonrpc_client_call(JsonRpcClient *client, const char *method, const char *params_json,
+                        JsonRpcCallback callback, void *user_data);
+
+/* Releases a client. The callbacks of calls still in flight may not be called. */
+void jsonrpc_client_free(JsonRpcClient *client);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
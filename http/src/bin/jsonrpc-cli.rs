@@ -0,0 +1,378 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Command line tool for making ad-hoc JSON-RPC 2.0 calls over HTTP. Requires the `cli` feature.
+//!
+//! ```text
+//! jsonrpc-cli --url http://localhost:8545 call eth_blockNumber
+//! jsonrpc-cli --url http://localhost:8545 call eth_getBalance '["0x...", "latest"]'
+//! jsonrpc-cli --url http://localhost:8545 batch net_version '[]' eth_chainId '[]'
+//! jsonrpc-cli --url https://example.com/rpc --bearer TOKEN notify ping
+//! ```
+
+extern crate futures;
+extern crate jsonrpc_client_core;
+extern crate jsonrpc_client_http;
+extern crate serde_json;
+
+use futures::{future, Future};
+use jsonrpc_client_core::timer::Delay;
+use jsonrpc_client_core::{ClientHandle, Error, ErrorKind, Transport};
+use jsonrpc_client_http::header::{Authorization, Basic, Bearer};
+use jsonrpc_client_http::{HttpHandle, HttpTransport};
+use serde_json::{Map, Value as JsonValue};
+use std::env;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: jsonrpc-cli --url URL [OPTIONS] COMMAND
+
+Commands:
+    call METHOD [PARAMS]                 Call a method and print the response
+    notify METHOD [PARAMS]               Send a notification
+    batch METHOD PARAMS [METHOD PARAMS]  Call several methods in one batch request
+
+PARAMS is a JSON array or object, and defaults to no parameters.
+
+Options:
+    --url URL                  The URI of the server, http://, https:// or unix://
+    --basic USER:PASSWORD      Authenticate with HTTP basic authentication
+    --bearer TOKEN             Authenticate with a bearer token
+    --timeout SECONDS          Fail if the server does not respond in time
+    --tls-server-name NAME     Verify the certificate of the server against NAME
+    --help                     Print this message";
+
+/// The parsed command line.
+#[derive(Debug, Default)]
+struct Options {
+    url: Option<String>,
+    basic: Option<String>,
+    bearer: Option<String>,
+    timeout: Option<Duration>,
+    tls_server_name: Option<String>,
+    command: Vec<String>,
+}
+
+/// A call or notification of a command.
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    params: Option<JsonValue>,
+}
+
+/// The parsed command.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Call(Request),
+    Notify(Request),
+    Batch(Vec<Request>),
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => fail(&msg),
+    };
+    let url = options.url.clone().unwrap_or_else(|| fail("--url is required"));
+    let command = match build_request(&options.command) {
+        Ok(command) => command,
+        Err(msg) => fail(&msg),
+    };
+
+    let mut builder = HttpTransport::with_tls();
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+    let transport = builder
+        .standalone()
+        .unwrap_or_else(|e| fail(&format!("Unable to create transport: {}", e)));
+    let mut handle = transport
+        .handle(&url)
+        .unwrap_or_else(|e| fail(&format!("Invalid URL: {}", e)));
+    configure_handle(&mut handle, &options);
+
+    let (client, client_handle) = handle.into_client();
+    let client = match command {
+        // Sends the calls as one batch once all of them are made.
+        Command::Batch(ref requests) => client.batch_calls(
+            Duration::from_secs(0),
+            requests.len(),
+            |_: Duration| -> Delay { Box::new(future::empty()) },
+        ),
+        _ => client,
+    };
+    let client = thread::spawn(move || client.wait());
+
+    let response = match command {
+        Command::Call(request) => Some(response(call(&client_handle, request).wait())),
+        Command::Notify(request) => {
+            client_handle
+                .send_notification(request.method, &request.params)
+                .wait()
+                .unwrap_or_else(|e| fail(&format!("Request failed: {}", e)));
+            None
+        }
+        Command::Batch(requests) => {
+            let calls = requests
+                .into_iter()
+                .map(|request| call(&client_handle, request).then(Ok::<_, ()>));
+            let results = future::join_all(calls)
+                .wait()
+                .expect("the calls of a batch are never failed");
+            Some(JsonValue::Array(results.into_iter().map(response).collect()))
+        }
+    };
+
+    // Lets the client send what is left and stop, which it does once all handles are dropped.
+    drop(client_handle);
+    match client.join() {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => fail(&format!("Request failed: {}", e)),
+        Err(_) => fail("The client panicked"),
+    }
+    // The response to a notification is not read by the client, so a failed request only shows
+    // in the statistics.
+    if transport.stats().errors > 0 {
+        fail("Request failed");
+    }
+
+    let response = match response {
+        Some(response) => response,
+        None => return,
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&response).expect("JSON values can always be serialized")
+    );
+    if has_error(&response) {
+        process::exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => options.url = Some(value(&mut args, &arg)?),
+            "--basic" => options.basic = Some(value(&mut args, &arg)?),
+            "--bearer" => options.bearer = Some(value(&mut args, &arg)?),
+            "--timeout" => {
+                let seconds = value(&mut args, &arg)?
+                    .parse::<u64>()
+                    .map_err(|_| "--timeout requires a number of seconds".to_owned())?;
+                options.timeout = Some(Duration::from_secs(seconds));
+            }
+            "--tls-server-name" => options.tls_server_name = Some(value(&mut args, &arg)?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => {
+                options.command.push(arg.clone());
+                options.command.extend(args.by_ref());
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Returns the value following the option `name`.
+fn value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} requires a value", name))
+}
+
+/// Returns the calls and notifications of the command.
+fn build_request(command: &[String]) -> Result<Command, String> {
+    match command.split_first() {
+        Some((name, args)) if name == "call" && (args.len() == 1 || args.len() == 2) => {
+            Ok(Command::Call(request(&args[0], args.get(1))?))
+        }
+        Some((name, args)) if name == "notify" && (args.len() == 1 || args.len() == 2) => {
+            Ok(Command::Notify(request(&args[0], args.get(1))?))
+        }
+        Some((name, args)) if name == "batch" && !args.is_empty() && args.len() % 2 == 0 => {
+            let requests = args
+                .chunks(2)
+                .map(|call| request(&call[0], Some(&call[1])))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Command::Batch(requests))
+        }
+        _ => Err("Invalid command".to_owned()),
+    }
+}
+
+/// Parses the parameters of a call or notification.
+fn request(method: &str, params: Option<&String>) -> Result<Request, String> {
+    let params = match params {
+        Some(params) => {
+            let params: JsonValue = serde_json::from_str(params)
+                .map_err(|e| format!("Invalid parameters for {}: {}", method, e))?;
+            if !params.is_array() && !params.is_object() {
+                return Err(format!("Parameters for {} must be an array or object", method));
+            }
+            Some(params)
+        }
+        None => None,
+    };
+    Ok(Request {
+        method: method.to_owned(),
+        params,
+    })
+}
+
+fn call(handle: &ClientHandle, request: Request) -> impl Future<Item = JsonValue, Error = Error> {
+    handle.call_method(request.method, &request.params)
+}
+
+/// Returns the outcome of a call as a response object without the `jsonrpc` and `id` members.
+/// Fails if the call failed for another reason than a JSON-RPC error.
+fn response(result: Result<JsonValue, Error>) -> JsonValue {
+    let mut response = Map::new();
+    match result {
+        Ok(result) => {
+            response.insert("result".to_owned(), result);
+        }
+        Err(Error(ErrorKind::JsonRpcError(error), _)) => {
+            let error =
+                serde_json::to_value(error).expect("JSON-RPC errors can always be serialized");
+            response.insert("error".to_owned(), error);
+        }
+        Err(e) => fail(&format!("Request failed: {}", e)),
+    }
+    JsonValue::Object(response)
+}
+
+fn configure_handle(handle: &mut HttpHandle, options: &Options) {
+    if let Some(ref credentials) = options.basic {
+        let mut parts = credentials.splitn(2, ':');
+        handle.set_header(Authorization(Basic {
+            username: parts.next().unwrap_or("").to_owned(),
+            password: parts.next().map(str::to_owned),
+        }));
+    }
+    if let Some(ref token) = options.bearer {
+        handle.set_header(Authorization(Bearer {
+            token: token.clone(),
+        }));
+    }
+    if let Some(ref server_name) = options.tls_server_name {
+        handle
+            .set_tls_server_name(server_name.clone())
+            .unwrap_or_else(|e| fail(&format!("Unable to set TLS server name: {}", e)));
+    }
+}
+
+/// Returns true if the response, or any response in a batch, is an error.
+fn has_error(response: &JsonValue) -> bool {
+    match response {
+        JsonValue::Array(responses) => responses.iter().any(has_error),
+        JsonValue::Object(fields) => fields.contains_key("error"),
+        _ => false,
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn request(method: &str, params: Option<JsonValue>) -> Request {
+        Request {
+            method: method.to_owned(),
+            params,
+        }
+    }
+
+    #[test]
+    fn options() {
+        let options = parse_options(
+            args(&[
+                "--url",
+                "http://localhost:8545",
+                "--basic",
+                "user:pass",
+                "--timeout",
+                "5",
+                "call",
+                "echo",
+                "--url",
+            ]).into_iter(),
+        ).unwrap();
+        assert_eq!(options.url, Some("http://localhost:8545".to_owned()));
+        assert_eq!(options.basic, Some("user:pass".to_owned()));
+        assert_eq!(options.bearer, None);
+        assert_eq!(options.timeout, Some(Duration::from_secs(5)));
+        // Everything after the command belongs to it.
+        assert_eq!(options.command, args(&["call", "echo", "--url"]));
+    }
+
+    #[test]
+    fn invalid_options() {
+        let error = |arguments: &[&str]| parse_options(args(arguments).into_iter()).unwrap_err();
+        assert_eq!(error(&["--verbose"]), "Unknown option --verbose");
+        assert_eq!(error(&["--url"]), "--url requires a value");
+        assert_eq!(
+            error(&["--timeout", "soon"]),
+            "--timeout requires a number of seconds"
+        );
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            build_request(&args(&["call", "eth_blockNumber"])),
+            Ok(Command::Call(request("eth_blockNumber", None)))
+        );
+        assert_eq!(
+            build_request(&args(&["call", "eth_getBalance", r#"["0x1", "latest"]"#])),
+            Ok(Command::Call(request(
+                "eth_getBalance",
+                Some(serde_json::from_str(r#"["0x1", "latest"]"#).unwrap())
+            )))
+        );
+        assert_eq!(
+            build_request(&args(&["notify", "ping", r#"{"a": 1}"#])),
+            Ok(Command::Notify(request(
+                "ping",
+                Some(serde_json::from_str(r#"{"a": 1}"#).unwrap())
+            )))
+        );
+        assert_eq!(
+            build_request(&args(&["batch", "net_version", "[]", "eth_chainId", "[]"])),
+            Ok(Command::Batch(vec![
+                request("net_version", Some(JsonValue::Array(vec![]))),
+                request("eth_chainId", Some(JsonValue::Array(vec![]))),
+            ]))
+        );
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let error = |command: &[&str]| build_request(&args(command)).unwrap_err();
+        assert_eq!(error(&[]), "Invalid command");
+        assert_eq!(error(&["get", "eth_blockNumber"]), "Invalid command");
+        assert_eq!(error(&["call"]), "Invalid command");
+        assert_eq!(error(&["batch", "net_version"]), "Invalid command");
+        assert_eq!(
+            error(&["call", "echo", "1"]),
+            "Parameters for echo must be an array or object"
+        );
+        assert!(error(&["call", "echo", "[1,"]).starts_with("Invalid parameters for echo: "));
+    }
+}
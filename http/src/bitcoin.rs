@@ -0,0 +1,274 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Clients for the RPC interface of Bitcoin Core.
+//!
+//! Bitcoin Core speaks a JSON-RPC 1.0 dialect. Responses lack the `jsonrpc` field and carry both
+//! `result` and `error`, and failed calls are answered with error status codes like
+//! `500 Internal Server Error`. Connections made through [`BitcoinCore`](struct.BitcoinCore.html)
+//! are configured for this, with lenient response parsing and JSON error bodies read as
+//! responses, and authenticate with the `rpcuser` and `rpcpassword` of the node. The password can
+//! also be read from a [`CredentialStore`](../trait.CredentialStore.html) with
+//! [`BitcoinCore::from_store`](struct.BitcoinCore.html#method.from_store).
+//!
+//! ```rust,no_run
+//! # extern crate futures;
+//! # extern crate jsonrpc_client_http;
+//! # use futures::Future;
+//! # use jsonrpc_client_http::HttpTransport;
+//! # use jsonrpc_client_http::bitcoin::BitcoinCore;
+//! # fn main() {
+//! let transport = HttpTransport::new().standalone().unwrap();
+//! let node = BitcoinCore::new(&transport, "http://127.0.0.1:8332/", "user", "pass").unwrap();
+//!
+//! let (client, mut rpc) = node.node();
+//! ::std::thread::spawn(move || client.wait());
+//! println!("Block count: {}", rpc.getblockcount().wait().unwrap());
+//!
+//! let (client, mut wallet) = node.wallet("savings").unwrap();
+//! ::std::thread::spawn(move || client.wait());
+//! println!("Balance: {}", wallet.getbalance().wait().unwrap());
+//! # }
+//! ```
+
+use jsonrpc_client_core::server::Server;
+use jsonrpc_client_core::{Client, ClientHandle, ResponseParsing, Transport};
+use serde_json::Value as JsonValue;
+
+use super::header::{Authorization, Basic};
+use super::{CredentialAuth, CredentialStore, HttpHandle, HttpTransport, Result};
+
+jsonrpc_client!(
+    /// A client for the methods of a Bitcoin Core node that do not involve a wallet.
+    pub struct NodeClient {
+        /// Returns the number of blocks in the longest chain.
+        pub fn getblockcount(&mut self) -> Future<u64>;
+
+        /// Returns the hash of the tip of the longest chain.
+        pub fn getbestblockhash(&mut self) -> Future<String>;
+
+        /// Returns the hash of the block at the given height in the longest chain.
+        pub fn getblockhash(&mut self, height: u64) -> Future<String>;
+
+        /// Returns the block with the given hash. Verbosity 0 returns the block as hex, 1 as an
+        /// object with transaction ids and 2 as an object with full transactions.
+        pub fn getblock<T>(&mut self, hash: &str, verbosity: u8) -> Future<T>;
+
+        /// Returns the header of the block with the given hash, as an object or as hex.
+        pub fn getblockheader<T>(&mut self, hash: &str, verbose: bool) -> Future<T>;
+
+        /// Returns information about the state of the block chain.
+        pub fn getblockchaininfo(&mut self) -> Future<JsonValue>;
+
+        /// Returns information about the peer-to-peer networking of the node.
+        pub fn getnetworkinfo(&mut self) -> Future<JsonValue>;
+
+        /// Returns the number of connections to other nodes.
+        pub fn getconnectioncount(&mut self) -> Future<u64>;
+
+        /// Returns information about the memory pool.
+        pub fn getmempoolinfo(&mut self) -> Future<JsonValue>;
+
+        /// Returns the ids of the transactions in the memory pool.
+        pub fn getrawmempool(&mut self) -> Future<Vec<String>>;
+
+        /// Returns the transaction with the given id, as hex or as an object.
+        pub fn getrawtransaction<T>(&mut self, txid: &str, verbose: bool) -> Future<T>;
+
+        /// Decodes a transaction given as hex.
+        pub fn decoderawtransaction(&mut self, hex: &str) -> Future<JsonValue>;
+
+        /// Submits a signed transaction given as hex, returning its id.
+        pub fn sendrawtransaction(&mut self, hex: &str) -> Future<String>;
+
+        /// Estimates the fee rate needed for a transaction to confirm within the given number of
+        /// blocks.
+        pub fn estimatesmartfee(&mut self, conf_target: u16) -> Future<JsonValue>;
+
+        /// Returns the names of the wallets loaded by the node.
+        pub fn listwallets(&mut self) -> Future<Vec<String>>;
+
+        /// Loads the wallet with the given name.
+        pub fn loadwallet(&mut self, name: &str) -> Future<JsonValue>;
+
+        /// Unloads the wallet with the given name.
+        pub fn unloadwallet(&mut self, name: &str);
+
+        /// Returns how long the node has been running, in seconds.
+        pub fn uptime(&mut self) -> Future<u64>;
+    }
+);
+
+jsonrpc_client!(
+    /// A client for the methods of a wallet loaded by a Bitcoin Core node.
+    pub struct WalletClient {
+        /// Returns information about the wallet.
+        pub fn getwalletinfo(&mut self) -> Future<JsonValue>;
+
+        /// Returns the confirmed balance of the wallet, in bitcoin.
+        pub fn getbalance(&mut self) -> Future<f64>;
+
+        /// Returns a new address for receiving payments, with the given label.
+        pub fn getnewaddress(&mut self, label: &str) -> Future<String>;
+
+        /// Returns information about an address.
+        pub fn getaddressinfo(&mut self, address: &str) -> Future<JsonValue>;
+
+        /// Returns the unspent outputs of the wallet with between `minconf` and `maxconf`
+        /// confirmations.
+        pub fn listunspent(&mut self, minconf: u32, maxconf: u32) -> Future<Vec<JsonValue>>;
+
+        /// Returns up to `count` of the most recent transactions of the wallet, after skipping
+        /// `skip` of them.
+        pub fn listtransactions(
+            &mut self,
+            label: &str,
+            count: u32,
+            skip: u32
+        ) -> Future<Vec<JsonValue>>;
+
+        /// Returns the wallet transaction with the given id.
+        pub fn gettransaction(&mut self, txid: &str) -> Future<JsonValue>;
+
+        /// Sends an amount in bitcoin to an address, returning the id of the transaction.
+        pub fn sendtoaddress(&mut self, address: &str, amount: f64) -> Future<String>;
+
+        /// Signs a transaction given as hex with the keys of the wallet.
+        pub fn signrawtransactionwithwallet(&mut self, hex: &str) -> Future<JsonValue>;
+
+        /// Unlocks an encrypted wallet for the given number of seconds.
+        pub fn walletpassphrase(&mut self, passphrase: &str, timeout: u64);
+
+        /// Locks an encrypted wallet.
+        pub fn walletlock(&mut self);
+    }
+);
+
+/// A connection to the RPC interface of a Bitcoin Core node, creating clients for the node and
+/// for its wallets.
+#[derive(Debug, Clone)]
+pub struct BitcoinCore {
+    handle: HttpHandle,
+}
+
+impl BitcoinCore {
+    /// Connects to the node at `uri`, like `http://127.0.0.1:8332/`, authenticating with the
+    /// given user name and password.
+    pub fn new(transport: &HttpTransport, uri: &str, user: &str, password: &str) -> Result<Self> {
+        let handle = transport.handle(uri)?;
+        Ok(Self::from_handle(handle, user, password))
+    }
+
+    /// Connects to a node through an existing handle, which keeps its other settings.
+    pub fn from_handle(mut handle: HttpHandle, user: &str, password: &str) -> Self {
+        handle
+            .set_header(Authorization(Basic {
+                username: user.to_owned(),
+                password: Some(password.to_owned()),
+            })).set_json_error_bodies(true);
+        BitcoinCore { handle }
+    }
+
+    /// Connects to a node through an existing handle, authenticating with the given user name and
+    /// the password named `password` in `store`. The password is looked up for every request, so
+    /// that a rotated password is picked up.
+    pub fn from_store(
+        mut handle: HttpHandle,
+        user: &str,
+        store: impl CredentialStore + 'static,
+        password: &str,
+    ) -> Self {
+        handle
+            .set_signer(CredentialAuth::basic(store, user, password))
+            .set_json_error_bodies(true);
+        BitcoinCore { handle }
+    }
+
+    /// Returns the handle requests to the node are sent through.
+    pub fn handle(&self) -> &HttpHandle {
+        &self.handle
+    }
+
+    /// Creates a client for the methods of the node. The `Client` future must be polled for the
+    /// calls to be sent.
+    pub fn node(&self) -> (Client<HttpHandle, Server>, NodeClient) {
+        let (client, client_handle) = lenient_client(self.handle.clone());
+        (client, NodeClient::new(client_handle))
+    }
+
+    /// Creates a client for the wallet with the given name, whose calls are sent to the
+    /// `/wallet/<name>` path of the node. The default wallet of the node has the empty name. The
+    /// `Client` future must be polled for the calls to be sent.
+    pub fn wallet(&self, name: &str) -> Result<(Client<HttpHandle, Server>, WalletClient)> {
+        let handle = self.handle.with_path(&wallet_path(name))?;
+        let (client, client_handle) = lenient_client(handle);
+        Ok((client, WalletClient::new(client_handle)))
+    }
+}
+
+/// Creates a client parsing the JSON-RPC 1.0 responses of Bitcoin Core.
+fn lenient_client(handle: HttpHandle) -> (Client<HttpHandle, Server>, ClientHandle) {
+    let (client, client_handle) = handle.into_client();
+    (
+        client.response_parsing(ResponseParsing::Lenient),
+        client_handle,
+    )
+}
+
+/// Returns the path of the wallet with the given name, percent-encoding everything in the name
+/// except unreserved characters.
+fn wallet_path(name: &str) -> String {
+    let mut path = String::from("/wallet/");
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                path.push(byte as char)
+            }
+            _ => path.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Secret;
+
+    #[test]
+    fn wallet_paths() {
+        assert_eq!(wallet_path(""), "/wallet/");
+        assert_eq!(wallet_path("savings"), "/wallet/savings");
+        assert_eq!(wallet_path("my wallet/2"), "/wallet/my%20wallet%2F2");
+        assert_eq!(wallet_path("plånbok"), "/wallet/pl%C3%A5nbok");
+    }
+
+    #[test]
+    fn wallet_handle_uri() {
+        let transport = HttpTransport::new().standalone().unwrap();
+        let node = BitcoinCore::new(&transport, "http://127.0.0.1:8332/", "user", "pass").unwrap();
+        let (_client, _wallet) = node.wallet("savings").unwrap();
+        let wallet_handle = node.handle().with_path(&wallet_path("savings")).unwrap();
+        assert_eq!(
+            wallet_handle.uri.to_string(),
+            "http://127.0.0.1:8332/wallet/savings"
+        );
+        assert!(node.handle().json_errors);
+    }
+
+    #[test]
+    fn password_from_store() {
+        let store = |_: &str| -> Result<Secret> { Ok(Secret::new("pass")) };
+        let transport = HttpTransport::new().standalone().unwrap();
+        let handle = transport.handle("http://127.0.0.1:8332/").unwrap();
+        let node = BitcoinCore::from_store(handle, "user", store, "bitcoin-rpc");
+        let wallet_handle = node.handle().with_path(&wallet_path("savings")).unwrap();
+        assert!(wallet_handle.signer.is_some());
+        assert!(wallet_handle.json_errors);
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::future::{self, Either};
+use futures::sync::oneshot;
+use futures::Future;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// Puts the calls of an ordered `HttpHandle`, and its clones, in line. Every call waits for the
+/// previous call to finish before it is sent.
+#[derive(Debug, Clone, Default)]
+pub struct CallQueue {
+    // completes when the last call in line is finished
+    tail: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+}
+
+impl CallQueue {
+    /// Puts a call last in line. Returns a future resolving when it is the turn of the call, and
+    /// a sender to complete, or drop, when the call is finished.
+    pub fn enqueue(&self) -> (impl Future<Item = (), Error = ()> + Send, oneshot::Sender<()>) {
+        let (done_tx, done_rx) = oneshot::channel();
+        let previous = mem::replace(&mut *self.tail.lock().unwrap(), Some(done_rx));
+        let turn = match previous {
+            // A dropped sender means the previous call is finished, or was abandoned.
+            Some(previous) => Either::A(previous.then(|_| Ok(()))),
+            None => Either::B(future::ok(())),
+        };
+        (turn, done_tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Async;
+
+    #[test]
+    fn calls_wait_for_previous() {
+        let queue = CallQueue::default();
+        let (mut first, first_done) = queue.enqueue();
+        let (mut second, second_done) = queue.enqueue();
+        let (mut third, _third_done) = queue.enqueue();
+        future::lazy(move || {
+            assert_eq!(first.poll(), Ok(Async::Ready(())));
+            assert_eq!(second.poll(), Ok(Async::NotReady));
+            first_done.send(()).unwrap();
+            assert_eq!(second.poll(), Ok(Async::Ready(())));
+            assert_eq!(third.poll(), Ok(Async::NotReady));
+            drop(second_done);
+            assert_eq!(third.poll(), Ok(Async::Ready(())));
+            Ok::<_, ()>(())
+        }).wait()
+        .unwrap();
+    }
+}
@@ -1,38 +1,63 @@
+use connector::{SocketConnector, SocketOptions};
 use hyper::client::{Client, Connect, HttpConnector};
 use hyper::Body;
+use std::fmt;
 use std::io;
 use tokio_core::reactor::Handle;
+use unix_socket::UnixConnector;
+
+/// Number of threads in the thread pool doing DNS resolutions.
+/// Since DNS is resolved via blocking syscall they must be run on separate threads.
+static DNS_THREADS: usize = 4;
 
 /// Trait for types able to produce Hyper `Client`s for use in `HttpTransport`.
 pub trait ClientCreator: Send + 'static {
     /// The connector type inside the `Client` created by this type.
     type Connect: Connect;
 
-    /// The error emitted by this type in case creating the `Client` failed.
-    type Error: ::std::error::Error + Send;
+    /// The error emitted by this type in case creating the `Client` failed. It is chained to the
+    /// `ClientCreatorError`, so any displayable type that can be sent between threads works,
+    /// including boxed errors.
+    type Error: fmt::Debug + fmt::Display + Send + 'static;
 
     /// Tries to create a Hyper `Client` based on the given Tokio `Handle`.
     fn create(&self, handle: &Handle) -> Result<Client<Self::Connect, Body>, Self::Error>;
 }
 
-/// Default `Client` creator that defaults to creating a standard `Client` with just
-/// `hyper::Client::new(handle)`.
+/// Default `Client` creator that defaults to creating a standard `Client` with the same settings
+/// as `hyper::Client::new(handle)`, with the connector wrapped in a `SocketConnector` and a
+/// `UnixConnector`.
 #[derive(Debug, Default)]
-pub struct DefaultClient;
+pub struct DefaultClient {
+    socket_options: SocketOptions,
+}
+
+impl DefaultClient {
+    /// Sets the options of the TCP sockets of the clients created by this creator.
+    pub fn set_socket_options(&mut self, options: SocketOptions) {
+        self.socket_options = options;
+    }
+}
 
 impl ClientCreator for DefaultClient {
-    type Connect = HttpConnector;
+    type Connect = UnixConnector<SocketConnector<HttpConnector>>;
     type Error = io::Error;
 
-    fn create(&self, handle: &Handle) -> Result<Client<HttpConnector, Body>, io::Error> {
-        Ok(Client::new(handle))
+    fn create(
+        &self,
+        handle: &Handle,
+    ) -> Result<Client<UnixConnector<SocketConnector<HttpConnector>>, Body>, io::Error> {
+        let http = HttpConnector::new(DNS_THREADS, handle);
+        let connector = SocketConnector::new(http, self.socket_options, handle);
+        let connector = UnixConnector::new(connector, handle);
+        Ok(Client::configure().connector(connector).build(handle))
     }
 }
 
 impl<C, E, F> ClientCreator for F
 where
     C: Connect,
-    E: ::std::error::Error + Send,
+    E: fmt::Debug + fmt::Display + Send + 'static,
     F: Fn(&Handle) -> Result<Client<C, Body>, E>,
     F: Send + 'static,
 {
@@ -44,32 +69,81 @@ where
     }
 }
 
+/// `Client` creator building a Hyper `Client` with default settings around connectors made by a
+/// function. Created by
+/// [`HttpTransportBuilder::with_connector`](struct.HttpTransportBuilder.html#method.with_connector).
+#[derive(Debug)]
+pub struct ConnectorCreator<F> {
+    connector_factory: F,
+}
+
+impl<F> ConnectorCreator<F> {
+    /// Creates a `ConnectorCreator` calling `connector_factory` for every `Client` it creates.
+    pub fn new(connector_factory: F) -> Self {
+        ConnectorCreator { connector_factory }
+    }
+}
+
+impl<C, E, F> ClientCreator for ConnectorCreator<F>
+where
+    C: Connect,
+    E: fmt::Debug + fmt::Display + Send + 'static,
+    F: Fn(&Handle) -> Result<C, E>,
+    F: Send + 'static,
+{
+    type Connect = C;
+    type Error = E;
+
+    fn create(&self, handle: &Handle) -> Result<Client<C, Body>, E> {
+        let connector = (self.connector_factory)(handle)?;
+        Ok(Client::configure().connector(connector).build(handle))
+    }
+}
+
 
 #[cfg(feature = "tls")]
 mod tls {
     use super::*;
-    use hyper_tls::HttpsConnector;
     use native_tls::Error;
+    use server_name::{HttpsSniConnector, TlsServerNames};
 
-    /// Number of threads in the thread pool doing DNS resolutions.
-    /// Since DNS is resolved via blocking syscall they must be run on separate threads.
-    static DNS_THREADS: usize = 2;
+    /// Number of threads in the thread pool doing DNS resolutions for TLS enabled clients.
+    static TLS_DNS_THREADS: usize = 2;
 
     /// Default `Client` creator for TLS enabled clients. Creates a Hyper `Client` based on
-    /// `hyper_tls::HttpsConnector`.
+    /// `HttpsSniConnector`, wrapped in a `UnixConnector`.
     #[derive(Debug, Default)]
-    pub struct DefaultTlsClient;
+    pub struct DefaultTlsClient {
+        server_names: TlsServerNames,
+        socket_options: SocketOptions,
+    }
+
+    impl DefaultTlsClient {
+        /// Returns the TLS server name overrides used by the clients created by this creator.
+        pub fn server_names(&self) -> &TlsServerNames {
+            &self.server_names
+        }
+
+        /// Sets the options of the TCP sockets of the clients created by this creator.
+        pub fn set_socket_options(&mut self, options: SocketOptions) {
+            self.socket_options = options;
+        }
+    }
 
     impl ClientCreator for DefaultTlsClient {
-        type Connect = HttpsConnector<HttpConnector>;
+        type Connect = UnixConnector<HttpsSniConnector>;
         type Error = Error;
 
         fn create(
             &self,
             handle: &Handle,
-        ) -> Result<Client<HttpsConnector<HttpConnector>, Body>, Error> {
-            let connector = HttpsConnector::new(DNS_THREADS, handle)?;
-            let client = Client::configure().connector(connector).build(handle);
+        ) -> Result<Client<UnixConnector<HttpsSniConnector>, Body>, Error> {
+            let connector =
+                HttpsSniConnector::new(TLS_DNS_THREADS, handle, self.server_names.clone())?
+                    .socket_options(self.socket_options);
+            let client = Client::configure()
+                .connector(UnixConnector::new(connector, handle))
+                .build(handle);
             Ok(client)
         }
     }
@@ -0,0 +1,137 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use flate2::write::GzEncoder;
+use flate2::Compression as Level;
+use hyper::header::{AcceptEncoding, Encoding, Headers};
+use hyper::Uri;
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Compression of request bodies. Response bodies are never compressed by this library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Request bodies are sent uncompressed. This is the default.
+    None,
+    /// Request bodies of at least the given number of bytes are compressed with gzip and sent with
+    /// a `Content-Encoding: gzip` header, once the server has advertised support for gzip by
+    /// including it in an `Accept-Encoding` header in a response.
+    GzipIfAdvertised(usize),
+    /// Request bodies of at least the given number of bytes are always compressed with gzip and
+    /// sent with a `Content-Encoding: gzip` header.
+    Gzip(usize),
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+/// The compression setting of a transport, together with the servers that have advertised gzip
+/// support. Shared between the handles and the event loops of the transport.
+#[derive(Debug, Clone)]
+pub struct CompressionState {
+    compression: Compression,
+    gzip_hosts: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CompressionState {
+    pub fn new(compression: Compression) -> Self {
+        CompressionState {
+            compression,
+            gzip_hosts: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Remembers if the server at `uri` advertised gzip support in the response headers.
+    pub fn record_response(&self, uri: &Uri, headers: &Headers) {
+        if let Compression::GzipIfAdvertised(_) = self.compression {
+            let accepts_gzip = headers
+                .get::<AcceptEncoding>()
+                .map(|encodings| {
+                    encodings
+                        .iter()
+                        .any(|encoding| encoding.item == Encoding::Gzip)
+                }).unwrap_or(false);
+            if let (true, Some(authority)) = (accepts_gzip, uri.authority()) {
+                self.gzip_hosts.lock().unwrap().insert(authority.to_owned());
+            }
+        }
+    }
+
+    /// Compresses the body if it should be compressed when sent to `uri`. Returns the body to send
+    /// and whether it was compressed.
+    pub fn compress(&self, uri: &Uri, body: Vec<u8>) -> (Vec<u8>, bool) {
+        let should_compress = match self.compression {
+            Compression::None => false,
+            Compression::Gzip(min_size) => body.len() >= min_size,
+            Compression::GzipIfAdvertised(min_size) => {
+                body.len() >= min_size && uri.authority().map_or(false, |authority| {
+                    self.gzip_hosts.lock().unwrap().contains(authority)
+                })
+            }
+        };
+        if !should_compress {
+            return (body, false);
+        }
+        match gzip(&body) {
+            Ok(compressed) => (compressed, true),
+            Err(e) => {
+                warn!("Unable to compress request body, sending uncompressed: {}", e);
+                (body, false)
+            }
+        }
+    }
+}
+
+fn gzip(body: &[u8]) -> ::std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Level::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use hyper::header::qitem;
+    use std::io::Read;
+    use std::str::FromStr;
+
+    #[test]
+    fn compresses_only_after_advertisement() {
+        let state = CompressionState::new(Compression::GzipIfAdvertised(10));
+        let uri = Uri::from_str("http://127.0.0.1:1234/").unwrap();
+        let body = vec![b'a'; 100];
+
+        let (sent, compressed) = state.compress(&uri, body.clone());
+        assert!(!compressed);
+        assert_eq!(sent, body);
+
+        let mut headers = Headers::new();
+        headers.set(AcceptEncoding(vec![qitem(Encoding::Gzip)]));
+        state.record_response(&uri, &headers);
+
+        let (sent, compressed) = state.compress(&uri, body.clone());
+        assert!(compressed);
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&sent[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+    }
+
+    #[test]
+    fn small_bodies_are_not_compressed() {
+        let state = CompressionState::new(Compression::Gzip(1000));
+        let uri = Uri::from_str("http://127.0.0.1:1234/").unwrap();
+        assert!(!state.compress(&uri, vec![b'a'; 100]).1);
+    }
+}
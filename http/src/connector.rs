@@ -0,0 +1,359 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::future::{self, Either};
+use futures::{Async, Future, Poll};
+use futures_cpupool::CpuPool;
+use hyper::Uri;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tokio_core::net::{TcpStream, TcpStreamNew};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_service::Service;
+
+/// Delay before starting the next connection attempt while the previous one is still pending,
+/// as recommended by RFC 8305.
+const DEFAULT_ATTEMPT_DELAY_MS: u64 = 250;
+
+/// An IP address family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    /// IPv4.
+    Ipv4,
+    /// IPv6.
+    Ipv6,
+}
+
+/// A Hyper connector implementing the Happy Eyeballs algorithm from RFC 8305.
+///
+/// When a host resolves to both IPv6 and IPv4 addresses, the addresses are tried alternating
+/// between the families, starting with the preferred family. If an attempt has not succeeded
+/// within the attempt delay, the next attempt is started without aborting the first one, and the
+/// first connection to be established is used. A broken route for one of the families thus only
+/// delays the connection instead of stalling it until the connect times out.
+///
+/// Use it with a custom Hyper `Client`:
+///
+/// ```rust,no_run
+/// # extern crate hyper;
+/// # extern crate jsonrpc_client_http;
+/// # use std::io;
+/// # use jsonrpc_client_http::{Family, Handle, HappyEyeballsConnector, HttpTransportBuilder};
+/// # fn main() {
+/// HttpTransportBuilder::with_client(|handle: &Handle| {
+///     let connector = HappyEyeballsConnector::new(2, handle).prefer(Family::Ipv4);
+///     Ok(hyper::Client::configure().connector(connector).build(handle)) as Result<_, io::Error>
+/// }).standalone()
+/// .unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HappyEyeballsConnector {
+    handle: Handle,
+    resolver: CpuPool,
+    preference: Family,
+    attempt_delay: Duration,
+}
+
+impl HappyEyeballsConnector {
+    /// Creates a new connector resolving host names on a pool of `dns_threads` threads, since
+    /// resolving is done with blocking system calls. Prefers IPv6 by default.
+    pub fn new(dns_threads: usize, handle: &Handle) -> Self {
+        HappyEyeballsConnector {
+            handle: handle.clone(),
+            resolver: CpuPool::new(dns_threads),
+            preference: Family::Ipv6,
+            attempt_delay: Duration::from_millis(DEFAULT_ATTEMPT_DELAY_MS),
+        }
+    }
+
+    /// Sets the address family to try first.
+    pub fn prefer(mut self, family: Family) -> Self {
+        self.preference = family;
+        self
+    }
+
+    /// Sets how long to wait for a connection attempt before starting the next one in parallel.
+    pub fn attempt_delay(mut self, delay: Duration) -> Self {
+        self.attempt_delay = delay;
+        self
+    }
+}
+
+impl Service for HappyEyeballsConnector {
+    type Request = Uri;
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = TcpStream, Error = io::Error>>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        let host = match uri.host() {
+            Some(host) => host.trim_matches(|c| c == '[' || c == ']').to_owned(),
+            None => {
+                return Box::new(::futures::future::err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "URI has no host",
+                )))
+            }
+        };
+        let port = uri.port().unwrap_or(match uri.scheme() {
+            Some("https") => 443,
+            _ => 80,
+        });
+        let handle = self.handle.clone();
+        let preference = self.preference;
+        let attempt_delay = self.attempt_delay;
+        let addresses = self.resolver.spawn_fn(move || {
+            (host.as_str(), port)
+                .to_socket_addrs()
+                .map(|addresses| addresses.collect::<Vec<_>>())
+        });
+        Box::new(addresses.and_then(move |addresses| {
+            ConnectRace::new(
+                interleave_families(addresses, preference),
+                attempt_delay,
+                handle,
+            )
+        }))
+    }
+}
+
+/// Orders the addresses alternating between the families, starting with the preferred one.
+fn interleave_families(addresses: Vec<SocketAddr>, preference: Family) -> VecDeque<SocketAddr> {
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) =
+        addresses.into_iter().partition(|address| match preference {
+            Family::Ipv6 => address.is_ipv6(),
+            Family::Ipv4 => address.is_ipv4(),
+        });
+    let mut ordered = VecDeque::with_capacity(preferred.len() + other.len());
+    loop {
+        match (preferred.pop_front(), other.pop_front()) {
+            (None, None) => return ordered,
+            (first, second) => {
+                ordered.extend(first);
+                ordered.extend(second);
+            }
+        }
+    }
+}
+
+/// Future racing staggered connection attempts to a list of addresses.
+struct ConnectRace {
+    addresses: VecDeque<SocketAddr>,
+    attempts: Vec<TcpStreamNew>,
+    attempt_delay: Duration,
+    next_attempt: Option<Timeout>,
+    last_error: Option<io::Error>,
+    handle: Handle,
+}
+
+impl ConnectRace {
+    fn new(addresses: VecDeque<SocketAddr>, attempt_delay: Duration, handle: Handle) -> Self {
+        ConnectRace {
+            addresses,
+            attempts: Vec::new(),
+            attempt_delay,
+            next_attempt: None,
+            last_error: None,
+            handle,
+        }
+    }
+
+    fn start_next_attempt(&mut self) -> io::Result<()> {
+        if let Some(address) = self.addresses.pop_front() {
+            trace!("Connecting to {}", address);
+            self.attempts
+                .push(TcpStream::connect(&address, &self.handle));
+            self.next_attempt = match self.next_attempt.take() {
+                Some(mut timeout) => {
+                    timeout.reset(Instant::now() + self.attempt_delay);
+                    Some(timeout)
+                }
+                None => Some(Timeout::new(self.attempt_delay, &self.handle)?),
+            };
+        }
+        Ok(())
+    }
+}
+
+impl Future for ConnectRace {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<TcpStream, io::Error> {
+        loop {
+            let mut index = 0;
+            while index < self.attempts.len() {
+                match self.attempts[index].poll() {
+                    Ok(Async::Ready(stream)) => return Ok(Async::Ready(stream)),
+                    Ok(Async::NotReady) => index += 1,
+                    Err(e) => {
+                        debug!("Connection attempt failed: {}", e);
+                        self.attempts.remove(index);
+                        self.last_error = Some(e);
+                    }
+                }
+            }
+
+            if self.addresses.is_empty() {
+                if self.attempts.is_empty() {
+                    return Err(self.last_error.take().unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "Host resolved to no addresses")
+                    }));
+                }
+                return Ok(Async::NotReady);
+            }
+
+            // Start the next attempt right away if all attempts failed, otherwise when the
+            // attempt delay has passed.
+            let delay_passed = match self.next_attempt {
+                Some(ref mut timeout) => timeout.poll()?.is_ready(),
+                None => true,
+            };
+            if self.attempts.is_empty() || delay_passed {
+                self.start_next_attempt()?;
+            } else {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+/// Options for the TCP sockets of the connections to servers. The defaults leave the sockets as
+/// the operating system creates them, without a connect timeout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Disables Nagle's algorithm with `TCP_NODELAY`, so small requests are sent right away
+    /// instead of waiting for more data. Recommended for RPC calls.
+    pub nodelay: bool,
+    /// Enables TCP keepalive, with the given time a connection has to be idle before keepalive
+    /// probes are sent, to detect dead connections in the pool.
+    pub keepalive: Option<Duration>,
+    /// The maximum time to wait for a connection to be established, separate from the timeout
+    /// of the whole request. Fails the connection with `TimedOut` when it passes.
+    pub connect_timeout: Option<Duration>,
+}
+
+impl SocketOptions {
+    fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        if self.keepalive.is_some() {
+            stream.set_keepalive(self.keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+/// A connector applying `SocketOptions` to the TCP connections made by another connector, like
+/// Hyper's `HttpConnector` or the [`HappyEyeballsConnector`](struct.HappyEyeballsConnector.html).
+/// The default clients use it, configured with
+/// [`HttpTransportBuilder::socket_options`](struct.HttpTransportBuilder.html#method.socket_options).
+#[derive(Debug, Clone)]
+pub struct SocketConnector<C> {
+    inner: C,
+    options: SocketOptions,
+    handle: Handle,
+}
+
+impl<C> SocketConnector<C> {
+    /// Wraps `inner`, applying `options` to its connections.
+    pub fn new(inner: C, options: SocketOptions, handle: &Handle) -> Self {
+        SocketConnector {
+            inner,
+            options,
+            handle: handle.clone(),
+        }
+    }
+
+    /// Sets the options applied to new connections.
+    pub fn set_options(&mut self, options: SocketOptions) {
+        self.options = options;
+    }
+}
+
+impl<C> Service for SocketConnector<C>
+where
+    C: Service<Request = Uri, Response = TcpStream, Error = io::Error>,
+    C::Future: 'static,
+{
+    type Request = Uri;
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = TcpStream, Error = io::Error>>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        let options = self.options;
+        let connecting = self.inner.call(uri).and_then(move |stream| {
+            options.apply(&stream)?;
+            Ok(stream)
+        });
+        let timeout = match options.connect_timeout {
+            Some(duration) => match Timeout::new(duration, &self.handle) {
+                Ok(timeout) => timeout,
+                Err(e) => return Box::new(future::err(e)),
+            },
+            None => return Box::new(connecting),
+        };
+        Box::new(connecting.select2(timeout).then(|result| match result {
+            Ok(Either::A((stream, _))) => Ok(stream),
+            Ok(Either::B(((), _))) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Timed out connecting to the server",
+            )),
+            Err(Either::A((e, _))) | Err(Either::B((e, _))) => Err(e),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaves_preferred_family_first() {
+        let addresses = vec![
+            "127.0.0.1:80".parse().unwrap(),
+            "127.0.0.2:80".parse().unwrap(),
+            "[::1]:80".parse().unwrap(),
+        ];
+        let ordered = interleave_families(addresses, Family::Ipv6);
+        let expected: Vec<SocketAddr> = vec![
+            "[::1]:80".parse().unwrap(),
+            "127.0.0.1:80".parse().unwrap(),
+            "127.0.0.2:80".parse().unwrap(),
+        ];
+        assert_eq!(ordered.into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn applies_socket_options() {
+        use hyper::client::HttpConnector;
+        use std::net::TcpListener;
+        use tokio_core::reactor::Core;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let mut core = Core::new().unwrap();
+        let options = SocketOptions {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(60)),
+            connect_timeout: Some(Duration::from_secs(5)),
+        };
+        let connector =
+            SocketConnector::new(HttpConnector::new(1, &core.handle()), options, &core.handle());
+        let stream = core.run(connector.call(uri)).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert!(stream.keepalive().unwrap().is_some());
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hyper::header::{Authorization, Basic, Bearer};
+use hyper::Request;
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(feature = "keyring")]
+use keyring::Keyring;
+
+use super::{ErrorKind, RequestSigner, Result, ResultExt};
+
+/// A secret, like a password or a token. Its `Debug` output does not contain the secret.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wraps a secret.
+    pub fn new(secret: impl Into<String>) -> Self {
+        Secret(secret.into())
+    }
+
+    /// Returns the secret itself.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// A source of secrets, looked up by name when they are needed instead of being kept in the
+/// configuration of the transport. Implemented for the environment by
+/// [`EnvCredentials`](struct.EnvCredentials.html), for the keyring of the operating system by
+/// `KeyringCredentials` when the "keyring" feature is enabled, and for closures, to fetch secrets
+/// from custom vaults.
+pub trait CredentialStore: Send + Sync {
+    /// Returns the secret stored under `name`. Fails with `CredentialError` if there is none.
+    fn secret(&self, name: &str) -> Result<Secret>;
+}
+
+impl<F> CredentialStore for F
+where
+    F: Fn(&str) -> Result<Secret> + Send + Sync,
+{
+    fn secret(&self, name: &str) -> Result<Secret> {
+        self(name)
+    }
+}
+
+/// Reads secrets from environment variables, named by the name of the secret after a prefix.
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    prefix: String,
+}
+
+impl EnvCredentials {
+    /// Creates a store reading the secret `name` from the variable `<prefix><name>`.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        EnvCredentials {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl CredentialStore for EnvCredentials {
+    fn secret(&self, name: &str) -> Result<Secret> {
+        let variable = format!("{}{}", self.prefix, name);
+        env::var(&variable)
+            .map(Secret)
+            .chain_err(|| ErrorKind::CredentialError(format!("No variable {}", variable)))
+    }
+}
+
+/// Reads secrets from the keyring of the operating system, stored for a service with the name of
+/// the secret as user name.
+#[cfg(feature = "keyring")]
+#[derive(Debug, Clone)]
+pub struct KeyringCredentials {
+    service: String,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringCredentials {
+    /// Creates a store reading the secrets of `service`.
+    pub fn new(service: impl Into<String>) -> Self {
+        KeyringCredentials {
+            service: service.into(),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl CredentialStore for KeyringCredentials {
+    fn secret(&self, name: &str) -> Result<Secret> {
+        Keyring::new(&self.service, name)
+            .get_password()
+            .map(Secret)
+            .chain_err(|| {
+                ErrorKind::CredentialError(format!("No secret {} for {}", name, self.service))
+            })
+    }
+}
+
+/// A secret given when configuring a signer, or looked up by name from a `CredentialStore` every
+/// time it is used.
+#[derive(Clone)]
+pub enum SecretSource {
+    Fixed(Vec<u8>),
+    Stored {
+        store: Arc<dyn CredentialStore>,
+        name: String,
+    },
+}
+
+impl SecretSource {
+    /// Looks up a secret from `store`.
+    pub fn stored(store: impl CredentialStore + 'static, name: impl Into<String>) -> Self {
+        SecretSource::Stored {
+            store: Arc::new(store),
+            name: name.into(),
+        }
+    }
+
+    /// Returns the current value of the secret.
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        match *self {
+            SecretSource::Fixed(ref secret) => Ok(secret.clone()),
+            SecretSource::Stored {
+                ref store,
+                ref name,
+            } => store.secret(name).map(|secret| secret.0.into_bytes()),
+        }
+    }
+}
+
+/// How a `CredentialAuth` adds a secret to requests.
+#[derive(Debug, Clone)]
+enum Scheme {
+    Bearer { token: String },
+    Basic { username: String, password: String },
+    Header { header: String, secret: String },
+}
+
+/// Authenticates requests with secrets from a `CredentialStore`, looked up for every request so
+/// that rotated secrets are picked up. Set on a handle with
+/// [`HttpHandle::set_signer`](struct.HttpHandle.html#method.set_signer).
+#[derive(Clone)]
+pub struct CredentialAuth {
+    store: Arc<dyn CredentialStore>,
+    scheme: Scheme,
+}
+
+impl CredentialAuth {
+    /// Sends the secret named `token` as a bearer token in the `Authorization` header.
+    pub fn bearer(store: impl CredentialStore + 'static, token: impl Into<String>) -> Self {
+        Self::with_scheme(store, Scheme::Bearer { token: token.into() })
+    }
+
+    /// Sends `username` and the secret named `password` with basic authentication in the
+    /// `Authorization` header.
+    pub fn basic(
+        store: impl CredentialStore + 'static,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        Self::with_scheme(
+            store,
+            Scheme::Basic {
+                username: username.into(),
+                password: password.into(),
+            },
+        )
+    }
+
+    /// Sends the secret named `secret` as the value of the header `header`, like `X-Api-Key`.
+    pub fn header(
+        store: impl CredentialStore + 'static,
+        header: impl Into<String>,
+        secret: impl Into<String>,
+    ) -> Self {
+        Self::with_scheme(
+            store,
+            Scheme::Header {
+                header: header.into(),
+                secret: secret.into(),
+            },
+        )
+    }
+
+    fn with_scheme(store: impl CredentialStore + 'static, scheme: Scheme) -> Self {
+        CredentialAuth {
+            store: Arc::new(store),
+            scheme,
+        }
+    }
+}
+
+impl fmt::Debug for CredentialAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CredentialAuth")
+            .field("scheme", &self.scheme)
+            .finish()
+    }
+}
+
+impl RequestSigner for CredentialAuth {
+    fn sign(&self, request: &mut Request, _body: &[u8]) -> Result<()> {
+        match self.scheme {
+            Scheme::Bearer { ref token } => {
+                let token = self.store.secret(token)?;
+                request.headers_mut().set(Authorization(Bearer {
+                    token: token.0,
+                }));
+            }
+            Scheme::Basic {
+                ref username,
+                ref password,
+            } => {
+                let password = self.store.secret(password)?;
+                request.headers_mut().set(Authorization(Basic {
+                    username: username.clone(),
+                    password: Some(password.0),
+                }));
+            }
+            Scheme::Header {
+                ref header,
+                ref secret,
+            } => {
+                let secret = self.store.secret(secret)?;
+                request.headers_mut().set_raw(header.clone(), secret.0);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Method;
+
+    #[test]
+    fn secrets_from_store() {
+        let store = |name: &str| -> Result<Secret> {
+            match name {
+                "token" => Ok(Secret::new("abc")),
+                _ => Err(ErrorKind::CredentialError(name.to_owned()).into()),
+            }
+        };
+        let mut request = Request::new(Method::Post, "http://localhost/".parse().unwrap());
+        CredentialAuth::bearer(store, "token")
+            .sign(&mut request, b"")
+            .unwrap();
+        assert_eq!(
+            request.headers().get_raw("Authorization").unwrap(),
+            "Bearer abc"
+        );
+        assert!(
+            CredentialAuth::header(store, "X-Api-Key", "missing")
+                .sign(&mut request, b"")
+                .is_err()
+        );
+        assert_eq!(format!("{:?}", Secret::new("abc")), "Secret(<redacted>)");
+    }
+}
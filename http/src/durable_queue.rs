@@ -0,0 +1,209 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::future::{self, Loop};
+use futures::Future;
+use serde::Serialize;
+use serde_json::{self, Map, Value as JsonValue};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{Error, ErrorKind, HttpHandle, Result, ResultExt};
+
+/// A queue of notifications that is kept in a file until the notifications have been delivered,
+/// so that none are lost if the server can't be reached or the process is restarted. For
+/// submitting telemetry and other events that must not be lost.
+///
+/// [`notify`](#method.notify) only writes a notification to the file. They are sent, in order,
+/// by [`flush`](#method.flush), and each is removed from the file once the server has responded
+/// to it. A notification can be sent again if the process stops between sending it and removing
+/// it, so delivery is at least once. Notifications left in the file are sent by the first flush
+/// after the queue is opened again.
+#[derive(Debug, Clone)]
+pub struct DurableQueue {
+    handle: HttpHandle,
+    state: Arc<Mutex<QueueState>>,
+}
+
+/// The notifications not yet delivered, shared by the clones of a `DurableQueue`.
+#[derive(Debug)]
+struct QueueState {
+    path: PathBuf,
+    // serialized notifications, each with a number telling it apart from others with equal content
+    pending: VecDeque<(u64, String)>,
+    next_number: u64,
+    flushing: bool,
+}
+
+impl DurableQueue {
+    /// Opens the queue kept in the file at `path`, creating it when notifications are first
+    /// queued. Notifications are sent through `handle`.
+    pub fn open(handle: HttpHandle, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let pending = load(&path)?;
+        Ok(DurableQueue {
+            handle,
+            state: Arc::new(Mutex::new(QueueState {
+                next_number: pending.len() as u64,
+                pending: pending.into_iter().enumerate().map(|(i, n)| (i as u64, n)).collect(),
+                flushing: false,
+                path,
+            })),
+        })
+    }
+
+    /// Writes a notification for `method` with `params` to the queue. Returns once the
+    /// notification is in the file. Call [`flush`](#method.flush) to send it.
+    pub fn notify(&self, method: &str, params: &impl Serialize) -> Result<()> {
+        let mut notification = Map::new();
+        notification.insert("jsonrpc".to_owned(), JsonValue::from("2.0"));
+        notification.insert("method".to_owned(), JsonValue::from(method));
+        let params = serde_json::to_value(params)
+            .chain_err(|| ErrorKind::QueueFileError("Unable to serialize parameters".to_owned()))?;
+        if !params.is_null() {
+            notification.insert("params".to_owned(), params);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let number = state.next_number;
+        state.next_number += 1;
+        state
+            .pending
+            .push_back((number, JsonValue::Object(notification).to_string()));
+        if let Err(e) = state.persist() {
+            state.pending.pop_back();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of notifications not yet delivered.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Returns true if all notifications have been delivered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sends the queued notifications one at a time, in order, removing each from the file once
+    /// the server has responded. Resolves when the queue is empty, or fails with the error of the
+    /// first notification that could not be delivered, which stays first in the queue. Call it
+    /// again after reconnecting, or periodically, to retry. If a flush is already running, this
+    /// resolves immediately and the running flush also sends notifications queued after it
+    /// started.
+    pub fn flush(&self) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.flushing {
+                return Box::new(future::ok(()));
+            }
+            state.flushing = true;
+        }
+        let handle = self.handle.clone();
+        let flushing = Flushing(self.state.clone());
+        let f = future::loop_fn((), move |()| {
+            let state = flushing.0.clone();
+            let next = state.lock().unwrap().pending.front().cloned();
+            let (number, notification) = match next {
+                Some(next) => next,
+                None => return future::Either::A(future::ok(Loop::Break(()))),
+            };
+            future::Either::B(handle.send(notification.into_bytes()).then(move |result| {
+                let mut state = state.lock().unwrap();
+                result
+                    .and_then(|_| state.remove(number))
+                    .map(Loop::Continue)
+            }))
+        });
+        Box::new(f)
+    }
+}
+
+/// Marks a queue as being flushed for as long as the flush is running.
+struct Flushing(Arc<Mutex<QueueState>>);
+
+impl Drop for Flushing {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().flushing = false;
+    }
+}
+
+impl QueueState {
+    /// Removes a delivered notification and writes the rest of the queue to the file.
+    fn remove(&mut self, number: u64) -> Result<()> {
+        if self.pending.front().map(|&(first, _)| first) == Some(number) {
+            self.pending.pop_front();
+        }
+        self.persist()
+    }
+
+    /// Writes the queue to the file, one notification per line. The file is replaced, through a
+    /// temporary file, so that it is never left half written.
+    fn persist(&self) -> Result<()> {
+        let mut data = String::new();
+        for &(_, ref notification) in &self.pending {
+            data.push_str(notification);
+            data.push('\n');
+        }
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, data)
+            .and_then(|()| fs::rename(&temp_path, &self.path))
+            .chain_err(|| queue_file_error(&self.path, "unable to write"))
+    }
+}
+
+/// Reads the notifications in the file at `path`, if it exists.
+fn load(path: &Path) -> Result<Vec<String>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::with_chain(e, queue_file_error(path, "unable to open"))),
+    };
+    io::BufReader::new(file)
+        .lines()
+        .filter(|line| line.as_ref().map(|line| !line.trim().is_empty()).unwrap_or(true))
+        .map(|line| line.chain_err(|| queue_file_error(path, "unable to read")))
+        .collect()
+}
+
+fn queue_file_error(path: &Path, msg: &str) -> ErrorKind {
+    ErrorKind::QueueFileError(format!("{}: {}", path.display(), msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use HttpTransport;
+
+    #[test]
+    fn undelivered_notifications_survive_reopening() {
+        let path = env::temp_dir().join(format!("jsonrpc-queue-test-{}", ::std::process::id()));
+        let transport = HttpTransport::new().standalone().unwrap();
+        // Nothing listens on port 1, so nothing is delivered.
+        let handle = transport.handle("http://127.0.0.1:1/").unwrap();
+
+        let queue = DurableQueue::open(handle.clone(), &path).unwrap();
+        queue.notify("event", &["started"]).unwrap();
+        queue.notify("event", &["stopped"]).unwrap();
+        assert!(queue.flush().wait().is_err());
+        assert_eq!(queue.len(), 2);
+
+        let reopened = DurableQueue::open(handle, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.len(), 2);
+        let first: JsonValue =
+            serde_json::from_str(&reopened.state.lock().unwrap().pending[0].1).unwrap();
+        assert_eq!(first["params"][0], "started");
+    }
+}
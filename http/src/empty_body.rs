@@ -0,0 +1,171 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+
+/// The JSON-RPC 2.0 error code of calls failed because of an empty response body.
+const INTERNAL_ERROR: i64 = -32603;
+
+/// How a call is completed when the server answers it with an empty body, set with
+/// [`HttpHandle::set_empty_body`](struct.HttpHandle.html#method.set_empty_body). Empty answers to
+/// notifications are always accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyBody {
+    /// The call fails with a JSON-RPC error with code -32603, internal error. The default.
+    Error,
+    /// The call succeeds with `null` as result, which deserializes into `()`.
+    Null,
+}
+
+impl Default for EmptyBody {
+    fn default() -> Self {
+        EmptyBody::Error
+    }
+}
+
+/// The handling of empty bodies for all calls of a handle, and for specific methods.
+#[derive(Debug, Clone, Default)]
+pub struct EmptyBodies {
+    pub default: EmptyBody,
+    pub methods: HashMap<String, EmptyBody>,
+}
+
+impl EmptyBodies {
+    /// Returns the response standing in for an empty body received for `calls`. Returns an empty
+    /// body again when all calls are notifications. The calls of a batch always fail, since an
+    /// empty answer to a batch is never a valid answer.
+    pub fn response(&self, calls: &SentCalls) -> Vec<u8> {
+        let responses = calls
+            .calls
+            .iter()
+            .filter_map(|call| {
+                let handling = if calls.batch {
+                    EmptyBody::Error
+                } else {
+                    *self.methods.get(&call.method).unwrap_or(&self.default)
+                };
+                call.id.clone().map(|id| response(id, handling))
+            }).collect::<Vec<_>>();
+        let response = match (calls.batch, responses.len()) {
+            (_, 0) => return Vec::new(),
+            (true, _) => Value::Array(responses),
+            (false, _) => responses.into_iter().next().unwrap(),
+        };
+        serde_json::to_vec(&response).unwrap_or_default()
+    }
+}
+
+/// The ids and methods of the calls in a payload, all that is needed to answer them if the server
+/// sends back an empty body.
+#[derive(Debug, Default)]
+pub struct SentCalls {
+    batch: bool,
+    calls: Vec<SentCall>,
+}
+
+impl SentCalls {
+    /// Reads the calls of a single call or batch payload. Anything else yields no calls.
+    pub fn of(payload: &[u8]) -> Self {
+        let batch = payload
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .map_or(false, |&byte| byte == b'[');
+        let calls = if batch {
+            serde_json::from_slice(payload).unwrap_or_default()
+        } else {
+            serde_json::from_slice(payload).map(|call| vec![call]).unwrap_or_default()
+        };
+        SentCalls { batch, calls }
+    }
+}
+
+/// The id and method of one call. The parameters are skipped without being copied.
+#[derive(Debug, Deserialize)]
+struct SentCall {
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    method: String,
+}
+
+fn response(id: Value, handling: EmptyBody) -> Value {
+    let (key, value) = match handling {
+        EmptyBody::Error => {
+            let mut error = Map::new();
+            error.insert("code".to_owned(), Value::from(INTERNAL_ERROR));
+            error.insert("message".to_owned(), Value::from("Empty response body"));
+            ("error", Value::Object(error))
+        }
+        EmptyBody::Null => ("result", Value::Null),
+    };
+    let mut response = Map::new();
+    response.insert("jsonrpc".to_owned(), Value::from("2.0"));
+    response.insert(key.to_owned(), value);
+    response.insert("id".to_owned(), id);
+    Value::Object(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_calls() {
+        let mut empty_bodies = EmptyBodies::default();
+        empty_bodies
+            .methods
+            .insert("ping".to_owned(), EmptyBody::Null);
+
+        let response = empty_bodies.response(&SentCalls::of(
+            br#"{"jsonrpc":"2.0","method":"ping","params":[{"id":9}],"id":1}"#,
+        ));
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(response["result"], Value::Null);
+        assert_eq!(response["id"], 1);
+
+        let response =
+            empty_bodies.response(&SentCalls::of(br#"{"jsonrpc":"2.0","method":"get","id":2}"#));
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+        assert_eq!(response["id"], 2);
+
+        assert!(
+            empty_bodies
+                .response(&SentCalls::of(br#"{"jsonrpc":"2.0","method":"notify"}"#))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn fails_batch_calls() {
+        let mut empty_bodies = EmptyBodies::default();
+        empty_bodies
+            .methods
+            .insert("ping".to_owned(), EmptyBody::Null);
+
+        let response = empty_bodies.response(&SentCalls::of(
+            br#" [{"jsonrpc":"2.0","method":"ping","id":1},
+                 {"jsonrpc":"2.0","method":"notify"},
+                 {"jsonrpc":"2.0","method":"get","id":"b"}]"#,
+        ));
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        let responses = response.as_array().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["error"]["code"], INTERNAL_ERROR);
+        assert_eq!(responses[1]["id"], "b");
+        assert_eq!(responses[1]["error"]["code"], INTERNAL_ERROR);
+
+        assert!(
+            empty_bodies
+                .response(&SentCalls::of(br#"[{"jsonrpc":"2.0","method":"notify"}]"#))
+                .is_empty()
+        );
+    }
+}
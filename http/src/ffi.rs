@@ -0,0 +1,193 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A minimal C API, for embedding the client in applications written in other languages. The
+//! functions are declared in `include/jsonrpc_client.h`, and are exported from any `staticlib` or
+//! `cdylib` linking this crate with the "ffi" feature enabled.
+//!
+//! Ownership rules:
+//!
+//! * A client created with `jsonrpc_client_new` is owned by the caller, and must be released with
+//!   `jsonrpc_client_free` exactly once. The callbacks of calls still in flight when it is
+//!   released may not be called.
+//! * Strings passed to the functions are borrowed for the duration of the function only.
+//! * Strings passed to a callback are owned by the client, and are only valid until the callback
+//!   returns. Copy them to keep them.
+//! * The callback of a call that was started successfully is called at most once, on a thread of
+//!   the client, and exactly once unless the client is released first. The user data pointer is
+//!   handed back as is, and never dereferenced.
+
+use futures::Future;
+use futures_cpupool::CpuPool;
+use jsonrpc_client_core::{ClientHandle, Transport};
+use serde_json::{self, Value};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use super::HttpTransport;
+
+/// The callback receiving the result of a call. Exactly one of `result` and `error` is non-null:
+/// `result` is the JSON result of the call, and `error` a description of why it failed.
+pub type JsonRpcCallback =
+    extern "C" fn(user_data: *mut c_void, result: *const c_char, error: *const c_char);
+
+/// A client, created with `jsonrpc_client_new`.
+pub struct JsonRpcClient {
+    // Kept alive for as long as the client, it runs the event loop of the transport.
+    _transport: HttpTransport,
+    handle: ClientHandle,
+    pool: CpuPool,
+}
+
+/// The user data of a callback, only handed back to the callback.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+/// Creates a client sending calls to `uri`. Returns null if `uri` is not valid UTF-8 or not a
+/// valid URI, or if the client could not be started.
+#[no_mangle]
+pub unsafe extern "C" fn jsonrpc_client_new(uri: *const c_char) -> *mut JsonRpcClient {
+    if uri.is_null() {
+        return ptr::null_mut();
+    }
+    let uri = match CStr::from_ptr(uri).to_str() {
+        Ok(uri) => uri.to_owned(),
+        Err(_) => return ptr::null_mut(),
+    };
+    let client = panic::catch_unwind(move || {
+        let transport = HttpTransport::new().standalone().ok()?;
+        let (client, handle) = transport.handle(&uri).ok()?.into_client();
+        let pool = CpuPool::new(1);
+        pool.spawn(client.map_err(|e| error!("Client for the C API failed: {}", e)))
+            .forget();
+        Some(JsonRpcClient {
+            _transport: transport,
+            handle,
+            pool,
+        })
+    });
+    match client {
+        Ok(Some(client)) => Box::into_raw(Box::new(client)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Calls `method` with the parameters in `params_json`, a JSON array or object, or null for no
+/// parameters. Returns 0 if the call was started, in which case `callback` is called with the
+/// result later. Returns -1, without calling `callback`, if an argument is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn jsonrpc_client_call(
+    client: *mut JsonRpcClient,
+    method: *const c_char,
+    params_json: *const c_char,
+    callback: JsonRpcCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    if client.is_null() || method.is_null() {
+        return -1;
+    }
+    let client = &*client;
+    let method = match CStr::from_ptr(method).to_str() {
+        Ok(method) => method.to_owned(),
+        Err(_) => return -1,
+    };
+    let params = if params_json.is_null() {
+        Value::Null
+    } else {
+        match CStr::from_ptr(params_json)
+            .to_str()
+            .ok()
+            .and_then(|params| serde_json::from_str(params).ok())
+        {
+            Some(params) => params,
+            None => return -1,
+        }
+    };
+    let user_data = UserData(user_data);
+    let started = panic::catch_unwind(AssertUnwindSafe(|| {
+        client.handle.call_with_callback(
+            &client.pool,
+            method,
+            &params,
+            move |result: jsonrpc_client_core::Result<Value>| {
+                match result.map(|result| result.to_string()) {
+                    Ok(result) => {
+                        let result = c_string(result);
+                        callback(user_data.0, result.as_ptr(), ptr::null());
+                    }
+                    Err(e) => {
+                        let error = c_string(e.to_string());
+                        callback(user_data.0, ptr::null(), error.as_ptr());
+                    }
+                }
+            },
+        )
+    }));
+    match started {
+        Ok(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Releases a client created with `jsonrpc_client_new`. Does nothing if `client` is null.
+#[no_mangle]
+pub unsafe extern "C" fn jsonrpc_client_free(client: *mut JsonRpcClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Converts a string to a C string, cutting it at the first nul byte.
+fn c_string(string: String) -> CString {
+    CString::new(string).unwrap_or_else(|e| {
+        let nul = e.nul_position();
+        let mut bytes = e.into_vec();
+        bytes.truncate(nul);
+        CString::new(bytes).unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        extern "C" fn callback(_: *mut c_void, _: *const c_char, _: *const c_char) {
+            panic!("callback of a call that was not started");
+        }
+        unsafe {
+            assert!(jsonrpc_client_new(ptr::null()).is_null());
+            let uri = CString::new("not a uri").unwrap();
+            assert!(jsonrpc_client_new(uri.as_ptr()).is_null());
+
+            let uri = CString::new("http://localhost:1/").unwrap();
+            let client = jsonrpc_client_new(uri.as_ptr());
+            assert!(!client.is_null());
+            let method = CString::new("echo").unwrap();
+            let params = CString::new("[1, 2").unwrap();
+            let status = jsonrpc_client_call(
+                client,
+                method.as_ptr(),
+                params.as_ptr(),
+                callback,
+                ptr::null_mut(),
+            );
+            assert_eq!(status, -1);
+            jsonrpc_client_free(client);
+        }
+    }
+
+    #[test]
+    fn cuts_strings_at_nul() {
+        assert_eq!(c_string("ab\0c".to_owned()).to_str().unwrap(), "ab");
+    }
+}
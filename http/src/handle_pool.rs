@@ -0,0 +1,225 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use jsonrpc_client_core::Transport;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use super::{HttpHandle, HttpTransport, Result};
+
+type Factory<K> = Arc<dyn Fn(&HttpTransport, &K) -> Result<HttpHandle> + Send + Sync>;
+
+/// The handles of a `HandlePool`, shared by its clones.
+struct PoolState<K> {
+    // every handle with the tick it was last used at
+    handles: HashMap<K, (HttpHandle, u64)>,
+    tick: u64,
+    max_size: usize,
+}
+
+impl<K: Hash + Eq + Clone> PoolState<K> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Evicts the least recently used handles until at most `len` are left.
+    fn evict_to(&mut self, len: usize) {
+        while self.handles.len() > len {
+            let oldest = self
+                .handles
+                .iter()
+                .min_by_key(|&(_, &(_, tick))| tick)
+                .map(|(key, _)| key.clone());
+            if let Some((handle, _)) = oldest.and_then(|key| self.handles.remove(&key)) {
+                debug!(
+                    "Evicting the least recently used handle for {}",
+                    handle.endpoint().unwrap_or_default()
+                );
+            }
+        }
+    }
+}
+
+/// Handles to one transport for many tenants, like the customer specific endpoints called by a
+/// SaaS backend, each created with the URI, credentials and headers of its tenant. A handle is
+/// created by the factory the pool is created with the first time it is asked for, and is then
+/// reused until it is evicted.
+///
+/// The pool holds at most [`max_size`](#method.max_size) handles, evicting the least recently
+/// used one to make room for a new one. Handles can also be evicted explicitly, for example when
+/// the credentials of a tenant change. Evicting a handle does not affect calls already made
+/// through it. Clones of the pool share the same handles.
+pub struct HandlePool<K> {
+    transport: HttpTransport,
+    factory: Factory<K>,
+    state: Arc<Mutex<PoolState<K>>>,
+}
+
+impl<K> Clone for HandlePool<K> {
+    fn clone(&self) -> Self {
+        HandlePool {
+            transport: self.transport.clone(),
+            factory: self.factory.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> HandlePool<K> {
+    /// Creates a pool creating the handle for a key by calling `factory` with `transport` and the
+    /// key. The factory typically calls [`HttpTransport::handle`] with the URI of the tenant, and
+    /// sets its credentials with [`HttpHandle::set_header`] or [`HttpHandle::set_signer`]. Holds at
+    /// most 1024 handles by default.
+    ///
+    /// [`HttpTransport::handle`]: struct.HttpTransport.html#method.handle
+    /// [`HttpHandle::set_header`]: struct.HttpHandle.html#method.set_header
+    /// [`HttpHandle::set_signer`]: struct.HttpHandle.html#method.set_signer
+    pub fn new<F>(transport: HttpTransport, factory: F) -> Self
+    where
+        F: Fn(&HttpTransport, &K) -> Result<HttpHandle> + Send + Sync + 'static,
+    {
+        HandlePool {
+            transport,
+            factory: Arc::new(factory),
+            state: Arc::new(Mutex::new(PoolState {
+                handles: HashMap::new(),
+                tick: 0,
+                max_size: 1024,
+            })),
+        }
+    }
+
+    /// Sets the maximum number of handles held by the pool, evicting the least recently used
+    /// handles if it holds more.
+    pub fn max_size(self, max_size: usize) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            let max_size = max_size.max(1);
+            state.max_size = max_size;
+            state.evict_to(max_size);
+        }
+        self
+    }
+
+    /// Returns the handle for `key`, creating it with the factory of the pool if the pool does
+    /// not hold one. Fails with the error of the factory if it fails, in which case nothing is
+    /// added to the pool.
+    pub fn get(&self, key: &K) -> Result<HttpHandle> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let tick = state.next_tick();
+            if let Some(&mut (ref handle, ref mut last_used)) = state.handles.get_mut(key) {
+                *last_used = tick;
+                return Ok(handle.clone());
+            }
+        }
+        // The factory runs without holding the lock, as it can be slow when it looks up
+        // credentials. If another thread created a handle for the same key in the meantime, that
+        // one is kept.
+        let handle = (self.factory)(&self.transport, key)?;
+        let mut state = self.state.lock().unwrap();
+        let tick = state.next_tick();
+        if !state.handles.contains_key(key) {
+            let room = state.max_size - 1;
+            state.evict_to(room);
+        }
+        let entry = state
+            .handles
+            .entry(key.clone())
+            .or_insert((handle, tick));
+        entry.1 = tick;
+        Ok(entry.0.clone())
+    }
+
+    /// Removes the handle for `key` from the pool, so the next [`get`](#method.get) creates a new
+    /// one. Returns the removed handle, if there was one.
+    pub fn evict(&self, key: &K) -> Option<HttpHandle> {
+        self.state
+            .lock()
+            .unwrap()
+            .handles
+            .remove(key)
+            .map(|(handle, _)| handle)
+    }
+
+    /// Removes all handles from the pool.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().handles.clear();
+    }
+
+    /// Returns the number of handles held by the pool.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().handles.len()
+    }
+
+    /// Returns true if the pool holds no handles.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K> fmt::Debug for HandlePool<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("HandlePool")
+            .field("transport", &self.transport)
+            .field("handles", &state.handles.len())
+            .field("max_size", &state.max_size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn pool(created: Arc<AtomicUsize>) -> HandlePool<String> {
+        let transport = HttpTransport::new().standalone().unwrap();
+        HandlePool::new(transport, move |transport: &HttpTransport, tenant: &String| {
+            created.fetch_add(1, Ordering::SeqCst);
+            transport.handle(&format!("http://127.0.0.1:1234/{}", tenant))
+        })
+    }
+
+    #[test]
+    fn reuses_handles() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = pool(created.clone());
+        let handle = pool.get(&"a".to_owned()).unwrap();
+        assert_eq!(handle.uri.to_string(), "http://127.0.0.1:1234/a");
+        pool.get(&"a".to_owned()).unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+
+        assert!(pool.evict(&"a".to_owned()).is_some());
+        assert!(pool.is_empty());
+        pool.get(&"a".to_owned()).unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = pool(created.clone()).max_size(2);
+        pool.get(&"a".to_owned()).unwrap();
+        pool.get(&"b".to_owned()).unwrap();
+        pool.get(&"a".to_owned()).unwrap();
+        pool.get(&"c".to_owned()).unwrap();
+        assert_eq!(pool.len(), 2);
+        assert_eq!(created.load(Ordering::SeqCst), 3);
+
+        // "b" was evicted, "a" was kept
+        pool.get(&"a".to_owned()).unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 3);
+        pool.get(&"b".to_owned()).unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 4);
+    }
+}
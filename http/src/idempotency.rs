@@ -0,0 +1,259 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::sync::oneshot;
+use hyper;
+use jsonrpc_client_core::ErrorCopy;
+use serde_json::{self, Value as JsonValue};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use super::{Error, ErrorKind, Result};
+use vcr::{ids, rewrite_ids};
+
+/// The header carrying the idempotency key of a request.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// A caller waiting for the result of a request in flight, with the JSON-RPC ids of the request
+/// it would have sent.
+struct Waiter {
+    ids: Vec<JsonValue>,
+    result_tx: oneshot::Sender<Result<Vec<u8>>>,
+}
+
+/// The JSON-RPC ids of a request in flight, and the callers waiting for its result.
+struct InFlight {
+    ids: Vec<JsonValue>,
+    waiters: Vec<Waiter>,
+}
+
+/// The idempotency keys of the requests in flight through a handle and its clones, each with the
+/// callers waiting for the result of the request instead of sending a duplicate.
+#[derive(Clone, Default)]
+pub struct InFlightKeys(Arc<Mutex<HashMap<String, InFlight>>>);
+
+impl ::std::fmt::Debug for InFlightKeys {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let keys = self.0.lock().unwrap();
+        f.debug_set().entries(keys.keys()).finish()
+    }
+}
+
+/// Marks an idempotency key as in flight until the result of its request is known, or the
+/// request is dropped.
+pub struct KeyGuard {
+    keys: InFlightKeys,
+    key: String,
+    finished: bool,
+}
+
+impl InFlightKeys {
+    /// Marks `key` as in flight for the request with the body `json_data` and returns a guard for
+    /// it, or returns a receiver for the result of the request already in flight with the key.
+    /// The result is changed to carry the JSON-RPC ids of `json_data`.
+    pub fn start(
+        &self,
+        key: &str,
+        json_data: &[u8],
+    ) -> ::std::result::Result<KeyGuard, oneshot::Receiver<Result<Vec<u8>>>> {
+        let ids = request_ids(json_data);
+        let mut keys = self.0.lock().unwrap();
+        if let Some(in_flight) = keys.get_mut(key) {
+            let (result_tx, result_rx) = oneshot::channel();
+            in_flight.waiters.push(Waiter { ids, result_tx });
+            return Err(result_rx);
+        }
+        keys.insert(
+            key.to_owned(),
+            InFlight {
+                ids,
+                waiters: Vec::new(),
+            },
+        );
+        Ok(KeyGuard {
+            keys: self.clone(),
+            key: key.to_owned(),
+            finished: false,
+        })
+    }
+}
+
+impl KeyGuard {
+    /// Hands a copy of the result to everyone waiting for it and lets the key be sent again.
+    pub fn finish(mut self, result: &Result<Vec<u8>>) {
+        self.finished = true;
+        let in_flight = match (self.keys.0).lock().unwrap().remove(&self.key) {
+            Some(in_flight) => in_flight,
+            None => return,
+        };
+        for waiter in in_flight.waiters {
+            let result = match *result {
+                Ok(ref body) => Ok(with_ids(body, &in_flight.ids, waiter.ids)),
+                Err(ref error) => Err(duplicate_error(error)),
+            };
+            let _ = waiter.result_tx.send(result);
+        }
+    }
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            // Dropping the waiters lets them know the request was abandoned.
+            (self.keys.0).lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+/// Returns the JSON-RPC ids of a request body, or none if it is not JSON.
+fn request_ids(json_data: &[u8]) -> Vec<JsonValue> {
+    serde_json::from_slice(json_data)
+        .map(|request| ids(&request))
+        .unwrap_or_default()
+}
+
+/// Returns a copy of a response body with the ids of the request that was sent replaced by the
+/// ids of the request of a waiter. Bodies that are not JSON are copied as they are.
+fn with_ids(body: &[u8], sent_ids: &[JsonValue], waiter_ids: Vec<JsonValue>) -> Vec<u8> {
+    if sent_ids == &waiter_ids[..] {
+        return body.to_vec();
+    }
+    match serde_json::from_slice::<JsonValue>(body) {
+        Ok(mut response) => {
+            let id_map = sent_ids.iter().cloned().zip(waiter_ids).collect::<Vec<_>>();
+            rewrite_ids(&mut response, &id_map);
+            response.to_string().into_bytes()
+        }
+        Err(_) => body.to_vec(),
+    }
+}
+
+/// Creates a copy of an error with the same kind, so waiters get the same `RecoveryHint` as the
+/// request they waited for. The causes are copied with `ErrorCopy`.
+fn duplicate_error(error: &Error) -> Error {
+    let kind = match *error.kind() {
+        ErrorKind::Msg(ref msg) => ErrorKind::Msg(msg.clone()),
+        ErrorKind::ClientCreatorError => ErrorKind::ClientCreatorError,
+        ErrorKind::HttpError(status) => ErrorKind::HttpError(status),
+        ErrorKind::RateLimited(retry_after) => ErrorKind::RateLimited(retry_after),
+        ErrorKind::RequestTimeout => ErrorKind::RequestTimeout,
+        ErrorKind::ReadTimeout => ErrorKind::ReadTimeout,
+        ErrorKind::BodyTooLarge(max_size) => ErrorKind::BodyTooLarge(max_size),
+        ErrorKind::UnexpectedContentType(ref content_type, ref preview) => {
+            ErrorKind::UnexpectedContentType(content_type.clone(), preview.clone())
+        }
+        ErrorKind::ParseBodyError => ErrorKind::ParseBodyError,
+        ErrorKind::CassetteError(ref msg) => ErrorKind::CassetteError(msg.clone()),
+        ErrorKind::CredentialError(ref msg) => ErrorKind::CredentialError(msg.clone()),
+        ErrorKind::QueueFileError(ref msg) => ErrorKind::QueueFileError(msg.clone()),
+        ErrorKind::TlsServerNameUnsupported => ErrorKind::TlsServerNameUnsupported,
+        ErrorKind::UriTemplateError(ref msg) => ErrorKind::UriTemplateError(msg.clone()),
+        ErrorKind::EventLoopStopped => ErrorKind::EventLoopStopped,
+        ErrorKind::TokioCoreError(msg) => ErrorKind::TokioCoreError(msg),
+        ErrorKind::Hyper(ref error) => match duplicate_hyper_error(error) {
+            Some(error) => ErrorKind::Hyper(error),
+            None => ErrorKind::Msg(error.to_string()),
+        },
+        _ => ErrorKind::Msg(error.to_string()),
+    };
+    match error.cause() {
+        Some(cause) => Error::with_chain(ErrorCopy::new(cause), kind),
+        None => kind.into(),
+    }
+}
+
+/// Creates a copy of the errors of Hyper that can be created outside of Hyper.
+fn duplicate_hyper_error(error: &hyper::Error) -> Option<hyper::Error> {
+    Some(match *error {
+        hyper::Error::Method => hyper::Error::Method,
+        hyper::Error::Version => hyper::Error::Version,
+        hyper::Error::Header => hyper::Error::Header,
+        hyper::Error::TooLarge => hyper::Error::TooLarge,
+        hyper::Error::Incomplete => hyper::Error::Incomplete,
+        hyper::Error::Status => hyper::Error::Status,
+        hyper::Error::Timeout => hyper::Error::Timeout,
+        hyper::Error::Upgrade => hyper::Error::Upgrade,
+        hyper::Error::Closed => hyper::Error::Closed,
+        hyper::Error::Io(ref error) => {
+            hyper::Error::Io(io::Error::new(error.kind(), error.to_string()))
+        }
+        hyper::Error::Utf8(error) => hyper::Error::Utf8(error),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+
+    #[test]
+    fn duplicates_wait_for_first() {
+        let keys = InFlightKeys::default();
+        let guard = keys.start("a", b"").ok().unwrap();
+        let duplicate = keys.start("a", b"").err().unwrap();
+        assert!(keys.start("b", b"").is_ok());
+        guard.finish(&Ok(b"result".to_vec()));
+        assert_eq!(duplicate.wait().unwrap().unwrap(), b"result".to_vec());
+        assert!(keys.start("a", b"").is_ok());
+    }
+
+    #[test]
+    fn dropped_request_releases_key() {
+        let keys = InFlightKeys::default();
+        let guard = keys.start("a", b"").ok().unwrap();
+        let duplicate = keys.start("a", b"").err().unwrap();
+        drop(guard);
+        assert!(duplicate.wait().is_err());
+        assert!(keys.start("a", b"").is_ok());
+    }
+
+    #[test]
+    fn duplicates_get_their_own_ids() {
+        let keys = InFlightKeys::default();
+        let guard = keys
+            .start("a", br#"{"jsonrpc":"2.0","method":"m","id":1}"#)
+            .ok()
+            .unwrap();
+        let duplicate = keys
+            .start("a", br#"{"jsonrpc":"2.0","method":"m","id":2}"#)
+            .err()
+            .unwrap();
+        guard.finish(&Ok(br#"{"jsonrpc":"2.0","result":"r","id":1}"#.to_vec()));
+        let response: JsonValue =
+            serde_json::from_slice(&duplicate.wait().unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"], "r");
+    }
+
+    #[test]
+    fn duplicates_get_errors_of_the_same_kind() {
+        let keys = InFlightKeys::default();
+        let errors = vec![
+            Error::from(ErrorKind::HttpError(hyper::StatusCode::ServiceUnavailable)),
+            ErrorKind::Hyper(hyper::Error::Io(io::ErrorKind::ConnectionReset.into())).into(),
+            Error::with_chain(io::Error::new(io::ErrorKind::Other, "cause"), "message"),
+        ];
+        for error in errors {
+            let guard = keys.start("a", b"").ok().unwrap();
+            let duplicate = keys.start("a", b"").err().unwrap();
+            let result = Err(error);
+            guard.finish(&result);
+            let copy = duplicate.wait().unwrap().unwrap_err();
+            let error = result.unwrap_err();
+            assert_eq!(copy.to_string(), error.to_string());
+            assert_eq!(copy.recovery_hint(), error.recovery_hint());
+            assert_eq!(
+                copy.cause().map(ToString::to_string),
+                error.cause().map(ToString::to_string)
+            );
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::time::Duration;
+
+use super::RetryPolicy;
+
+/// Pre-tuned combinations of builder settings, applied with
+/// [`HttpTransportBuilder::preset`](struct.HttpTransportBuilder.html#method.preset).
+///
+/// The connection pool is configured on the Hyper `Client` returned by the `ClientCreator`, so it
+/// is not affected by presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// For interactive use against fast, nearby servers. Short timeout, a single quick retry and
+    /// many concurrent requests. Slow requests fail fast instead of holding up the caller.
+    LowLatency,
+    /// A middle ground suitable for most services.
+    Balanced,
+    /// For slow or fragile servers. Long timeout, several retries with generous backoff, and few
+    /// concurrent requests to avoid overloading the server.
+    Conservative,
+}
+
+/// The settings a `Preset` consists of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresetSettings {
+    /// The timeout of every request attempt.
+    pub timeout: Duration,
+    /// The policy for retrying failed requests.
+    pub retry: RetryPolicy,
+    /// The maximum number of requests processed concurrently by each core.
+    pub max_concurrency: usize,
+}
+
+impl Preset {
+    /// Returns the settings of this preset.
+    pub fn settings(&self) -> PresetSettings {
+        match *self {
+            Preset::LowLatency => PresetSettings {
+                timeout: Duration::from_secs(2),
+                retry: RetryPolicy::new(1, Duration::from_millis(25)),
+                max_concurrency: 64,
+            },
+            Preset::Balanced => PresetSettings {
+                timeout: Duration::from_secs(30),
+                retry: RetryPolicy::new(2, Duration::from_millis(200)).retry_rate_limited(true),
+                max_concurrency: 16,
+            },
+            Preset::Conservative => PresetSettings {
+                timeout: Duration::from_secs(120),
+                retry: RetryPolicy::new(4, Duration::from_secs(1)).retry_rate_limited(true),
+                max_concurrency: 2,
+            },
+        }
+    }
+}
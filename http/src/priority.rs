@@ -0,0 +1,85 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+
+/// How urgent the requests sent through an `HttpHandle` are, set with
+/// [`HttpHandle::set_priority`](struct.HttpHandle.html#method.set_priority).
+///
+/// When an event loop already has as many requests in flight as its concurrency allows, the
+/// waiting requests are started in order of priority, and in the order they were made within the
+/// same priority. Requests already in flight are never interrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    /// For requests that should not be held up by other traffic, like cancelling an order.
+    High,
+    /// The priority of handles that have not been given one.
+    Normal,
+    /// For bulk background requests that can wait.
+    Low,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+/// Requests waiting to be started by an event loop, one queue per priority.
+#[derive(Debug)]
+pub struct PriorityQueue<T> {
+    high: VecDeque<T>,
+    normal: VecDeque<T>,
+    low: VecDeque<T>,
+}
+
+impl<T> PriorityQueue<T> {
+    pub fn new() -> Self {
+        PriorityQueue {
+            high: VecDeque::new(),
+            normal: VecDeque::new(),
+            low: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, priority: Priority, item: T) {
+        match priority {
+            Priority::High => self.high.push_back(item),
+            Priority::Normal => self.normal.push_back(item),
+            Priority::Low => self.low.push_back(item),
+        }
+    }
+
+    /// Takes the oldest item of the highest priority.
+    pub fn pop(&mut self) -> Option<T> {
+        self.high
+            .pop_front()
+            .or_else(|| self.normal.pop_front())
+            .or_else(|| self.low.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_priority_first() {
+        let mut queue = PriorityQueue::new();
+        queue.push(Priority::Low, 1);
+        queue.push(Priority::Normal, 2);
+        queue.push(Priority::High, 3);
+        queue.push(Priority::Normal, 4);
+        queue.push(Priority::High, 5);
+        let mut order = Vec::new();
+        while let Some(item) = queue.pop() {
+            order.push(item);
+        }
+        assert_eq!(order, vec![3, 5, 2, 4, 1]);
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hyper::{self, StatusCode};
+use jsonrpc_client_core::RecoveryHint;
+use std::cmp;
+use std::time::Duration;
+
+use super::{Error, ErrorKind};
+
+/// Upper limit on the exponent of the exponential backoff, to avoid overflowing the delay.
+const MAX_BACKOFF_DOUBLINGS: u32 = 16;
+
+/// Policy for retrying requests that failed in a way that is likely to be temporary: timeouts,
+/// connection errors and the 502, 503 and 504 status codes. Requests rejected with 429 Too Many
+/// Requests are only retried if `retry_rate_limited` is set.
+///
+/// Note that a retried call might be executed more than once by the server, if the failure
+/// happened after the server received the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of times a request is retried after the first attempt.
+    pub max_retries: u32,
+    /// The delay before the first retry. The delay doubles for every following retry.
+    pub backoff: Duration,
+    /// Whether to retry requests rejected with 429 Too Many Requests. They are retried after the
+    /// delay the server asked for in the `Retry-After` header, or after the normal backoff if
+    /// there was no such header.
+    pub retry_rate_limited: bool,
+}
+
+impl RetryPolicy {
+    /// A policy never retrying any request.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            backoff: Duration::from_secs(0),
+            retry_rate_limited: false,
+        }
+    }
+
+    /// A policy retrying requests at most `max_retries` times, with exponential backoff starting
+    /// at `backoff`.
+    pub fn new(max_retries: u32, backoff: Duration) -> Self {
+        RetryPolicy {
+            max_retries,
+            backoff,
+            retry_rate_limited: false,
+        }
+    }
+
+    /// Sets whether requests rejected with 429 Too Many Requests are retried.
+    pub fn retry_rate_limited(mut self, retry_rate_limited: bool) -> Self {
+        self.retry_rate_limited = retry_rate_limited;
+        self
+    }
+
+    /// Returns the delay before retrying a request that failed on the given attempt, counted from
+    /// zero.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.pow(cmp::min(attempt, MAX_BACKOFF_DOUBLINGS))
+    }
+
+    /// Returns the delay before retrying a request that failed with `error` on the given attempt.
+    /// This is the delay requested by the server for rate limited requests, and the backoff delay
+    /// otherwise.
+    pub fn delay_after(&self, attempt: u32, error: &Error) -> Duration {
+        match *error.kind() {
+            ErrorKind::RateLimited(Some(retry_after)) => retry_after,
+            _ => self.delay(attempt),
+        }
+    }
+
+    /// Returns true if a request that failed with `error` on the given attempt should be retried.
+    pub fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        attempt < self.max_retries && match *error.kind() {
+            ErrorKind::RateLimited(_) => self.retry_rate_limited,
+            _ => is_retryable(error),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+impl Error {
+    /// Returns what an application can do about this error. Errors a `RetryPolicy` would retry,
+    /// and rate limiting, can be retried. The `401`, `403` and `407` status codes and failing to
+    /// read a secret call for new credentials. Errors of the event loop or of the connection call
+    /// for a new transport. Nothing helps against the other errors.
+    pub fn recovery_hint(&self) -> RecoveryHint {
+        if is_retryable(self) {
+            return RecoveryHint::Retry;
+        }
+        match *self.kind() {
+            ErrorKind::RateLimited(_) => RecoveryHint::Retry,
+            ErrorKind::HttpError(StatusCode::Unauthorized)
+            | ErrorKind::HttpError(StatusCode::Forbidden)
+            | ErrorKind::HttpError(StatusCode::ProxyAuthenticationRequired)
+            | ErrorKind::CredentialError(_) => RecoveryHint::Reauthenticate,
+            ErrorKind::EventLoopStopped
+            | ErrorKind::TokioCoreError(_)
+            | ErrorKind::ClientCreatorError
+            | ErrorKind::Hyper(_) => RecoveryHint::Reconnect,
+            _ => RecoveryHint::GiveUp,
+        }
+    }
+}
+
+/// Returns true if the error is likely to be temporary.
+fn is_retryable(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::RequestTimeout | ErrorKind::ReadTimeout => true,
+        ErrorKind::Hyper(hyper::Error::Io(_)) => true,
+        ErrorKind::HttpError(status) => match status {
+            StatusCode::BadGateway | StatusCode::ServiceUnavailable | StatusCode::GatewayTimeout => {
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(100));
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn retries_only_temporary_errors() {
+        let policy = RetryPolicy::new(1, Duration::from_millis(100));
+        let timeout = Error::from(ErrorKind::RequestTimeout);
+        let not_found = Error::from(ErrorKind::HttpError(StatusCode::NotFound));
+        assert!(policy.should_retry(0, &timeout));
+        assert!(!policy.should_retry(1, &timeout));
+        assert!(!policy.should_retry(0, &not_found));
+    }
+
+    #[test]
+    fn recovery_hints() {
+        let hint = |kind: ErrorKind| Error::from(kind).recovery_hint();
+        assert_eq!(hint(ErrorKind::RequestTimeout), RecoveryHint::Retry);
+        assert_eq!(hint(ErrorKind::RateLimited(None)), RecoveryHint::Retry);
+        assert_eq!(
+            hint(ErrorKind::HttpError(StatusCode::Unauthorized)),
+            RecoveryHint::Reauthenticate
+        );
+        assert_eq!(hint(ErrorKind::EventLoopStopped), RecoveryHint::Reconnect);
+        assert_eq!(hint(ErrorKind::HttpError(StatusCode::NotFound)), RecoveryHint::GiveUp);
+    }
+
+    #[test]
+    fn retries_rate_limited_after_requested_delay() {
+        let policy = RetryPolicy::new(1, Duration::from_millis(100));
+        let rate_limited = Error::from(ErrorKind::RateLimited(Some(Duration::from_secs(5))));
+        assert!(!policy.should_retry(0, &rate_limited));
+
+        let policy = policy.retry_rate_limited(true);
+        assert!(policy.should_retry(0, &rate_limited));
+        assert_eq!(policy.delay_after(0, &rate_limited), Duration::from_secs(5));
+    }
+}
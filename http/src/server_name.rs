@@ -0,0 +1,168 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hyper::Uri;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Overrides of the name used for TLS SNI and certificate verification, keyed on the authority
+/// (host and optional port) of the URI being connected to. Cloning it gives a new reference to the
+/// same set of overrides.
+///
+/// Needed when connecting to a server by IP address, or through an IP based load balancer, while
+/// the certificate of the server is issued for a DNS name.
+#[derive(Debug, Clone, Default)]
+pub struct TlsServerNames {
+    names: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl TlsServerNames {
+    /// Use `server_name` instead of the URI host when establishing TLS connections to `authority`.
+    pub fn set(&self, authority: impl Into<String>, server_name: impl Into<String>) {
+        self.names
+            .lock()
+            .unwrap()
+            .insert(authority.into(), server_name.into());
+    }
+
+    /// Removes any override for `authority`.
+    pub fn remove(&self, authority: &str) {
+        self.names.lock().unwrap().remove(authority);
+    }
+
+    /// Returns the name to use for TLS connections to the given URI.
+    pub fn server_name(&self, uri: &Uri) -> Option<String> {
+        let names = self.names.lock().unwrap();
+        uri.authority()
+            .and_then(|authority| names.get(authority))
+            .cloned()
+            .or_else(|| uri.host().map(|host| host.to_owned()))
+    }
+}
+
+#[cfg(feature = "tls")]
+pub use self::connector::HttpsSniConnector;
+
+#[cfg(feature = "tls")]
+mod connector {
+    use super::TlsServerNames;
+    use connector::{SocketConnector, SocketOptions};
+    use futures::{future, Future};
+    use hyper::client::{Connect, HttpConnector};
+    use hyper::Uri;
+    use hyper_tls::MaybeHttpsStream;
+    use native_tls::{self, TlsConnector};
+    use std::io;
+    use tokio_core::net::TcpStream;
+    use tokio_core::reactor::Handle;
+    use tokio_service::Service;
+    use tokio_tls::TlsConnectorExt;
+
+    /// A connector for http and https URIs, like `hyper_tls::HttpsConnector`, except that the
+    /// name used for SNI and certificate verification can be overridden per authority with
+    /// [`TlsServerNames`](struct.TlsServerNames.html).
+    #[derive(Clone)]
+    pub struct HttpsSniConnector {
+        http: SocketConnector<HttpConnector>,
+        tls: TlsConnector,
+        server_names: TlsServerNames,
+    }
+
+    impl HttpsSniConnector {
+        /// Creates a connector resolving DNS on `dns_threads` threads and looking up server name
+        /// overrides in `server_names`.
+        pub fn new(
+            dns_threads: usize,
+            handle: &Handle,
+            server_names: TlsServerNames,
+        ) -> Result<Self, native_tls::Error> {
+            let mut http = HttpConnector::new(dns_threads, handle);
+            http.enforce_http(false);
+            let tls = TlsConnector::builder()?.build()?;
+            Ok(HttpsSniConnector {
+                http: SocketConnector::new(http, SocketOptions::default(), handle),
+                tls,
+                server_names,
+            })
+        }
+
+        /// Sets the options of the TCP sockets of the connections.
+        pub fn socket_options(mut self, options: SocketOptions) -> Self {
+            self.http.set_options(options);
+            self
+        }
+    }
+
+    impl ::std::fmt::Debug for HttpsSniConnector {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            f.debug_struct("HttpsSniConnector")
+                .field("server_names", &self.server_names)
+                .finish()
+        }
+    }
+
+    impl Service for HttpsSniConnector {
+        type Request = Uri;
+        type Response = MaybeHttpsStream<TcpStream>;
+        type Error = io::Error;
+        type Future = Box<dyn Future<Item = Self::Response, Error = io::Error>>;
+
+        fn call(&self, uri: Uri) -> Self::Future {
+            let is_https = uri.scheme() == Some("https");
+            let server_name = match self.server_names.server_name(&uri) {
+                Some(server_name) => server_name,
+                None => {
+                    return Box::new(future::err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "URI is missing a host",
+                    )))
+                }
+            };
+            let tls = self.tls.clone();
+            let connecting = self.http.connect(uri);
+            if is_https {
+                Box::new(connecting.and_then(move |tcp| {
+                    trace!("Establishing TLS connection to {}", server_name);
+                    tls.connect_async(&server_name, tcp)
+                        .map(MaybeHttpsStream::Https)
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                }))
+            } else {
+                Box::new(connecting.map(MaybeHttpsStream::Http))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn server_name_override() {
+        let server_names = TlsServerNames::default();
+        server_names.set("10.0.0.1:8443", "rpc.example.com");
+        let overridden = Uri::from_str("https://10.0.0.1:8443/").unwrap();
+        let other_port = Uri::from_str("https://10.0.0.1/").unwrap();
+        assert_eq!(
+            server_names.server_name(&overridden),
+            Some("rpc.example.com".to_owned())
+        );
+        assert_eq!(
+            server_names.server_name(&other_port),
+            Some("10.0.0.1".to_owned())
+        );
+
+        server_names.remove("10.0.0.1:8443");
+        assert_eq!(
+            server_names.server_name(&overridden),
+            Some("10.0.0.1".to_owned())
+        );
+    }
+}
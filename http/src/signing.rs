@@ -0,0 +1,189 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hmac::{Hmac, Mac};
+use hyper::Request;
+use jsonrpc_client_core::timer::{Clock, SystemClock};
+use sha2::Sha256;
+use std::fmt;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use super::credentials::SecretSource;
+use super::{CredentialStore, Result};
+
+/// Signs requests right before they are sent, for APIs that authenticate every request. Set on a
+/// handle with [`HttpHandle::set_signer`](struct.HttpHandle.html#method.set_signer).
+pub trait RequestSigner: Send + Sync {
+    /// Adds the authentication headers to `request`. `body` is the body exactly as it will be
+    /// sent, after any compression. Returning an error fails the call without sending it.
+    fn sign(&self, request: &mut Request, body: &[u8]) -> Result<()>;
+}
+
+/// The signer of a handle.
+#[derive(Clone)]
+pub struct Signer(pub Arc<dyn RequestSigner>);
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Signer")
+    }
+}
+
+/// Signs requests with an HMAC-SHA256 over the current time and the body.
+///
+/// The time is written as milliseconds since the Unix epoch to the timestamp header. The
+/// signature is the lowercase hex encoded HMAC of the timestamp string directly followed by the
+/// body, and is written to the signature header. The headers default to `X-Timestamp` and
+/// `X-Signature`.
+pub struct HmacSigner {
+    key: SecretSource,
+    signature_header: String,
+    timestamp_header: String,
+    clock: Box<dyn Clock + Send + Sync>,
+}
+
+impl HmacSigner {
+    /// Creates a signer using the given secret key.
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self::with_key(SecretSource::Fixed(key.into()))
+    }
+
+    /// Creates a signer using the secret named `key` from `store`, looked up for every request so
+    /// that a rotated key is picked up. Fails the calls with `CredentialError` when the store has
+    /// no such secret.
+    pub fn from_store(store: impl CredentialStore + 'static, key: impl Into<String>) -> Self {
+        Self::with_key(SecretSource::stored(store, key))
+    }
+
+    fn with_key(key: SecretSource) -> Self {
+        HmacSigner {
+            key,
+            signature_header: "X-Signature".to_owned(),
+            timestamp_header: "X-Timestamp".to_owned(),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Configure the name of the header the signature is written to.
+    pub fn signature_header(mut self, name: impl Into<String>) -> Self {
+        self.signature_header = name.into();
+        self
+    }
+
+    /// Configure the name of the header the timestamp is written to.
+    pub fn timestamp_header(mut self, name: impl Into<String>) -> Self {
+        self.timestamp_header = name.into();
+        self
+    }
+
+    /// Configure the clock the timestamps are read from. Defaults to the system time.
+    pub fn clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Returns the hex encoded signature of the given timestamp and body.
+    fn signature(&self, timestamp: &str, body: &[u8]) -> Result<String> {
+        let key = self.key.bytes()?;
+        let mut mac = Hmac::<Sha256>::new_varkey(&key).expect("HMAC accepts keys of any length");
+        mac.input(timestamp.as_bytes());
+        mac.input(body);
+        Ok(hex_encode(&mac.result().code()))
+    }
+}
+
+impl fmt::Debug for HmacSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HmacSigner")
+            .field("signature_header", &self.signature_header)
+            .field("timestamp_header", &self.timestamp_header)
+            .finish()
+    }
+}
+
+impl RequestSigner for HmacSigner {
+    fn sign(&self, request: &mut Request, body: &[u8]) -> Result<()> {
+        let since_epoch = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let timestamp = (since_epoch.as_secs() * 1000
+            + u64::from(since_epoch.subsec_nanos() / 1_000_000))
+            .to_string();
+        let signature = self.signature(&timestamp, body)?;
+        let headers = request.headers_mut();
+        headers.set_raw(self.timestamp_header.clone(), timestamp);
+        headers.set_raw(self.signature_header.clone(), signature);
+        Ok(())
+    }
+}
+
+/// Encodes bytes as lowercase hex.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Method;
+    use jsonrpc_client_core::timer::ManualTimer;
+    use {ErrorKind, Secret};
+    use std::time::Duration;
+
+    #[test]
+    fn hmac_signature_headers() {
+        let clock = ManualTimer::default();
+        clock.advance(Duration::from_millis(1_500));
+        let signer = HmacSigner::new("key")
+            .signature_header("X-Sig")
+            .clock(clock);
+        let mut request = Request::new(Method::Post, "http://localhost/".parse().unwrap());
+        signer.sign(&mut request, b"body").unwrap();
+
+        let headers = request.headers();
+        assert_eq!(headers.get_raw("X-Timestamp").unwrap(), "1500");
+        assert_eq!(
+            headers.get_raw("X-Sig").unwrap(),
+            signer.signature("1500", b"body").unwrap().as_str()
+        );
+        assert_eq!(
+            signer
+                .signature("", b"The quick brown fox jumps over the lazy dog")
+                .unwrap(),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn hmac_key_from_store() {
+        let store = |name: &str| -> Result<Secret> {
+            match name {
+                "hmac-key" => Ok(Secret::new("key")),
+                _ => Err(ErrorKind::CredentialError(name.to_owned()).into()),
+            }
+        };
+        let signer = HmacSigner::from_store(store, "hmac-key");
+        assert_eq!(
+            signer.signature("1500", b"body").unwrap(),
+            HmacSigner::new("key").signature("1500", b"body").unwrap()
+        );
+
+        let mut request = Request::new(Method::Post, "http://localhost/".parse().unwrap());
+        let error = HmacSigner::from_store(store, "missing")
+            .sign(&mut request, b"body")
+            .unwrap_err();
+        assert!(match *error.kind() {
+            ErrorKind::CredentialError(_) => true,
+            _ => false,
+        });
+        assert!(request.headers().get_raw("X-Signature").is_none());
+    }
+}
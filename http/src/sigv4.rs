@@ -0,0 +1,328 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hmac::{Hmac, Mac};
+use hyper::header::Host;
+use hyper::Request;
+use jsonrpc_client_core::timer::{Clock, SystemClock};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::credentials::SecretSource;
+use super::signing::{hex_encode, RequestSigner};
+use super::{CredentialStore, Result};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Signs requests with AWS Signature Version 4, for endpoints behind API Gateway with IAM
+/// authorization. Requires the `aws-sigv4` feature.
+///
+/// Signs the `host` and `x-amz-date` headers, and `x-amz-security-token` when a session token is
+/// used, together with the method, path, query and body of the request.
+pub struct SigV4Signer {
+    access_key_id: String,
+    secret_access_key: SecretSource,
+    session_token: Option<String>,
+    region: String,
+    service: String,
+    clock: Box<dyn Clock + Send + Sync>,
+}
+
+impl SigV4Signer {
+    /// Creates a signer for the given credentials and region, signing for the `execute-api`
+    /// service used by API Gateway.
+    pub fn new(
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+        region: impl Into<String>,
+    ) -> Self {
+        let secret_access_key = SecretSource::Fixed(secret_access_key.into().into_bytes());
+        Self::with_secret(access_key_id.into(), secret_access_key, region.into())
+    }
+
+    /// Creates a signer for the given access key whose secret access key is the secret named
+    /// `secret_access_key` in `store`, looked up for every request so that a rotated key is picked
+    /// up. Fails the calls with `CredentialError` when the store has no such secret.
+    pub fn from_store(
+        access_key_id: impl Into<String>,
+        store: impl CredentialStore + 'static,
+        secret_access_key: impl Into<String>,
+        region: impl Into<String>,
+    ) -> Self {
+        let secret_access_key = SecretSource::stored(store, secret_access_key);
+        Self::with_secret(access_key_id.into(), secret_access_key, region.into())
+    }
+
+    fn with_secret(access_key_id: String, secret_access_key: SecretSource, region: String) -> Self {
+        SigV4Signer {
+            access_key_id,
+            secret_access_key,
+            session_token: None,
+            region,
+            service: "execute-api".to_owned(),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Configure the session token of temporary credentials.
+    pub fn session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Configure the name of the service to sign for. Defaults to `execute-api`.
+    pub fn service(mut self, service: impl Into<String>) -> Self {
+        self.service = service.into();
+        self
+    }
+
+    /// Configure the clock the signing time is read from. Defaults to the system time.
+    pub fn clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Derives the signing key for the given date, formatted as `YYYYMMDD`.
+    fn signing_key(&self, date: &str) -> Result<Vec<u8>> {
+        let mut secret = b"AWS4".to_vec();
+        secret.extend(self.secret_access_key.bytes()?);
+        let date_key = hmac_sha256(&secret, date.as_bytes());
+        let region_key = hmac_sha256(&date_key, self.region.as_bytes());
+        let service_key = hmac_sha256(&region_key, self.service.as_bytes());
+        Ok(hmac_sha256(&service_key, b"aws4_request"))
+    }
+}
+
+impl fmt::Debug for SigV4Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SigV4Signer")
+            .field("access_key_id", &self.access_key_id)
+            .field("region", &self.region)
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+impl RequestSigner for SigV4Signer {
+    fn sign(&self, request: &mut Request, body: &[u8]) -> Result<()> {
+        let timestamp = amz_date(self.clock.now());
+        let date = timestamp[..8].to_owned();
+        // Signs the `Host` header that is sent, which differs from the authority of the URI for
+        // Unix sockets.
+        let host = match request.headers().get::<Host>() {
+            Some(host) => host.to_string(),
+            None => request.uri().authority().unwrap_or("").to_owned(),
+        };
+
+        let mut canonical_headers = vec![("host", host), ("x-amz-date", timestamp.clone())];
+        if let Some(ref token) = self.session_token {
+            canonical_headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = canonical_headers
+            .iter()
+            .map(|&(name, _)| name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            request.method(),
+            canonical_path(request.uri().path()),
+            canonical_query(request.uri().query().unwrap_or("")),
+            canonical_headers
+                .iter()
+                .map(|&(name, ref value)| format!("{}:{}\n", name, value.trim()))
+                .collect::<String>(),
+            signed_headers,
+            hex_encode(&Sha256::digest(body)),
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            timestamp,
+            scope,
+            hex_encode(&Sha256::digest(canonical_request.as_bytes())),
+        );
+        let signature = hex_encode(&hmac_sha256(
+            &self.signing_key(&date)?,
+            string_to_sign.as_bytes(),
+        ));
+
+        let headers = request.headers_mut();
+        headers.set_raw("X-Amz-Date", timestamp);
+        if let Some(ref token) = self.session_token {
+            headers.set_raw("X-Amz-Security-Token", token.clone());
+        }
+        headers.set_raw(
+            "Authorization",
+            format!(
+                "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                ALGORITHM, self.access_key_id, scope, signed_headers, signature
+            ),
+        );
+        Ok(())
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+fn canonical_path(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
+
+/// Sorts the already encoded query parameters by name and value.
+fn canonical_query(query: &str) -> String {
+    let mut parameters = query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| {
+            let mut parts = parameter.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            (name, parts.next().unwrap_or(""))
+        }).collect::<Vec<_>>();
+    parameters.sort();
+    parameters
+        .iter()
+        .map(|&(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Formats a time as `YYYYMMDD'T'HHMMSS'Z'` in UTC.
+fn amz_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Method;
+    use jsonrpc_client_core::timer::ManualTimer;
+    use std::time::Duration;
+    use {ErrorKind, Secret};
+
+    #[test]
+    fn formats_amz_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+        assert_eq!(amz_date(time), "20150830T123600Z");
+        assert_eq!(amz_date(UNIX_EPOCH), "19700101T000000Z");
+    }
+
+    #[test]
+    fn signs_get_vanilla() {
+        // The `get-vanilla` case of the AWS Signature Version 4 test suite.
+        let clock = ManualTimer::new(UNIX_EPOCH + Duration::from_secs(1_440_938_160));
+        let signer = SigV4Signer::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "us-east-1",
+        ).service("service")
+        .clock(clock);
+        let uri = "http://example.amazonaws.com/".parse().unwrap();
+        let mut request = Request::new(Method::Get, uri);
+        signer.sign(&mut request, b"").unwrap();
+
+        assert_eq!(
+            request.headers().get_raw("Authorization").unwrap(),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert_eq!(request.headers().get_raw("X-Amz-Date").unwrap(), "20150830T123600Z");
+    }
+
+    #[test]
+    fn signs_sent_host_header() {
+        let clock = ManualTimer::new(UNIX_EPOCH + Duration::from_secs(1_440_938_160));
+        let signer = SigV4Signer::new("AKIDEXAMPLE", "secret", "us-east-1").clock(clock);
+        let uri = "unix://2f746d702f6e6f64652e736f636b/".parse().unwrap();
+        let mut unix_request = Request::new(Method::Post, uri);
+        unix_request
+            .headers_mut()
+            .set(Host::new("localhost", None));
+        signer.sign(&mut unix_request, b"{}").unwrap();
+        let mut request = Request::new(Method::Post, "http://localhost/".parse().unwrap());
+        signer.sign(&mut request, b"{}").unwrap();
+
+        assert_eq!(
+            unix_request.headers().get_raw("Authorization"),
+            request.headers().get_raw("Authorization")
+        );
+    }
+
+    #[test]
+    fn secret_access_key_from_store() {
+        let store = |name: &str| -> Result<Secret> {
+            match name {
+                "aws-secret" => Ok(Secret::new("secret")),
+                _ => Err(ErrorKind::CredentialError(name.to_owned()).into()),
+            }
+        };
+        let time = UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+        let stored = SigV4Signer::from_store("AKIDEXAMPLE", store, "aws-secret", "us-east-1")
+            .clock(ManualTimer::new(time));
+        let fixed =
+            SigV4Signer::new("AKIDEXAMPLE", "secret", "us-east-1").clock(ManualTimer::new(time));
+        let mut stored_request = Request::new(Method::Post, "http://localhost/".parse().unwrap());
+        stored.sign(&mut stored_request, b"{}").unwrap();
+        let mut request = Request::new(Method::Post, "http://localhost/".parse().unwrap());
+        fixed.sign(&mut request, b"{}").unwrap();
+        assert_eq!(
+            stored_request.headers().get_raw("Authorization"),
+            request.headers().get_raw("Authorization")
+        );
+
+        let missing = SigV4Signer::from_store("AKIDEXAMPLE", store, "missing", "us-east-1");
+        let error = missing.sign(&mut request, b"{}").unwrap_err();
+        assert!(match *error.kind() {
+            ErrorKind::CredentialError(_) => true,
+            _ => false,
+        });
+    }
+}
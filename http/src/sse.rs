@@ -0,0 +1,195 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::sync::mpsc;
+use futures::{Async, Poll, Sink, Stream};
+use hyper::{self, Method, Request, Uri};
+use jsonrpc_client_core::{DuplexTransport, RecoveryHint, Transport};
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use super::{CoreRequest, Error, ErrorKind, HttpHandle, Result, ResultExt};
+
+type ChunkStream = Box<dyn Stream<Item = hyper::Chunk, Error = Error> + Send>;
+
+/// A handle to a [`HttpTransport`](struct.HttpTransport.html) that, in addition to sending
+/// requests like a regular [`HttpHandle`](struct.HttpHandle.html), listens for messages pushed
+/// by the server over a Server-Sent Events stream.
+///
+/// The `data` of every event is expected to be a JSON-RPC 2.0 notification or request. They are
+/// routed to the server handler of the client just like on any other duplex transport, so
+/// subscriptions work over plain HTTP. The event stream is opened when the client is created and
+/// is not reopened if the server closes it.
+///
+/// Created with [`HttpHandle::with_sse`](struct.HttpHandle.html#method.with_sse).
+#[derive(Debug, Clone)]
+pub struct SseHandle {
+    handle: HttpHandle,
+    events_uri: Uri,
+}
+
+impl HttpHandle {
+    /// Creates a handle that also receives messages from the server over a Server-Sent Events
+    /// stream at the given URI. The headers configured on this handle are also sent when opening
+    /// the event stream.
+    pub fn with_sse(self, events_uri: &str) -> Result<SseHandle> {
+        Ok(SseHandle {
+            handle: self,
+            events_uri: Uri::from_str(events_uri)?,
+        })
+    }
+}
+
+impl SseHandle {
+    /// Opens the event stream, returning a stream of the event payloads.
+    fn open_event_stream(&self) -> SseEvents<ChunkStream> {
+        let mut request = Request::new(Method::Get, self.events_uri.clone());
+        {
+            let headers = request.headers_mut();
+            headers.set_raw("Accept", "text/event-stream");
+            headers.extend(self.handle.headers.iter());
+        }
+        let (chunk_tx, chunk_rx) = mpsc::unbounded();
+        let chunks: ChunkStream =
+            match self
+                .handle
+                .request_tx
+                .unbounded_send(self.handle.priority, CoreRequest::Stream(request, chunk_tx))
+            {
+                Ok(()) => Box::new(chunk_rx.then(|chunk| match chunk {
+                    Ok(chunk) => chunk,
+                    Err(()) => Err(ErrorKind::EventLoopStopped.into()),
+                })),
+                Err(_) => Box::new(::futures::stream::once(Err(
+                    ErrorKind::EventLoopStopped.into(),
+                ))),
+            };
+        SseEvents::new(chunks)
+    }
+}
+
+impl Transport for SseHandle {
+    type Error = Error;
+    type Sink = Box<dyn Sink<SinkItem = String, SinkError = Self::Error> + Send>;
+    type Stream = Box<dyn Stream<Item = String, Error = Self::Error> + Send>;
+
+    fn endpoint(&self) -> Option<String> {
+        self.handle.endpoint()
+    }
+
+    fn recovery_hint(error: &Error) -> RecoveryHint {
+        <HttpHandle as Transport>::recovery_hint(error)
+    }
+
+    fn io_pair(self) -> (Self::Sink, Self::Stream) {
+        let events = self.open_event_stream();
+        let (sink, responses) = self.handle.io_pair();
+        (sink, Box::new(responses.select(events)))
+    }
+}
+
+impl DuplexTransport for SseHandle {}
+
+/// Stream adapter parsing a `text/event-stream` body into the data of the events.
+struct SseEvents<S> {
+    chunks: S,
+    buffer: Vec<u8>,
+    events: VecDeque<String>,
+}
+
+impl<S> SseEvents<S> {
+    fn new(chunks: S) -> Self {
+        SseEvents {
+            chunks,
+            buffer: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Appends received bytes to the buffer and queues the data of every complete event.
+    fn push_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        // Normalize line endings so events are always separated by "\n\n".
+        self.buffer
+            .extend(bytes.iter().cloned().filter(|&byte| byte != b'\r'));
+        while let Some(end) = self.buffer.windows(2).position(|window| window == b"\n\n") {
+            let event = self.buffer.drain(..end + 2).collect::<Vec<u8>>();
+            let event = String::from_utf8(event).chain_err(|| ErrorKind::ParseBodyError)?;
+            if let Some(data) = parse_event_data(&event) {
+                self.events.push_back(data);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Stream<Item = hyper::Chunk, Error = Error>> Stream for SseEvents<S> {
+    type Item = String;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<String>, Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Async::Ready(Some(event)));
+            }
+            match self.chunks.poll()? {
+                Async::Ready(Some(chunk)) => self.push_bytes(&chunk)?,
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+/// Extracts the data of a single event, joining multiple `data` fields with newlines. Returns
+/// `None` for events without data, such as keep-alive comments.
+fn parse_event_data(event: &str) -> Option<String> {
+    let data_lines = event
+        .lines()
+        .filter(|line| line.starts_with("data:"))
+        .map(|line| {
+            let value = &line["data:".len()..];
+            if value.starts_with(' ') {
+                &value[1..]
+            } else {
+                value
+            }
+        }).collect::<Vec<&str>>();
+    if data_lines.is_empty() {
+        None
+    } else {
+        Some(data_lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{stream, Future};
+
+    fn parse(chunks: &[&'static str]) -> Vec<String> {
+        let chunks = stream::iter_ok::<_, Error>(
+            chunks
+                .iter()
+                .map(|chunk| hyper::Chunk::from(*chunk))
+                .collect::<Vec<_>>(),
+        );
+        SseEvents::new(chunks).collect().wait().unwrap()
+    }
+
+    #[test]
+    fn parses_events_split_over_chunks() {
+        let events = parse(&["data: {\"a\":", "1}\n\n: keep-alive\n\nda", "ta:2\r\n\r\n"]);
+        assert_eq!(events, vec!["{\"a\":1}".to_owned(), "2".to_owned()]);
+    }
+
+    #[test]
+    fn joins_multiline_data() {
+        let events = parse(&["event: message\ndata: [1,\ndata: 2]\n\n"]);
+        assert_eq!(events, vec!["[1,\n2]".to_owned()]);
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Error, ErrorKind};
+
+/// A snapshot of the request statistics of a `HttpTransport`, returned by
+/// [`HttpTransport::stats`](struct.HttpTransport.html#method.stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Requests sent to the event loop(s) that have not started processing yet.
+    pub queued: usize,
+    /// Requests currently being sent or waiting for a response. Open response streams are counted
+    /// until they are closed.
+    pub in_flight: usize,
+    /// Total number of requests sent through the transport.
+    pub total_requests: usize,
+    /// Total number of requests that failed, including timeouts.
+    pub errors: usize,
+    /// Total number of requests that failed because they timed out.
+    pub timeouts: usize,
+}
+
+/// The counters behind `Stats`, shared between the handles and the event loop(s) of a transport.
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    total_requests: AtomicUsize,
+    errors: AtomicUsize,
+    timeouts: AtomicUsize,
+}
+
+impl StatsCounters {
+    /// A request is about to be sent to an event loop.
+    pub fn request_queued(&self) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        self.total_requests.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// A request could not be sent to an event loop after all.
+    pub fn request_unqueued(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        self.total_requests.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// The event loop started processing a request.
+    pub fn request_started(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// The event loop finished processing a request, possibly with an error.
+    pub fn request_finished(&self, error: Option<&Error>) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        if let Some(error) = error {
+            self.errors.fetch_add(1, Ordering::SeqCst);
+            match *error.kind() {
+                ErrorKind::RequestTimeout | ErrorKind::ReadTimeout => {
+                    self.timeouts.fetch_add(1, Ordering::SeqCst);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns the current value of all counters.
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            queued: self.queued.load(Ordering::SeqCst),
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            total_requests: self.total_requests.load(Ordering::SeqCst),
+            errors: self.errors.load(Ordering::SeqCst),
+            timeouts: self.timeouts.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_request_lifecycle() {
+        let counters = StatsCounters::default();
+        counters.request_queued();
+        counters.request_queued();
+        counters.request_queued();
+        counters.request_unqueued();
+        counters.request_started();
+        assert_eq!(
+            counters.snapshot(),
+            Stats {
+                queued: 1,
+                in_flight: 1,
+                total_requests: 2,
+                errors: 0,
+                timeouts: 0,
+            }
+        );
+
+        counters.request_finished(Some(&ErrorKind::RequestTimeout.into()));
+        counters.request_started();
+        counters.request_finished(None);
+        assert_eq!(
+            counters.snapshot(),
+            Stats {
+                queued: 0,
+                in_flight: 0,
+                total_requests: 2,
+                errors: 1,
+                timeouts: 1,
+            }
+        );
+    }
+}
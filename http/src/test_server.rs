@@ -0,0 +1,253 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A tiny in-process JSON-RPC 2.0 server for tests. Compiled if the "test-server" feature is
+//! enabled.
+//!
+//! The server runs on its own thread, listens on a random port on localhost and answers every
+//! call deterministically: methods registered as fixtures return their canned result or error,
+//! and all other methods echo their parameters back as the result. This makes it possible to
+//! exercise the full HTTP path in tests without spawning external processes.
+//!
+//! ```rust,no_run
+//! # extern crate jsonrpc_client_http;
+//! # extern crate serde_json;
+//! use jsonrpc_client_http::test_server::TestServer;
+//!
+//! # fn main() {
+//! let server = TestServer::builder()
+//!     .result("version", serde_json::Value::from("1.0.0"))
+//!     .error("fail", -32000, "Failure")
+//!     .spawn()
+//!     .unwrap();
+//! let uri = server.uri();
+//! // Send requests to `uri`, then inspect them with `server.requests()`.
+//! # }
+//! ```
+
+use futures::sync::oneshot;
+use futures::{future, Future, Stream};
+use hyper::header::{ContentLength, ContentType};
+use hyper::server::{Http, Request, Response, Service};
+use hyper::{self, StatusCode};
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use super::{ErrorKind, Result, ResultExt};
+
+/// The canned response to calls to a method.
+#[derive(Debug, Clone)]
+enum Fixture {
+    Result(Value),
+    Error(Value),
+    NoResponse,
+}
+
+/// Builder for a [`TestServer`](struct.TestServer.html).
+#[derive(Debug, Default)]
+pub struct TestServerBuilder {
+    fixtures: HashMap<String, Fixture>,
+}
+
+impl TestServerBuilder {
+    /// Make calls to `method` return `result`.
+    pub fn result(mut self, method: impl Into<String>, result: Value) -> Self {
+        self.fixtures.insert(method.into(), Fixture::Result(result));
+        self
+    }
+
+    /// Make calls to `method` return an error object with the given code and message.
+    pub fn error(mut self, method: impl Into<String>, code: i64, message: &str) -> Self {
+        let error = json_object(vec![
+            ("code", Value::from(code)),
+            ("message", Value::from(message)),
+        ]);
+        self.fixtures.insert(method.into(), Fixture::Error(error));
+        self
+    }
+
+    /// Make calls to `method` go unanswered, like notifications, so that a request containing only
+    /// such calls is answered with an empty body.
+    pub fn no_response(mut self, method: impl Into<String>) -> Self {
+        self.fixtures.insert(method.into(), Fixture::NoResponse);
+        self
+    }
+
+    /// Starts the server on a random port on localhost.
+    pub fn spawn(self) -> Result<TestServer> {
+        let service = TestService {
+            fixtures: Arc::new(self.fixtures),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let requests = service.requests.clone();
+        let (address_tx, address_rx) = mpsc::channel();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        thread::Builder::new()
+            .name("jsonrpc-test-server".to_owned())
+            .spawn(move || {
+                let bind_address = "127.0.0.1:0".parse().unwrap();
+                let server = match Http::new().bind(&bind_address, move || Ok(service.clone())) {
+                    Ok(server) => server,
+                    Err(e) => {
+                        address_tx.send(Err(e)).unwrap();
+                        return;
+                    }
+                };
+                address_tx.send(server.local_addr()).unwrap();
+                if let Err(e) = server.run_until(shutdown_rx.then(|_| Ok::<(), ()>(()))) {
+                    error!("Test server failed: {}", e);
+                }
+            }).chain_err(|| ErrorKind::TokioCoreError("Unable to spawn test server thread"))?;
+        let address = address_rx
+            .recv()
+            .chain_err(|| ErrorKind::TokioCoreError("Test server thread died"))??;
+        Ok(TestServer {
+            address,
+            requests,
+            shutdown_tx: Some(shutdown_tx),
+        })
+    }
+}
+
+/// A running in-process JSON-RPC server. Shuts down when dropped.
+#[derive(Debug)]
+pub struct TestServer {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<Value>>>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl TestServer {
+    /// Returns a builder for a server with custom fixtures.
+    pub fn builder() -> TestServerBuilder {
+        TestServerBuilder::default()
+    }
+
+    /// Starts a server without fixtures, echoing the parameters of all calls back as the result.
+    pub fn echo() -> Result<TestServer> {
+        Self::builder().spawn()
+    }
+
+    /// The address the server is listening on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The http URI of the server, for use with `HttpTransport::handle`.
+    pub fn uri(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+
+    /// Returns all JSON-RPC request objects received so far, in the order they were received.
+    /// The members of batch requests are returned individually.
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TestService {
+    fixtures: Arc<HashMap<String, Fixture>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl TestService {
+    /// Returns the response to a single request object, or `None` for notifications and calls
+    /// that go unanswered.
+    fn respond(&self, request: Value) -> Option<Value> {
+        self.requests.lock().unwrap().push(request.clone());
+        let id = request.get("id")?.clone();
+        let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+        let (key, value) = match self.fixtures.get(method) {
+            Some(Fixture::Result(result)) => ("result", result.clone()),
+            Some(Fixture::Error(error)) => ("error", error.clone()),
+            Some(Fixture::NoResponse) => return None,
+            None => (
+                "result",
+                request.get("params").cloned().unwrap_or(Value::Null),
+            ),
+        };
+        Some(json_object(vec![
+            ("jsonrpc", Value::from("2.0")),
+            (key, value),
+            ("id", id),
+        ]))
+    }
+
+    fn respond_to_body(&self, body: &[u8]) -> Option<Value> {
+        match serde_json::from_slice(body) {
+            Ok(Value::Array(requests)) => {
+                let responses = requests
+                    .into_iter()
+                    .filter_map(|request| self.respond(request))
+                    .collect::<Vec<_>>();
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Value::Array(responses))
+                }
+            }
+            Ok(request) => self.respond(request),
+            Err(_) => Some(json_object(vec![
+                ("jsonrpc", Value::from("2.0")),
+                (
+                    "error",
+                    json_object(vec![
+                        ("code", Value::from(-32700)),
+                        ("message", Value::from("Parse error")),
+                    ]),
+                ),
+                ("id", Value::Null),
+            ])),
+        }
+    }
+}
+
+impl Service for TestService {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<dyn Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let service = self.clone();
+        Box::new(request.body().concat2().and_then(move |body| {
+            let response = match service.respond_to_body(&body) {
+                Some(json) => {
+                    let body = serde_json::to_vec(&json).unwrap();
+                    Response::new()
+                        .with_header(ContentType::json())
+                        .with_header(ContentLength(body.len() as u64))
+                        .with_body(body)
+                }
+                None => Response::new().with_status(StatusCode::Ok),
+            };
+            future::ok(response)
+        }))
+    }
+}
+
+fn json_object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+    )
+}
@@ -0,0 +1,99 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::Future;
+use jsonrpc_client_core::timer::{Clock, Timer};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio_core::reactor::{Handle, Timeout};
+
+/// A `Timer` that is also a `Clock`, as configured with
+/// [`HttpTransportBuilder::timer`](struct.HttpTransportBuilder.html#method.timer).
+pub trait TimerClock: Timer + Clock + Send + Sync {}
+
+impl<T: Timer + Clock + Send + Sync> TimerClock for T {}
+
+/// A user supplied timer shared by all event loops of a transport.
+#[derive(Clone)]
+pub struct CustomTimer(pub Arc<dyn TimerClock>);
+
+impl fmt::Debug for CustomTimer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CustomTimer")
+    }
+}
+
+/// The source of time and delays for everything that waits on an event loop: request timeouts,
+/// retry backoff, rate limits and idle shutdown. Uses Tokio timeouts unless a custom timer has
+/// been configured.
+#[derive(Clone)]
+pub enum EventLoopTimer {
+    Tokio(Handle),
+    Custom(CustomTimer),
+}
+
+impl EventLoopTimer {
+    pub fn new(custom: &Option<CustomTimer>, handle: &Handle) -> Self {
+        match *custom {
+            Some(ref timer) => EventLoopTimer::Custom(timer.clone()),
+            None => EventLoopTimer::Tokio(handle.clone()),
+        }
+    }
+
+    /// Returns a future resolving after `duration`. An error from the future is treated the
+    /// same as the delay expiring.
+    pub fn delay(&self, duration: Duration) -> Box<dyn Future<Item = (), Error = ()>> {
+        match *self {
+            EventLoopTimer::Tokio(ref handle) => Box::new(
+                Timeout::new(duration, handle)
+                    .expect("failure to create Timeout")
+                    .map_err(|e| warn!("Timer failed: {}", e)),
+            ),
+            EventLoopTimer::Custom(CustomTimer(ref timer)) => {
+                Box::new(timer.delay(duration))
+            }
+        }
+    }
+
+    /// Returns a stopwatch started at the current time.
+    pub fn stopwatch(&self) -> Stopwatch {
+        match *self {
+            EventLoopTimer::Tokio(_) => Stopwatch::Monotonic(Instant::now()),
+            EventLoopTimer::Custom(ref timer) => Stopwatch::Custom(timer.clone(), timer.0.now()),
+        }
+    }
+
+    /// Returns the current time.
+    pub fn now(&self) -> SystemTime {
+        match *self {
+            EventLoopTimer::Tokio(_) => SystemTime::now(),
+            EventLoopTimer::Custom(CustomTimer(ref timer)) => timer.now(),
+        }
+    }
+}
+
+/// Measures the time elapsed since it was started. Uses `Instant` unless a custom timer has been
+/// configured, so changes to the system clock don't affect it.
+#[derive(Clone)]
+pub enum Stopwatch {
+    Monotonic(Instant),
+    Custom(CustomTimer, SystemTime),
+}
+
+impl Stopwatch {
+    /// Returns the time elapsed since the stopwatch was started.
+    pub fn elapsed(&self) -> Duration {
+        match *self {
+            Stopwatch::Monotonic(start) => start.elapsed(),
+            Stopwatch::Custom(CustomTimer(ref timer), start) => {
+                timer.now().duration_since(start).unwrap_or_default()
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::{future, Future, Poll};
+use hyper::client::Connect;
+use hyper::Uri;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_service::Service;
+#[cfg(unix)]
+use tokio_uds::UnixStream;
+
+use super::Result;
+
+/// The URI scheme for HTTP over Unix domain sockets.
+pub const UNIX_SCHEME: &str = "unix";
+
+/// Converts a `unix:///path/to/socket` URI into the internal representation used by Hyper. Since
+/// Hyper requires URIs to have an authority, the socket path is hex encoded into the authority.
+/// The requests are sent to the root path, unless changed with `HttpHandle::with_path`.
+pub fn unix_socket_uri(uri: &str) -> Result<Uri> {
+    let path = &uri[UNIX_SCHEME.len() + "://".len()..];
+    let encoded_path = path
+        .bytes()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    Ok(Uri::from_str(&format!("{}://{}/", UNIX_SCHEME, encoded_path))?)
+}
+
+/// Returns the socket path encoded into an URI created by `unix_socket_uri`.
+fn socket_path(uri: &Uri) -> io::Result<String> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid Unix socket URI");
+    let encoded_path = uri.host().ok_or_else(invalid)?;
+    if encoded_path.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    let bytes = (0..encoded_path.len() / 2)
+        .map(|index| u8::from_str_radix(&encoded_path[2 * index..2 * index + 2], 16))
+        .collect::<::std::result::Result<Vec<u8>, _>>()
+        .map_err(|_| invalid())?;
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+/// Returns true if the string is a `unix://` URI.
+pub fn is_unix_socket_uri(uri: &str) -> bool {
+    uri.starts_with(UNIX_SCHEME) && uri[UNIX_SCHEME.len()..].starts_with("://")
+}
+
+/// A Hyper connector that connects to Unix domain sockets for `unix://` URIs, and delegates all
+/// other URIs to an inner connector. Used by the default clients, so
+/// `HttpTransport::handle("unix:///var/run/service.sock")` works out of the box.
+///
+/// On platforms without Unix domain sockets, connecting to a `unix://` URI fails.
+#[derive(Debug, Clone)]
+pub struct UnixConnector<C> {
+    inner: C,
+    handle: Handle,
+}
+
+impl<C: Connect> UnixConnector<C> {
+    /// Wraps the given connector.
+    pub fn new(inner: C, handle: &Handle) -> Self {
+        UnixConnector {
+            inner,
+            handle: handle.clone(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(&self, uri: &Uri) -> io::Result<MaybeUnixStream<C::Output>> {
+        let path = socket_path(uri)?;
+        trace!("Connecting to Unix socket {}", path);
+        UnixStream::connect(path, &self.handle).map(MaybeUnixStream::Unix)
+    }
+
+    #[cfg(not(unix))]
+    fn connect_unix(&self, _uri: &Uri) -> io::Result<MaybeUnixStream<C::Output>> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Unix sockets are not supported on this platform",
+        ))
+    }
+}
+
+impl<C: Connect> Service for UnixConnector<C> {
+    type Request = Uri;
+    type Response = MaybeUnixStream<C::Output>;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = io::Error>>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        if uri.scheme() == Some(UNIX_SCHEME) {
+            Box::new(future::result(self.connect_unix(&uri)))
+        } else {
+            Box::new(self.inner.connect(uri).map(MaybeUnixStream::Inner))
+        }
+    }
+}
+
+/// A stream that is either a Unix domain socket or the stream of the inner connector.
+#[derive(Debug)]
+pub enum MaybeUnixStream<T> {
+    /// A connection made by the inner connector.
+    Inner(T),
+    /// A Unix domain socket connection.
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl<T: Read> Read for MaybeUnixStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MaybeUnixStream::Inner(stream) => stream.read(buf),
+            #[cfg(unix)]
+            MaybeUnixStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<T: Write> Write for MaybeUnixStream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MaybeUnixStream::Inner(stream) => stream.write(buf),
+            #[cfg(unix)]
+            MaybeUnixStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            MaybeUnixStream::Inner(stream) => stream.flush(),
+            #[cfg(unix)]
+            MaybeUnixStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for MaybeUnixStream<T> {}
+
+impl<T: AsyncWrite> AsyncWrite for MaybeUnixStream<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self {
+            MaybeUnixStream::Inner(stream) => stream.shutdown(),
+            #[cfg(unix)]
+            MaybeUnixStream::Unix(stream) => AsyncWrite::shutdown(stream),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_path_round_trip() {
+        let uri = unix_socket_uri("unix:///var/run/service.sock").unwrap();
+        assert_eq!(uri.scheme(), Some(UNIX_SCHEME));
+        assert_eq!(uri.path(), "/");
+        assert_eq!(socket_path(&uri).unwrap(), "/var/run/service.sock");
+    }
+
+    #[test]
+    fn detects_unix_socket_uris() {
+        assert!(is_unix_socket_uri("unix:///tmp/socket"));
+        assert!(!is_unix_socket_uri("http://unix/"));
+    }
+}
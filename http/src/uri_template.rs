@@ -0,0 +1,164 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hyper::Uri;
+use serde_json::{self, Value};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::{ErrorKind, Result};
+
+/// Information about the call being sent, given to the resolver of a URI template.
+#[derive(Debug, Clone, Copy)]
+pub struct CallContext<'a> {
+    method: Option<&'a str>,
+}
+
+impl<'a> CallContext<'a> {
+    /// The name of the method being called. `None` for batches, and when the template is rendered
+    /// while creating the handle.
+    pub fn method(&self) -> Option<&'a str> {
+        self.method
+    }
+}
+
+/// A function resolving the value of a variable in a URI template.
+type Resolver = dyn Fn(&str, &CallContext) -> Option<String> + Send + Sync;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Variable(String),
+}
+
+/// A URI with `{variable}` placeholders, rendered into a concrete URI for every request.
+#[derive(Clone)]
+pub struct UriTemplate {
+    template: String,
+    parts: Vec<Part>,
+    resolver: Arc<Resolver>,
+}
+
+impl UriTemplate {
+    /// Parses a template, where every variable is resolved by calling `resolver`.
+    pub fn parse<F>(template: &str, resolver: F) -> Result<Self>
+    where
+        F: Fn(&str, &CallContext) -> Option<String> + Send + Sync + 'static,
+    {
+        Ok(UriTemplate {
+            template: template.to_owned(),
+            parts: parse_parts(template)?,
+            resolver: Arc::new(resolver),
+        })
+    }
+
+    /// Renders the template for a request with the given JSON-RPC payload, or without a call if
+    /// `payload` is `None`.
+    pub fn render(&self, payload: Option<&[u8]>) -> Result<Uri> {
+        let message = payload.and_then(|payload| serde_json::from_slice::<Value>(payload).ok());
+        let context = CallContext {
+            method: message
+                .as_ref()
+                .and_then(|message| message.get("method"))
+                .and_then(Value::as_str),
+        };
+        let mut uri = String::with_capacity(self.template.len());
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => uri.push_str(literal),
+                Part::Variable(name) => {
+                    let value = (self.resolver)(name, &context).ok_or_else(|| {
+                        ErrorKind::UriTemplateError(format!("Unresolved variable {}", name))
+                    })?;
+                    percent_encode(&value, &mut uri);
+                }
+            }
+        }
+        Ok(Uri::from_str(&uri)?)
+    }
+}
+
+impl fmt::Debug for UriTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UriTemplate")
+            .field("template", &self.template)
+            .finish()
+    }
+}
+
+fn parse_parts(template: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_owned()));
+        }
+        let end = rest[start..].find('}').ok_or_else(|| {
+            ErrorKind::UriTemplateError(format!("Unclosed variable in {}", template))
+        })? + start;
+        let name = &rest[start + 1..end];
+        if name.is_empty() || name.contains('{') {
+            let message = format!("Invalid variable in {}", template);
+            return Err(ErrorKind::UriTemplateError(message).into());
+        }
+        parts.push(Part::Variable(name.to_owned()));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_owned()));
+    }
+    Ok(parts)
+}
+
+/// Appends `value` to `output`, percent encoding everything except unreserved characters.
+fn percent_encode(value: &str, output: &mut String) {
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                output.push(byte as char)
+            }
+            _ => output.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_variables_per_call() {
+        let template = UriTemplate::parse(
+            "https://gateway/{tenant}/rpc?apikey={key}&method={method}",
+            |name, context| match name {
+                "tenant" => Some("acme".to_owned()),
+                "key" => Some("s3cr3t/+".to_owned()),
+                "method" => Some(context.method().unwrap_or("none").to_owned()),
+                _ => None,
+            },
+        ).unwrap();
+        let payload = br#"{"jsonrpc":"2.0","method":"get_balance","params":[],"id":1}"#;
+        assert_eq!(
+            template.render(Some(payload)).unwrap().to_string(),
+            "https://gateway/acme/rpc?apikey=s3cr3t%2F%2B&method=get_balance"
+        );
+        assert_eq!(
+            template.render(None).unwrap().to_string(),
+            "https://gateway/acme/rpc?apikey=s3cr3t%2F%2B&method=none"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(UriTemplate::parse("http://host/{unclosed", |_, _| None).is_err());
+        assert!(UriTemplate::parse("http://host/{}", |_, _| None).is_err());
+        let template = UriTemplate::parse("http://host/{missing}", |_, _| None).unwrap();
+        assert!(template.render(None).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use futures::sync::mpsc;
+use futures::{future, Future, Sink, Stream};
+use jsonrpc_client_core::{RecoveryHint, Transport};
+use serde_json::{self, Map, Value as JsonValue};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{Error, ErrorKind, HttpHandle, Result, ResultExt};
+
+/// A transport that records the requests sent through an `HttpHandle` and their responses to a
+/// file, and replays the responses from that file later without a server. Lets integration tests
+/// run against a recording of a real node.
+///
+/// Recorded requests are matched against new requests with the `id` fields ignored, in the order
+/// they were recorded. The ids in replayed responses are changed to the ids of the new request.
+#[derive(Debug, Clone)]
+pub struct VcrHandle {
+    handle: Option<HttpHandle>,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl VcrHandle {
+    /// Sends requests through `handle` and records them, together with their responses, to the
+    /// file at `path`. Any existing recording in the file is replaced.
+    pub fn record(handle: HttpHandle, path: impl AsRef<Path>) -> Self {
+        VcrHandle {
+            handle: Some(handle),
+            cassette: Arc::new(Mutex::new(Cassette::new(path.as_ref(), Vec::new()))),
+        }
+    }
+
+    /// Answers requests with the responses recorded in the file at `path`. Requests that were not
+    /// recorded fail with `CassetteError`.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        let interactions = load(path.as_ref())?;
+        Ok(VcrHandle {
+            handle: None,
+            cassette: Arc::new(Mutex::new(Cassette::new(path.as_ref(), interactions))),
+        })
+    }
+
+    /// Replays the recording in the file at `path` if it exists, otherwise records to it through
+    /// `handle`.
+    pub fn open(handle: HttpHandle, path: impl AsRef<Path>) -> Result<Self> {
+        if path.as_ref().exists() {
+            Self::replay(path)
+        } else {
+            Ok(Self::record(handle, path))
+        }
+    }
+
+    /// Returns true if this handle replays a recording instead of sending requests.
+    pub fn is_replaying(&self) -> bool {
+        self.handle.is_none()
+    }
+
+    /// Sends, or replays, a request with the given body, returning a future that will resolve to
+    /// the corresponding response.
+    pub fn send(
+        &self,
+        json_data: Vec<u8>,
+    ) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        let request: JsonValue = match serde_json::from_slice(&json_data) {
+            Ok(request) => request,
+            Err(e) => {
+                return Box::new(future::err(Error::with_chain(
+                    e,
+                    ErrorKind::CassetteError("Request is not JSON".to_owned()),
+                )))
+            }
+        };
+        match self.handle {
+            Some(ref handle) => {
+                let cassette = self.cassette.clone();
+                Box::new(handle.send(json_data).and_then(move |response| -> Result<Vec<u8>> {
+                    let text = String::from_utf8(response.clone())
+                        .chain_err(|| ErrorKind::ParseBodyError)?;
+                    cassette.lock().unwrap().record(request, text)?;
+                    Ok(response)
+                }))
+            }
+            None => Box::new(future::result(
+                self.cassette
+                    .lock()
+                    .unwrap()
+                    .replay(&request)
+                    .map(String::into_bytes),
+            )),
+        }
+    }
+}
+
+impl Transport for VcrHandle {
+    type Error = Error;
+    type Sink = Box<dyn Sink<SinkItem = String, SinkError = Self::Error> + Send>;
+    type Stream = Box<dyn Stream<Item = String, Error = Self::Error> + Send>;
+
+    fn endpoint(&self) -> Option<String> {
+        self.handle.as_ref().and_then(HttpHandle::endpoint)
+    }
+
+    fn recovery_hint(error: &Error) -> RecoveryHint {
+        <HttpHandle as Transport>::recovery_hint(error)
+    }
+
+    fn io_pair(self) -> (Self::Sink, Self::Stream) {
+        let (tx, rx) = mpsc::channel(0);
+        let sink = tx
+            .sink_map_err(|_| Error::from(ErrorKind::TokioCoreError("Not listening for requests")))
+            .with(move |json_string: String| self.send(json_string.into_bytes()));
+        let stream = rx
+            .map_err(|_| Error::from(ErrorKind::TokioCoreError("Sender closed")))
+            .and_then(|bytes| String::from_utf8(bytes).chain_err(|| ErrorKind::ParseBodyError));
+        (Box::new(sink), Box::new(stream))
+    }
+}
+
+/// A recorded request and the body of its response.
+#[derive(Debug, Clone)]
+struct Interaction {
+    request: JsonValue,
+    response: String,
+}
+
+/// The recording shared by the clones of a `VcrHandle`.
+#[derive(Debug)]
+struct Cassette {
+    path: PathBuf,
+    interactions: Vec<Interaction>,
+    replayed: Vec<bool>,
+}
+
+impl Cassette {
+    fn new(path: &Path, interactions: Vec<Interaction>) -> Self {
+        Cassette {
+            path: path.to_owned(),
+            replayed: vec![false; interactions.len()],
+            interactions,
+        }
+    }
+
+    /// Adds an interaction and writes the whole recording to the file.
+    fn record(&mut self, request: JsonValue, response: String) -> Result<()> {
+        self.interactions.push(Interaction { request, response });
+        self.replayed.push(false);
+        let entries = self
+            .interactions
+            .iter()
+            .map(|interaction| {
+                let mut entry = Map::new();
+                entry.insert("request".to_owned(), interaction.request.clone());
+                entry.insert(
+                    "response".to_owned(),
+                    JsonValue::String(interaction.response.clone()),
+                );
+                JsonValue::Object(entry)
+            }).collect();
+        let data = serde_json::to_vec_pretty(&JsonValue::Array(entries))
+            .chain_err(|| cassette_error(&self.path, "unable to serialize"))?;
+        fs::write(&self.path, data).chain_err(|| cassette_error(&self.path, "unable to write"))
+    }
+
+    /// Returns the response of the first recorded request, not already replayed, that matches
+    /// `request`.
+    fn replay(&mut self, request: &JsonValue) -> Result<String> {
+        let key = without_ids(request);
+        let index = (0..self.interactions.len())
+            .find(|&index| {
+                !self.replayed[index] && without_ids(&self.interactions[index].request) == key
+            }).ok_or_else(|| {
+                ErrorKind::CassetteError(format!("No recorded response for request {}", request))
+            })?;
+        self.replayed[index] = true;
+
+        let interaction = &self.interactions[index];
+        if interaction.response.trim().is_empty() {
+            return Ok(interaction.response.clone());
+        }
+        let mut response: JsonValue = serde_json::from_str(&interaction.response)
+            .chain_err(|| cassette_error(&self.path, "recorded response is not JSON"))?;
+        let id_map = ids(&interaction.request)
+            .into_iter()
+            .zip(ids(request))
+            .collect::<Vec<_>>();
+        rewrite_ids(&mut response, &id_map);
+        Ok(response.to_string())
+    }
+}
+
+/// Reads the recording in the file at `path`.
+fn load(path: &Path) -> Result<Vec<Interaction>> {
+    let file = fs::File::open(path).chain_err(|| cassette_error(path, "unable to open"))?;
+    let value: JsonValue = serde_json::from_reader(io::BufReader::new(file))
+        .chain_err(|| cassette_error(path, "invalid JSON"))?;
+    let entries = value
+        .as_array()
+        .ok_or_else(|| cassette_error(path, "expected an array of interactions"))?;
+    entries
+        .iter()
+        .map(|entry| -> Result<Interaction> {
+            let request = entry
+                .get("request")
+                .cloned()
+                .ok_or_else(|| cassette_error(path, "interaction without request"))?;
+            let response = entry
+                .get("response")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| cassette_error(path, "interaction without response"))?
+                .to_owned();
+            Ok(Interaction { request, response })
+        }).collect()
+}
+
+fn cassette_error(path: &Path, msg: &str) -> ErrorKind {
+    ErrorKind::CassetteError(format!("{}: {}", path.display(), msg))
+}
+
+/// Returns a copy of a request, or batch of requests, with the ids removed.
+fn without_ids(request: &JsonValue) -> JsonValue {
+    match request {
+        JsonValue::Array(requests) => JsonValue::Array(requests.iter().map(without_ids).collect()),
+        JsonValue::Object(fields) => {
+            let mut fields = fields.clone();
+            fields.remove("id");
+            JsonValue::Object(fields)
+        }
+        other => other.clone(),
+    }
+}
+
+/// Returns the ids of a request, or of each request in a batch, in order.
+pub fn ids(request: &JsonValue) -> Vec<JsonValue> {
+    match request {
+        JsonValue::Array(requests) => requests.iter().flat_map(ids).collect(),
+        JsonValue::Object(fields) => vec![fields.get("id").cloned().unwrap_or(JsonValue::Null)],
+        _ => Vec::new(),
+    }
+}
+
+/// Replaces the recorded ids in a response, or batch of responses, with the new ids.
+pub fn rewrite_ids(response: &mut JsonValue, id_map: &[(JsonValue, JsonValue)]) {
+    match response {
+        JsonValue::Array(responses) => for response in responses {
+            rewrite_ids(response, id_map);
+        },
+        JsonValue::Object(fields) => if let Some(id) = fields.get_mut("id") {
+            if let Some(&(_, ref new_id)) = id_map.iter().find(|&&(ref old_id, _)| *old_id == *id) {
+                *id = new_id.clone();
+            }
+        },
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn replays_with_new_ids() {
+        let path = env::temp_dir().join(format!("jsonrpc-vcr-test-{}.json", ::std::process::id()));
+        let request = br#"{"jsonrpc":"2.0","method":"echo","params":["a"],"id":1}"#.to_vec();
+        let mut cassette = Cassette::new(&path, Vec::new());
+        cassette
+            .record(
+                serde_json::from_slice(&request).unwrap(),
+                r#"{"jsonrpc":"2.0","result":"a","id":1}"#.to_owned(),
+            ).unwrap();
+
+        let handle = VcrHandle::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(handle.is_replaying());
+        let request = br#"{"jsonrpc":"2.0","method":"echo","params":["a"],"id":7}"#.to_vec();
+        let response: JsonValue =
+            serde_json::from_slice(&handle.send(request.clone()).wait().unwrap()).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"], "a");
+
+        // Every recorded interaction is only replayed once.
+        assert!(handle.send(request).wait().is_err());
+    }
+}
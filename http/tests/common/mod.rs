@@ -1,10 +1,15 @@
 #![allow(dead_code)]
 
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::time::Duration;
 
 use futures::future::{self, Empty};
+use futures::{Future, Stream};
 use jsonrpc_core::{Error, IoHandler};
-use jsonrpc_http_server::hyper::server::{Request, Response, Service};
+use jsonrpc_http_server::hyper::header::ContentType;
+use jsonrpc_http_server::hyper::server::{Http, Request, Response, Service};
 use jsonrpc_http_server::{self, hyper, ServerBuilder};
 
 // Generate server API trait. Actual implementation at bottom of file.
@@ -34,13 +39,41 @@ pub struct MockRpcServer;
 
 impl MockRpcServer {
     pub fn spawn() -> jsonrpc_http_server::Server {
-        let mut io = IoHandler::new();
-        io.extend_with(MockRpcServer.to_delegate());
-
-        ServerBuilder::new(io)
+        ServerBuilder::new(Self::io_handler())
             .start_http(&"127.0.0.1:0".parse().unwrap())
             .expect("failed to spawn server")
     }
+
+    /// Spawns a server with the same API that also answers `HEAD` requests, which the server
+    /// of `jsonrpc_http_server` does not. Returns its address and the number of connections it
+    /// has accepted.
+    pub fn spawn_counting_connections() -> (SocketAddr, Arc<AtomicUsize>) {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let server_connections = connections.clone();
+        let (address_tx, address_rx) = mpsc::channel();
+
+        ::std::thread::spawn(move || {
+            let io = Arc::new(Self::io_handler());
+            let address = "127.0.0.1:0".parse().unwrap();
+            let server = Http::new()
+                .bind(&address, move || {
+                    server_connections.fetch_add(1, Ordering::SeqCst);
+                    Ok(RpcService(io.clone()))
+                }).unwrap();
+
+            address_tx.send(server.local_addr().unwrap()).unwrap();
+
+            server.run().unwrap();
+        });
+
+        (address_rx.recv().unwrap(), connections)
+    }
+
+    fn io_handler() -> IoHandler {
+        let mut io = IoHandler::new();
+        io.extend_with(MockRpcServer.to_delegate());
+        io
+    }
 }
 
 impl MockRpcServerApi for MockRpcServer {
@@ -72,3 +105,29 @@ impl Service for UnresponsiveService {
         future::empty()
     }
 }
+
+/// Answers `HEAD` requests with an empty response, and other requests with the response of the
+/// handler to their body.
+pub struct RpcService(Arc<IoHandler>);
+
+impl Service for RpcService {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn call(&self, request: Self::Request) -> Self::Future {
+        if *request.method() == hyper::Method::Head {
+            return Box::new(future::ok(Response::new()));
+        }
+        let io = self.0.clone();
+        Box::new(request.body().concat2().map(move |body| {
+            let response = io
+                .handle_request_sync(&String::from_utf8_lossy(&body))
+                .unwrap_or_default();
+            Response::new()
+                .with_header(ContentType::json())
+                .with_body(response)
+        }))
+    }
+}
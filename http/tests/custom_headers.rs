@@ -4,7 +4,8 @@ extern crate jsonrpc_client_core;
 extern crate jsonrpc_client_http;
 extern crate tokio_service;
 
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
 
@@ -12,10 +13,10 @@ use futures::future::{Future, FutureResult, IntoFuture};
 use futures::sync::oneshot;
 use hyper::server::Http;
 use hyper::{Request, Response, StatusCode};
-use jsonrpc_client_http::header::{ContentLength, ContentType, Host};
+use jsonrpc_client_http::header::{Connection, ContentLength, ContentType, Host};
 use tokio_service::Service;
 
-use jsonrpc_client_http::{HttpHandle, HttpTransport};
+use jsonrpc_client_http::{HttpHandle, HttpTransport, RequestSigner, Result, RetryPolicy};
 
 #[test]
 fn set_host_header() {
@@ -83,11 +84,46 @@ fn set_content_length() {
     assert_eq!(*content_length, fake_content_length);
 }
 
+#[test]
+fn signs_every_attempt() {
+    let server = Server::spawn(true);
+
+    let transport = HttpTransport::new()
+        .retries(RetryPolicy::new(1, Duration::from_millis(10)))
+        .standalone()
+        .unwrap();
+    let uri = format!("http://127.0.0.1:{}", server.port);
+    let mut transport_handle = transport.handle(&uri).unwrap();
+    transport_handle.set_signer(CountingSigner(AtomicUsize::new(0)));
+
+    transport_handle.send(vec![b' '; 64]).wait().unwrap();
+    for attempt in &["1", "2"] {
+        let request = server
+            .requests
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(request.headers().get_raw("X-Attempt").unwrap(), *attempt);
+    }
+}
+
+/// Numbers the requests it signs.
+struct CountingSigner(AtomicUsize);
+
+impl RequestSigner for CountingSigner {
+    fn sign(&self, request: &mut Request, _body: &[u8]) -> Result<()> {
+        let attempt = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+        request
+            .headers_mut()
+            .set_raw("X-Attempt", attempt.to_string());
+        Ok(())
+    }
+}
+
 fn test_custom_headers<S>(set_headers: S) -> Request
 where
     S: FnOnce(&mut HttpHandle),
 {
-    let server = Server::spawn();
+    let server = Server::spawn(false);
 
     let transport = HttpTransport::new().standalone().unwrap();
     let uri = format!("http://127.0.0.1:{}", server.port);
@@ -102,15 +138,21 @@ where
         .unwrap()
 }
 
+/// Forwards requests to a channel, answering the first one with `503 Service Unavailable` if
+/// `fail_first` is set.
 #[derive(Clone)]
 pub struct ForwardToChannel {
     sender: mpsc::Sender<Request>,
+    fail_first: Arc<AtomicBool>,
 }
 
 impl ForwardToChannel {
-    pub fn new() -> (Self, mpsc::Receiver<Request>) {
+    pub fn new(fail_first: bool) -> (Self, mpsc::Receiver<Request>) {
         let (sender, receiver) = mpsc::channel();
-        let service = ForwardToChannel { sender };
+        let service = ForwardToChannel {
+            sender,
+            fail_first: Arc::new(AtomicBool::new(fail_first)),
+        };
 
         (service, receiver)
     }
@@ -123,9 +165,15 @@ impl Service for ForwardToChannel {
     type Future = FutureResult<Self::Response, Self::Error>;
 
     fn call(&self, request: Request) -> Self::Future {
+        let mut response = Response::new();
+        if self.fail_first.swap(false, Ordering::SeqCst) {
+            // The body is never read, so the connection can't be used for another request.
+            response.set_status(StatusCode::ServiceUnavailable);
+            response.headers_mut().set(Connection::close());
+        }
         let _ = self.sender.send(request);
 
-        Ok(Response::new().with_status(StatusCode::Ok)).into_future()
+        Ok(response).into_future()
     }
 }
 
@@ -136,8 +184,8 @@ pub struct Server {
 }
 
 impl Server {
-    fn spawn() -> Self {
-        let (forward_service, requests) = ForwardToChannel::new();
+    fn spawn(fail_first: bool) -> Self {
+        let (forward_service, requests) = ForwardToChannel::new(fail_first);
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
         let (port_tx, port_rx) = oneshot::channel();
 
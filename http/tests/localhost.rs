@@ -22,8 +22,11 @@ mod common;
 
 use futures::future::Either;
 use futures::Future;
-use jsonrpc_client_core::Transport;
-use jsonrpc_client_http::HttpTransport;
+use jsonrpc_client_core::{Error, ErrorKind, Transport};
+use jsonrpc_client_http::{HttpTransport, Stats};
+use jsonrpc_http_server::hyper;
+use std::io;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use tokio_core::reactor::{Core, Timeout};
 
@@ -93,3 +96,132 @@ fn dropped_rpc_request_should_not_crash_transport() {
         _ => panic!("Sleep did not return as it should"),
     }
 }
+
+#[test]
+fn idle_standalone_core_restarts_on_request() {
+    let server = MockRpcServer::spawn();
+    let uri = format!("http://{}", server.address());
+
+    let mut core = Core::new().unwrap();
+    let transport = HttpTransport::new()
+        .idle_timeout(Duration::from_millis(50))
+        .standalone()
+        .unwrap()
+        .handle(&uri)
+        .unwrap();
+    let (json_client, client_handle) = transport.into_client();
+    core.handle().spawn(json_client.map_err(|_| ()));
+    let mut client = MockRpcClient::new(client_handle);
+
+    assert_eq!("FOO", core.run(client.to_upper("foo")).unwrap());
+    // Give the event loop time to shut down, then make sure the next call starts a new one.
+    ::std::thread::sleep(Duration::from_millis(300));
+    assert_eq!("BAR", core.run(client.to_upper("bar")).unwrap());
+}
+
+#[test]
+fn preconnect_before_first_call() {
+    let (address, connections) = MockRpcServer::spawn_counting_connections();
+    let uri = format!("http://{}", address);
+
+    let mut core = Core::new().unwrap();
+    let transport = HttpTransport::new().shared(&core.handle()).unwrap();
+    let handle = transport.handle(&uri).unwrap();
+    core.run(handle.preconnect()).unwrap();
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    assert_eq!(transport.stats(), Stats::default());
+
+    let (json_client, client_handle) = handle.into_client();
+    core.handle().spawn(json_client.map_err(|_| ()));
+    let mut client = MockRpcClient::new(client_handle);
+    assert_eq!("FOO", core.run(client.to_upper("foo")).unwrap());
+    assert_eq!(
+        connections.load(Ordering::SeqCst),
+        1,
+        "the call did not reuse the preconnected connection"
+    );
+    assert_eq!(transport.stats().total_requests, 1);
+}
+
+#[test]
+fn transport_error_reaches_pending_call() {
+    // Find a port nothing listens on.
+    let address = ::std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let error = failed_call_to(&format!("http://{}", address));
+    let kind = with_io_error(&error, io::Error::kind);
+    assert_eq!(kind, Some(io::ErrorKind::ConnectionRefused));
+}
+
+#[test]
+fn dns_failure_reaches_pending_call() {
+    let error = failed_call_to("http://nonexistent.invalid/");
+    let kind = with_io_error(&error, io::Error::kind).expect("no IO error behind the error");
+    assert_ne!(kind, io::ErrorKind::ConnectionRefused);
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn tls_failure_reaches_pending_call() {
+    // The server only speaks plain HTTP, so the TLS handshake fails.
+    let server = MockRpcServer::spawn();
+    let uri = format!("https://{}", server.address());
+    let mut core = Core::new().unwrap();
+    let transport = HttpTransport::with_tls()
+        .shared(&core.handle())
+        .unwrap()
+        .handle(&uri)
+        .unwrap();
+    let error = failed_call(&mut core, transport);
+    // The TLS error is wrapped in an IO error by the connector.
+    let has_tls_error = with_io_error(&error, |error| {
+        error.kind() != io::ErrorKind::ConnectionRefused && error.get_ref().is_some()
+    });
+    assert_eq!(has_tls_error, Some(true));
+}
+
+/// Makes a call to `uri` and returns the error it fails with.
+fn failed_call_to(uri: &str) -> Error {
+    let mut core = Core::new().unwrap();
+    let transport = HttpTransport::new()
+        .shared(&core.handle())
+        .unwrap()
+        .handle(uri)
+        .unwrap();
+    failed_call(&mut core, transport)
+}
+
+/// Makes a call through `transport` and returns the error it fails with, which must be a
+/// `TransportError`.
+fn failed_call(core: &mut Core, transport: jsonrpc_client_http::HttpHandle) -> Error {
+    let (json_client, client_handle) = transport.into_client();
+    core.handle().spawn(json_client.map_err(|_| ()));
+    let mut client = MockRpcClient::new(client_handle);
+
+    let error = core.run(client.to_upper("foo")).unwrap_err();
+    match *error.kind() {
+        ErrorKind::TransportError => (),
+        ref kind => panic!("invalid error kind: {:?}", kind),
+    }
+    error
+}
+
+/// Calls `f` with the IO error the transport failed with, found by downcasting the error of the
+/// transport.
+fn with_io_error<F, R>(error: &Error, f: F) -> Option<R>
+where
+    F: FnOnce(&io::Error) -> R,
+{
+    error
+        .with_transport_error(|error| {
+            let error = error.downcast_ref::<jsonrpc_client_http::Error>()?;
+            match *error.kind() {
+                jsonrpc_client_http::ErrorKind::Hyper(hyper::Error::Io(ref error)) => {
+                    Some(f(error))
+                }
+                _ => None,
+            }
+        }).and_then(|result| result)
+}
@@ -0,0 +1,106 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate futures;
+#[macro_use]
+extern crate jsonrpc_client_core;
+extern crate jsonrpc_client_http;
+extern crate serde_json;
+extern crate tokio_core;
+
+use futures::Future;
+use jsonrpc_client_core::timer::ManualTimer;
+use jsonrpc_client_core::Transport;
+use jsonrpc_client_http::test_server::TestServer;
+use jsonrpc_client_http::HttpTransport;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+
+jsonrpc_client!(pub struct FixtureClient {
+    pub fn echo(&mut self, text: &str) -> Future<Vec<String>>;
+    pub fn version(&mut self) -> Future<String>;
+    pub fn fail(&mut self) -> Future<String>;
+});
+
+fn client(core: &Core, server: &TestServer) -> FixtureClient {
+    let transport = HttpTransport::new()
+        .shared(&core.handle())
+        .unwrap()
+        .handle(&server.uri())
+        .unwrap();
+    let (client, client_handle) = transport.into_client();
+    core.handle().spawn(client.map_err(|_| ()));
+    FixtureClient::new(client_handle)
+}
+
+#[test]
+fn echoes_params() {
+    let server = TestServer::echo().unwrap();
+    let mut core = Core::new().unwrap();
+    let mut client = client(&core, &server);
+
+    let result = core.run(client.echo("hello")).unwrap();
+    assert_eq!(result, vec!["hello".to_owned()]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["method"], "echo");
+}
+
+#[test]
+fn returns_fixtures() {
+    let server = TestServer::builder()
+        .result("version", serde_json::Value::from("1.2.3"))
+        .error("fail", -32000, "Deliberate failure")
+        .spawn()
+        .unwrap();
+    let mut core = Core::new().unwrap();
+    let mut client = client(&core, &server);
+
+    assert_eq!(core.run(client.version()).unwrap(), "1.2.3");
+    let error = core.run(client.fail()).unwrap_err();
+    match error.kind() {
+        jsonrpc_client_core::ErrorKind::JsonRpcError(rpc_error) => {
+            assert_eq!(rpc_error.code.code(), -32000)
+        }
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+}
+
+#[test]
+fn empty_body_fails_batched_calls() {
+    let server = TestServer::builder().no_response("silent").spawn().unwrap();
+    let mut core = Core::new().unwrap();
+    let transport = HttpTransport::new()
+        .shared(&core.handle())
+        .unwrap()
+        .handle(&server.uri())
+        .unwrap();
+    let (client, client_handle) = transport.into_client();
+    let client = client.batch_calls(Duration::from_secs(1), 2, ManualTimer::default());
+    core.handle().spawn(client.map_err(|_| ()));
+
+    let calls = client_handle
+        .call_method::<serde_json::Value>("silent", &(1,))
+        .then(Ok::<_, ()>)
+        .join(
+            client_handle
+                .call_method::<serde_json::Value>("silent", &(2,))
+                .then(Ok::<_, ()>),
+        );
+    let (first, second) = core.run(calls).unwrap();
+    for result in vec![first, second] {
+        match result.unwrap_err().kind() {
+            jsonrpc_client_core::ErrorKind::JsonRpcError(rpc_error) => {
+                assert_eq!(rpc_error.code.code(), -32603)
+            }
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+    }
+    assert_eq!(server.requests().len(), 2);
+}
@@ -25,6 +25,8 @@ use std::sync::mpsc;
 use std::time::Duration;
 
 use futures::future::{Either, Future};
+use futures::sync::oneshot;
+use jsonrpc_client_core::timer::ManualTimer;
 use jsonrpc_client_core::Transport;
 use jsonrpc_client_http::{ErrorKind, HttpTransport};
 use jsonrpc_http_server::hyper::server::Http;
@@ -126,3 +128,59 @@ fn timeout_error() {
         },
     }
 }
+
+#[test]
+fn timeout_with_manual_timer() {
+    let mut reactor = Core::new().unwrap();
+    let handle = reactor.handle();
+
+    let (address_tx, address_rx) = mpsc::channel();
+
+    ::std::thread::spawn(move || {
+        let address = "127.0.0.1:0".parse().unwrap();
+        let server = Http::new()
+            .bind(&address, || Ok(UnresponsiveService))
+            .unwrap();
+
+        address_tx.send(server.local_addr().unwrap()).unwrap();
+
+        server.run().unwrap();
+    });
+
+    let address = address_rx.recv().unwrap();
+
+    let timer = ManualTimer::default();
+    let transport = HttpTransport::new()
+        .timeout(Duration::from_secs(60))
+        .timer(timer.clone())
+        .shared(&handle)
+        .unwrap()
+        .handle(&format!("http://{}", address))
+        .unwrap();
+
+    let (result_tx, result_rx) = oneshot::channel();
+    handle.spawn(transport.send(vec![1, 2, 3, 4]).then(move |result| {
+        let _ = result_tx.send(result);
+        Ok(())
+    }));
+
+    // Run the event loop until the request waits for its timeout, then skip past it.
+    for _ in 0..500 {
+        if timer.pending() > 0 {
+            break;
+        }
+        reactor.turn(Some(Duration::from_millis(10)));
+    }
+    assert_eq!(timer.pending(), 1);
+    timer.advance(Duration::from_secs(59));
+    assert_eq!(timer.pending(), 1);
+    timer.advance(Duration::from_secs(1));
+
+    match reactor.run(result_rx).unwrap() {
+        Ok(_) => panic!("request didn't time out as expected"),
+        Err(error) => match error.kind() {
+            &ErrorKind::RequestTimeout => (),
+            _ => panic!("failed to send request: {}", error),
+        },
+    }
+}
@@ -0,0 +1,24 @@
+// Copyright 2017 Amagicom AB.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate jsonrpc_client_core;
+extern crate jsonrpc_client_http;
+
+use jsonrpc_client_core::transport_tests::{self, ERROR_METHOD};
+use jsonrpc_client_http::test_server::TestServer;
+use jsonrpc_client_http::HttpTransport;
+
+#[test]
+fn http_handle_conforms() {
+    let server = TestServer::builder()
+        .error(ERROR_METHOD, -32000, "Deliberate failure")
+        .spawn()
+        .unwrap();
+    let transport = HttpTransport::new().standalone().unwrap();
+    transport_tests::run_all(|| transport.handle(&server.uri()).unwrap());
+}
@@ -1,5 +1,8 @@
 //! An IPC transport for JSON-RPC. Allows one to connect to a JSON-RPC server through a Unix socket
 //! or a Named Pipe on Windows.
+//!
+//! To share one connection between several clients, each with its own ids and subscriptions, wrap
+//! the transport in a `jsonrpc_client_core::Multiplexer`.
 #![deny(missing_docs)]
 extern crate futures;
 extern crate jsonrpc_client_core;
@@ -25,7 +28,9 @@ pub struct IpcTransport {
 }
 
 impl IpcTransport {
-    /// Constructs a new IpcTransport for a given path.
+    /// Constructs a new IpcTransport for a given path. On Unix the path is the path of the socket,
+    /// and on Windows it is the name of the pipe, like `\\.\pipe\geth.ipc`. Both are connected
+    /// through the same type, so only the path has to differ between platforms.
     pub fn new(path: &impl AsRef<Path>, handle: &Handle) -> io::Result<IpcTransport> {
         Ok(IpcTransport {
             connection: IpcConnection::connect(path, handle)?,
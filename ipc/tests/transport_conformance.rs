@@ -0,0 +1,29 @@
+extern crate jsonrpc_client_core;
+extern crate jsonrpc_client_ipc;
+extern crate jsonrpc_ipc_server;
+extern crate tokio;
+
+use jsonrpc_client_core::transport_tests::{self, ECHO_METHOD, ERROR_METHOD};
+use jsonrpc_client_ipc::IpcTransport;
+use jsonrpc_ipc_server::jsonrpc_core::{Error, IoHandler, Params, Value};
+use jsonrpc_ipc_server::ServerBuilder;
+use std::env;
+use std::process;
+use tokio::reactor::Handle;
+
+#[test]
+fn ipc_transport_conforms() {
+    let mut io = IoHandler::new();
+    io.add_method(ECHO_METHOD, |params: Params| params.parse::<Value>());
+    io.add_method(ERROR_METHOD, |_| Err::<Value, _>(Error::internal_error()));
+
+    let path = if cfg!(windows) {
+        format!(r"\\.\pipe\jsonrpc-client-ipc-{}", process::id())
+    } else {
+        let file = format!("jsonrpc-client-ipc-{}.ipc", process::id());
+        env::temp_dir().join(file).to_string_lossy().into_owned()
+    };
+    let server = ServerBuilder::new(io).start(&path).unwrap();
+    transport_tests::run_all(|| IpcTransport::new(&path, &Handle::default()).unwrap());
+    server.close();
+}